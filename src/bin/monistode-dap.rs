@@ -0,0 +1,63 @@
+//! A Debug Adapter Protocol server on stdin and stdout, for editors to
+//! launch as a debug adapter for monistode executables.
+
+use std::io::{self, BufReader};
+use std::process;
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+use monistode_emulator_bindings::dap::{read_message, write_message, DapSession};
+
+/// How many instructions a continued program runs between checks for new
+/// requests, such as a pause.
+const STEPS_PER_POLL: usize = 10_000;
+
+fn send(messages: Vec<String>) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    for message in messages {
+        write_message(&mut stdout, &message)?;
+    }
+    Ok(())
+}
+
+fn serve() -> io::Result<()> {
+    // Requests are read on their own thread so that they can arrive while
+    // the program runs.
+    let (sender, requests) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = BufReader::new(io::stdin());
+        while let Ok(Some(message)) = read_message(&mut stdin) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut session = DapSession::new();
+    while !session.is_disconnected() {
+        let request = if session.is_running() {
+            match requests.try_recv() {
+                Ok(request) => request,
+                Err(TryRecvError::Empty) => {
+                    send(session.poll(STEPS_PER_POLL))?;
+                    continue;
+                }
+                Err(TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match requests.recv() {
+                Ok(request) => request,
+                Err(_) => break,
+            }
+        };
+        send(session.handle(&request))?;
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(error) = serve() {
+        eprintln!("monistode-dap: {}", error);
+        process::exit(1);
+    }
+}
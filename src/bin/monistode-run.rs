@@ -0,0 +1,277 @@
+//! Runs a monistode executable in the terminal, with stdin and stdout
+//! attached to the processor's ports.
+//!
+//! Exits with 0 if the program halted, 1 if the processor hit an error,
+//! 2 if the executable couldn't be loaded, 3 if `--max-steps` or `--timeout`
+//! ran out, 4 if `--detect-loops` found the program stuck in a loop, 5 if
+//! the command line is invalid, and 6 if a `--gdb` session ended before the
+//! program did. After a GDB session, the other codes tell how the program
+//! last stopped.
+
+use std::io::{self, Read, Write};
+use std::process;
+
+use monistode_emulator_bindings::{
+    detect_processor_type, PortIo, ProcessorType, Runner, WasmProcessorContinue,
+};
+
+const USAGE: &str = "\
+Usage: monistode-run [OPTIONS] <EXECUTABLE>
+
+Options:
+    --processor <TYPE>      stack, acc, risc or cisc; detected from the
+                            executable by default
+    --input-port <PORT>     port that reads bytes from stdin [default: 0]
+    --output-port <PORT>    port that writes bytes to stdout [default: 0]
+    --max-steps <N>         stop after N instructions
+    --timeout <MS>          stop after MS milliseconds
+    --detect-loops          stop when the program is stuck in a loop
+    --trace                 print every instruction to stderr before running it
+    --dump-registers        print the registers to stderr on exit
+    --gdb <ADDRESS>         wait for GDB to connect at ADDRESS, such as
+                            localhost:1234, and let it drive the program;
+                            needs the `gdb` feature
+    -h, --help              print this message";
+
+const EXIT_HALT: i32 = 0;
+const EXIT_ERROR: i32 = 1;
+const EXIT_LOAD_FAILED: i32 = 2;
+const EXIT_STEP_LIMIT: i32 = 3;
+const EXIT_LOOP: i32 = 4;
+const EXIT_USAGE: i32 = 5;
+const EXIT_UNFINISHED: i32 = 6;
+
+struct Options {
+    path: String,
+    processor: Option<ProcessorType>,
+    input_port: u16,
+    output_port: u16,
+    max_steps: Option<u64>,
+    timeout: Option<f64>,
+    detect_loops: bool,
+    trace: bool,
+    dump_registers: bool,
+    gdb: Option<String>,
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", flag, value))
+}
+
+fn parse_processor(value: Option<String>) -> Result<ProcessorType, String> {
+    match value.as_deref() {
+        Some("stack") => Ok(ProcessorType::Stack),
+        Some("acc") => Ok(ProcessorType::Acc),
+        Some("risc") => Ok(ProcessorType::Risc),
+        Some("cisc") => Ok(ProcessorType::Cisc),
+        Some(other) => Err(format!("Unknown processor: {}", other)),
+        None => Err("--processor needs a value".to_string()),
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut path = None;
+    let mut options = Options {
+        path: String::new(),
+        processor: None,
+        input_port: 0,
+        output_port: 0,
+        max_steps: None,
+        timeout: None,
+        detect_loops: false,
+        trace: false,
+        dump_registers: false,
+        gdb: None,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "--processor" => options.processor = Some(parse_processor(args.next())?),
+            "--input-port" => options.input_port = parse_number(&arg, args.next())?,
+            "--output-port" => options.output_port = parse_number(&arg, args.next())?,
+            "--max-steps" => options.max_steps = Some(parse_number(&arg, args.next())?),
+            "--timeout" => options.timeout = Some(parse_number(&arg, args.next())?),
+            "--detect-loops" => options.detect_loops = true,
+            "--trace" => options.trace = true,
+            "--dump-registers" => options.dump_registers = true,
+            "--gdb" => options.gdb = Some(args.next().ok_or("--gdb needs a value")?),
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+    options.path = path.ok_or("No executable given")?;
+    Ok(options)
+}
+
+/// Maps one port to stdin and one to stdout, a byte per access. Reading
+/// past the end of stdin gives 0; other ports read 0 and drop writes.
+struct StdIo {
+    input_port: u16,
+    output_port: u16,
+    stdin: io::Stdin,
+    stdout: io::Stdout,
+}
+
+impl PortIo for StdIo {
+    fn output(&mut self, port: u16, value: u16) {
+        if port == self.output_port {
+            let _ = self.stdout.write_all(&[value as u8]);
+        }
+    }
+
+    fn input(&mut self, port: u16) -> u16 {
+        if port != self.input_port {
+            return 0;
+        }
+        // Anything printed so far is probably a prompt for this input.
+        let _ = self.stdout.flush();
+        let mut byte = [0];
+        match self.stdin.read(&mut byte) {
+            Ok(1) => byte[0].into(),
+            _ => 0,
+        }
+    }
+}
+
+fn load(options: &Options) -> Result<Runner, String> {
+    let program = std::fs::read(&options.path)
+        .map_err(|error| format!("Failed to read {}: {}", options.path, error))?;
+    let processor_type = match &options.processor {
+        Some(processor_type) => processor_type.clone(),
+        None => detect_processor_type(&program)?,
+    };
+    let mut runner = Runner::new(processor_type);
+    runner.load_program(&program)?;
+    runner.set_step_budget(options.max_steps);
+    runner.set_time_budget(options.timeout);
+    runner.set_loop_detection(options.detect_loops);
+    Ok(runner)
+}
+
+/// The exit code for how the program stopped, saying why on stderr if it
+/// didn't halt; `None` if it can keep running.
+fn exit_code(runner: &Runner, result: WasmProcessorContinue) -> Option<i32> {
+    match result {
+        WasmProcessorContinue::Continue
+        | WasmProcessorContinue::Breakpoint
+        | WasmProcessorContinue::Catchpoint => None,
+        WasmProcessorContinue::Halt => Some(EXIT_HALT),
+        WasmProcessorContinue::Error | WasmProcessorContinue::IoError => {
+            eprintln!("Processor error at {:#06x}", runner.pc());
+            Some(EXIT_ERROR)
+        }
+        WasmProcessorContinue::Timeout => {
+            eprintln!("Stopped after {} steps", runner.steps());
+            Some(EXIT_STEP_LIMIT)
+        }
+        WasmProcessorContinue::Loop => {
+            let pc = runner.pc();
+            match runner.describe_address(pc) {
+                Some(symbol) => eprintln!("Stuck in a loop at {:#06x} ({})", pc, symbol),
+                None => eprintln!("Stuck in a loop at {:#06x}", pc),
+            }
+            Some(EXIT_LOOP)
+        }
+    }
+}
+
+fn run(runner: &mut Runner, options: &Options) -> i32 {
+    let mut io = StdIo {
+        input_port: options.input_port,
+        output_port: options.output_port,
+        stdin: io::stdin(),
+        stdout: io::stdout(),
+    };
+    let code = loop {
+        if options.trace {
+            let pc = runner.pc();
+            let instruction = runner
+                .disassemble(pc)
+                .unwrap_or_else(|| "<invalid>".to_string());
+            eprintln!("{:#06x}: {}", pc, instruction);
+        }
+        let result = runner.run_with(&mut io);
+        if let Some(code) = exit_code(runner, result) {
+            break code;
+        }
+    };
+    let _ = io.stdout.flush();
+    code
+}
+
+/// Lets GDB drive the program, and returns the runner with how the program
+/// last stopped.
+#[cfg(feature = "gdb")]
+fn debug(
+    runner: Runner,
+    options: &Options,
+    address: &str,
+) -> Result<(Runner, WasmProcessorContinue), String> {
+    let mut io = StdIo {
+        input_port: options.input_port,
+        output_port: options.output_port,
+        stdin: io::stdin(),
+        stdout: io::stdout(),
+    };
+    eprintln!("Waiting for GDB on {}", address);
+    let stub = monistode_emulator_bindings::gdb::serve_tcp(runner, address, &mut io)
+        .map_err(|error| format!("GDB session failed: {}", error))?;
+    let _ = io.stdout.flush();
+    let last_stop = stub.last_stop();
+    Ok((stub.into_runner(), last_stop))
+}
+
+#[cfg(not(feature = "gdb"))]
+fn debug(
+    _runner: Runner,
+    _options: &Options,
+    _address: &str,
+) -> Result<(Runner, WasmProcessorContinue), String> {
+    Err("This build doesn't support GDB, rebuild with `--features gdb`".to_string())
+}
+
+fn main() {
+    let options = match parse_options(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("monistode-run: {}\n\n{}", error, USAGE);
+            process::exit(EXIT_USAGE);
+        }
+    };
+    let mut runner = match load(&options) {
+        Ok(runner) => runner,
+        Err(error) => {
+            eprintln!("monistode-run: {}", error);
+            process::exit(EXIT_LOAD_FAILED);
+        }
+    };
+    let code = match &options.gdb {
+        Some(address) => match debug(runner, &options, address) {
+            Ok((debugged, last_stop)) => {
+                runner = debugged;
+                exit_code(&runner, last_stop).unwrap_or_else(|| {
+                    eprintln!("GDB left before the program finished");
+                    EXIT_UNFINISHED
+                })
+            }
+            Err(error) => {
+                eprintln!("monistode-run: {}", error);
+                process::exit(EXIT_ERROR);
+            }
+        },
+        None => run(&mut runner, &options),
+    };
+    if options.dump_registers {
+        for register in runner.get_registers() {
+            eprintln!("{:>4} = {:#06x}", register.name(), register.value());
+        }
+    }
+    process::exit(code);
+}
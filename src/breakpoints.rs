@@ -0,0 +1,40 @@
+//! Breakpoints that only stop when a condition holds, or after they have
+//! been reached a number of times.
+
+use crate::expression::Expression;
+
+#[derive(Clone, Default)]
+pub struct Breakpoint {
+    condition: Option<Expression>,
+    hit_count: Option<u32>,
+    hits: u32,
+}
+
+impl Breakpoint {
+    pub fn new(condition: Option<Expression>, hit_count: Option<u32>) -> Self {
+        Breakpoint {
+            condition,
+            hit_count,
+            hits: 0,
+        }
+    }
+
+    pub fn condition(&self) -> Option<&Expression> {
+        self.condition.as_ref()
+    }
+
+    /// Counts a visit at which the condition held, and tells whether
+    /// execution should stop there.
+    pub fn hit(&mut self) -> bool {
+        self.hits = self.hits.saturating_add(1);
+        self.hits >= self.hit_count.unwrap_or(0)
+    }
+
+    pub fn hits(&self) -> u32 {
+        self.hits
+    }
+
+    pub fn reset_hits(&mut self) {
+        self.hits = 0;
+    }
+}
@@ -0,0 +1,131 @@
+use wasm_bindgen::prelude::*;
+
+use crate::decoder::{Instruction, InstructionKind};
+use crate::symbols::SymbolTable;
+
+struct Frame {
+    call_site: u16,
+    target: u16,
+    return_address: u16,
+    stack_pointer: u16,
+}
+
+/// A shadow of the program's call stack, rebuilt from the CALL and RET
+/// instructions as they execute.
+#[derive(Default)]
+pub struct CallStack {
+    frames: Vec<Frame>,
+}
+
+impl CallStack {
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Records the effect of an instruction that has just executed, given the
+    /// program counter and stack pointer it left behind.
+    pub fn track(&mut self, instruction: &Instruction, pc: u16, stack_pointer: u16) {
+        match instruction.kind {
+            InstructionKind::Call => self.frames.push(Frame {
+                call_site: instruction.address,
+                target: pc,
+                return_address: instruction.next_address(),
+                stack_pointer,
+            }),
+            InstructionKind::Return => {
+                // Programs that juggle their return addresses can skip frames;
+                // unwind to the frame we actually returned from if there is one.
+                match self
+                    .frames
+                    .iter()
+                    .rposition(|frame| frame.return_address == pc)
+                {
+                    Some(index) => self.frames.truncate(index),
+                    None => {
+                        self.frames.pop();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Records an interrupt as a call from the interrupted instruction,
+    /// which the handler's RET returns to.
+    pub fn interrupt(&mut self, pc: u16, vector: u16, stack_pointer: u16) {
+        self.frames.push(Frame {
+            call_site: pc,
+            target: vector,
+            return_address: pc,
+            stack_pointer,
+        });
+    }
+
+    /// The active calls, innermost first.
+    pub fn backtrace(&self, symbols: &SymbolTable) -> Vec<CallFrame> {
+        self.frames
+            .iter()
+            .rev()
+            .map(|frame| CallFrame {
+                call_site: frame.call_site,
+                target: frame.target,
+                return_address: frame.return_address,
+                stack_pointer: frame.stack_pointer,
+                function: symbols.describe(frame.target),
+                caller: symbols.describe(frame.call_site),
+            })
+            .collect()
+    }
+}
+
+#[wasm_bindgen]
+pub struct CallFrame {
+    call_site: u16,
+    target: u16,
+    return_address: u16,
+    stack_pointer: u16,
+    function: Option<String>,
+    caller: Option<String>,
+}
+
+#[wasm_bindgen]
+impl CallFrame {
+    /// The address of the CALL instruction.
+    #[wasm_bindgen]
+    pub fn call_site(&self) -> u16 {
+        self.call_site
+    }
+
+    /// The address that was called.
+    #[wasm_bindgen]
+    pub fn target(&self) -> u16 {
+        self.target
+    }
+
+    #[wasm_bindgen]
+    pub fn return_address(&self) -> u16 {
+        self.return_address
+    }
+
+    /// The stack pointer right after the call, pointing at the return address.
+    #[wasm_bindgen]
+    pub fn stack_pointer(&self) -> u16 {
+        self.stack_pointer
+    }
+
+    /// The symbol the called address resolves to, if there are symbols.
+    #[wasm_bindgen]
+    pub fn function(&self) -> Option<String> {
+        self.function.clone()
+    }
+
+    /// The symbol the call site resolves to, if there are symbols.
+    #[wasm_bindgen]
+    pub fn caller(&self) -> Option<String> {
+        self.caller.clone()
+    }
+}
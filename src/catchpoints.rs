@@ -0,0 +1,196 @@
+//! Catchpoints, which stop execution when an instruction does something
+//! rather than when it is reached: a port access, a call or return, a
+//! register changing, or a write to text memory.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use wasm_bindgen::prelude::*;
+
+use crate::decoder::InstructionKind;
+use crate::io::PortAccess;
+
+pub enum Catchpoint {
+    /// IN and OUT on `port`, or on any port if it's `None`.
+    Port {
+        port: Option<u16>,
+        input: bool,
+        output: bool,
+    },
+    Call {
+        calls: bool,
+        returns: bool,
+    },
+    /// Any change of the register, or only those that cross `threshold`.
+    Register {
+        name: String,
+        threshold: Option<u16>,
+    },
+    /// Writes that change text memory in the range.
+    TextWrite(Range<usize>),
+}
+
+/// Which catchpoint stopped execution, and what it saw.
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct CatchEvent {
+    catchpoint: u32,
+    description: String,
+}
+
+#[wasm_bindgen]
+impl CatchEvent {
+    /// The id the catchpoint was given when it was added.
+    #[wasm_bindgen]
+    pub fn catchpoint(&self) -> u32 {
+        self.catchpoint
+    }
+
+    /// What happened, such as `OUT to port 1` or `SP changed from 0x0400
+    /// to 0x03fe`.
+    #[wasm_bindgen]
+    pub fn description(&self) -> String {
+        self.description.clone()
+    }
+}
+
+/// The registers and text cells catchpoints compare across an instruction.
+#[derive(Default)]
+pub struct Watched {
+    pub registers: BTreeMap<String, u16>,
+    /// The cells the instruction may write in the range of every text
+    /// write catchpoint, by its id, with their addresses.
+    pub text: BTreeMap<u32, Vec<(usize, u8)>>,
+}
+
+/// What an instruction did, as far as catchpoints care.
+pub struct Step<'a> {
+    pub kind: Option<InstructionKind>,
+    /// Where the instruction left the program counter.
+    pub pc: u16,
+    pub ports: &'a [PortAccess],
+    pub before: &'a Watched,
+    pub after: &'a Watched,
+}
+
+#[derive(Default)]
+pub struct Catchpoints {
+    entries: BTreeMap<u32, Catchpoint>,
+    next_id: u32,
+}
+
+impl Catchpoints {
+    pub fn add(&mut self, catchpoint: Catchpoint) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entries.insert(id, catchpoint);
+        id
+    }
+
+    pub fn remove(&mut self, id: u32) -> bool {
+        self.entries.remove(&id).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn ids(&self) -> Vec<u32> {
+        self.entries.keys().copied().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn watches_registers(&self) -> bool {
+        self.entries
+            .values()
+            .any(|catchpoint| matches!(catchpoint, Catchpoint::Register { .. }))
+    }
+
+    pub fn watches_text(&self) -> bool {
+        self.entries
+            .values()
+            .any(|catchpoint| matches!(catchpoint, Catchpoint::TextWrite(_)))
+    }
+
+    /// The text ranges to compare across an instruction, by catchpoint id.
+    pub fn text_ranges(&self) -> impl Iterator<Item = (u32, Range<usize>)> + '_ {
+        self.entries
+            .iter()
+            .filter_map(|(id, catchpoint)| match catchpoint {
+                Catchpoint::TextWrite(range) => Some((*id, range.clone())),
+                _ => None,
+            })
+    }
+
+    /// The first catchpoint, in the order they were added, that `step`
+    /// sets off.
+    pub fn check(&self, step: &Step) -> Option<CatchEvent> {
+        self.entries.iter().find_map(|(id, catchpoint)| {
+            let description = describe(*id, catchpoint, step)?;
+            Some(CatchEvent {
+                catchpoint: *id,
+                description,
+            })
+        })
+    }
+}
+
+fn describe(id: u32, catchpoint: &Catchpoint, step: &Step) -> Option<String> {
+    match catchpoint {
+        Catchpoint::Port {
+            port,
+            input,
+            output,
+        } => step
+            .ports
+            .iter()
+            .find(|access| {
+                let port_matches = match port {
+                    Some(port) => *port == access.port,
+                    None => true,
+                };
+                port_matches && if access.input { *input } else { *output }
+            })
+            .map(|access| match access.input {
+                true => format!("IN from port {}", access.port),
+                false => format!("OUT to port {}", access.port),
+            }),
+        Catchpoint::Call { calls, returns } => match step.kind? {
+            InstructionKind::Call if *calls => Some(format!("CALL to {:#06x}", step.pc)),
+            InstructionKind::Return if *returns => Some(format!("RET to {:#06x}", step.pc)),
+            _ => None,
+        },
+        Catchpoint::Register { name, threshold } => {
+            let before = *step.before.registers.get(name)?;
+            let after = *step.after.registers.get(name)?;
+            match threshold {
+                None if before != after => Some(format!(
+                    "{} changed from {:#06x} to {:#06x}",
+                    name, before, after
+                )),
+                Some(threshold) if (before >= *threshold) != (after >= *threshold) => {
+                    Some(format!(
+                        "{} crossed {:#06x}, going from {:#06x} to {:#06x}",
+                        name, threshold, before, after
+                    ))
+                }
+                _ => None,
+            }
+        }
+        Catchpoint::TextWrite(_) => {
+            let before = step.before.text.get(&id)?;
+            let after = step.after.text.get(&id)?;
+            let ((address, old), (_, new)) = before
+                .iter()
+                .zip(after)
+                .find(|((_, old), (_, new))| old != new)?;
+            Some(format!(
+                "Text memory at {:#06x} changed from {:#04x} to {:#04x}",
+                address, old, new
+            ))
+        }
+    }
+}
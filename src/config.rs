@@ -0,0 +1,439 @@
+//! Processors with smaller memories and their stacks moved around, for
+//! exercises with tight limits.
+
+use wasm_bindgen::prelude::*;
+
+use crate::decoder::{Instruction, Operand};
+use crate::io::PortIo;
+use crate::memory::{MemoryBlock, MemoryLayout, MemoryType};
+use crate::processor::{WasmProcessor, WasmProcessorContinue};
+use crate::processors::{create_processor, ProcessorType};
+use crate::registers::RegisterState;
+use crate::stack::{stack_address, stack_effects, StackLayout};
+use monistode_binutils::Executable;
+
+#[derive(Clone)]
+struct StackConfig {
+    name: String,
+    base: u16,
+    limit: u16,
+}
+
+/// Limits for `Runner::with_config`. Anything left unset keeps the
+/// emulator's default. Words stay 16 bits wide and cells keep their width,
+/// since the emulator has no way to change them.
+#[wasm_bindgen]
+#[derive(Clone, Default)]
+pub struct ProcessorConfig {
+    text_memory_size: Option<usize>,
+    data_memory_size: Option<usize>,
+    stacks: Vec<StackConfig>,
+    initial_pc: Option<u16>,
+}
+
+#[wasm_bindgen]
+impl ProcessorConfig {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> ProcessorConfig {
+        Self::default()
+    }
+
+    /// Limits text memory to its first `cells` cells.
+    #[wasm_bindgen]
+    pub fn set_text_memory_size(&mut self, cells: usize) {
+        self.text_memory_size = Some(cells);
+    }
+
+    /// Limits data memory, on processors that have one, to its first
+    /// `cells` cells.
+    #[wasm_bindgen]
+    pub fn set_data_memory_size(&mut self, cells: usize) {
+        self.data_memory_size = Some(cells);
+    }
+
+    /// Moves the stack with the pointer register `name`, such as `SP` or
+    /// `TOS`, to start at `base` and end at `limit`. The pointer starts out
+    /// at `base`.
+    #[wasm_bindgen]
+    pub fn set_stack(&mut self, name: &str, base: u16, limit: u16) {
+        self.stacks.retain(|stack| stack.name != name);
+        self.stacks.push(StackConfig {
+            name: name.to_string(),
+            base,
+            limit,
+        });
+    }
+
+    /// Starts programs at `pc` instead of at their entry point.
+    #[wasm_bindgen]
+    pub fn set_initial_pc(&mut self, pc: u16) {
+        self.initial_pc = Some(pc);
+    }
+
+    #[wasm_bindgen]
+    pub fn text_memory_size(&self) -> Option<usize> {
+        self.text_memory_size
+    }
+
+    #[wasm_bindgen]
+    pub fn data_memory_size(&self) -> Option<usize> {
+        self.data_memory_size
+    }
+
+    #[wasm_bindgen]
+    pub fn initial_pc(&self) -> Option<u16> {
+        self.initial_pc
+    }
+}
+
+pub(crate) fn memory_name(mem_type: MemoryType) -> &'static str {
+    match mem_type {
+        MemoryType::Text => "text",
+        MemoryType::Data => "data",
+    }
+}
+
+/// A processor held to a `ProcessorConfig`. Besides reporting the smaller
+/// memories, it refuses to run an instruction that would fetch or access
+/// memory past their end, or push or pop past the end of a stack, and
+/// stops with an error once a stack pointer otherwise leaves its stack.
+pub struct ConfiguredProcessor {
+    inner: Box<dyn WasmProcessor>,
+    stack_machine: bool,
+    config: ProcessorConfig,
+    fault: Option<String>,
+}
+
+impl ConfiguredProcessor {
+    pub fn new(
+        processor_type: ProcessorType,
+        config: ProcessorConfig,
+    ) -> Result<ConfiguredProcessor, String> {
+        let mut processor = ConfiguredProcessor {
+            stack_machine: matches!(processor_type, ProcessorType::Stack),
+            inner: create_processor(processor_type),
+            config,
+            fault: None,
+        };
+        processor.validate()?;
+        processor.power_on()?;
+        Ok(processor)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let sizes = [
+            (MemoryType::Text, self.config.text_memory_size),
+            (MemoryType::Data, self.config.data_memory_size),
+        ];
+        for (mem_type, size) in sizes {
+            let Some(size) = size else {
+                continue;
+            };
+            let layout = self
+                .inner
+                .memory_layout(mem_type)
+                .ok_or_else(|| format!("This processor has no {} memory", memory_name(mem_type)))?;
+            if size == 0 || size > layout.size {
+                return Err(format!(
+                    "The {} memory can hold 1 to {} cells, not {}",
+                    memory_name(mem_type),
+                    layout.size,
+                    size
+                ));
+            }
+        }
+        let layouts = self.inner.stack_layouts();
+        for stack in &self.config.stacks {
+            let layout = layouts.iter().find(|layout| layout.name == stack.name);
+            if layout.is_none() {
+                return Err(format!("This processor has no {} stack", stack.name));
+            }
+        }
+        for layout in self.stack_layouts() {
+            let memory = self.memory_layout(layout.memory_type).unwrap();
+            let (low, high) = if layout.grows_down {
+                (layout.limit, layout.base)
+            } else {
+                (layout.base, layout.limit)
+            };
+            if low > high || usize::from(high) + memory.word_cells > memory.size {
+                return Err(format!(
+                    "The {} stack from {:#06x} to {:#06x} doesn't fit into the \
+                     {}-cell {} memory",
+                    layout.name,
+                    layout.base,
+                    layout.limit,
+                    memory.size,
+                    memory_name(layout.memory_type)
+                ));
+            }
+        }
+        if let Some(pc) = self.config.initial_pc {
+            let text = self.memory_layout(MemoryType::Text).unwrap();
+            if usize::from(pc) >= text.size {
+                return Err(format!(
+                    "The initial PC {:#06x} is outside the {}-cell text memory",
+                    pc, text.size
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts the configured stack pointers and PC into the registers.
+    fn power_on(&mut self) -> Result<(), String> {
+        for stack in self.config.stacks.clone() {
+            if !self.inner.set_register(&stack.name, stack.base) {
+                return Err(format!("Cannot set register {}", stack.name));
+            }
+        }
+        if let Some(pc) = self.config.initial_pc {
+            self.inner.set_register("PC", pc);
+        }
+        Ok(())
+    }
+
+    fn fail(&mut self, fault: String) -> WasmProcessorContinue {
+        self.fault = Some(fault);
+        WasmProcessorContinue::Error
+    }
+
+    /// The memory operands of instructions live in data memory where there
+    /// is one.
+    fn operand_memory(&self) -> MemoryType {
+        match self.inner.memory_layout(MemoryType::Data) {
+            Some(_) => MemoryType::Data,
+            None => MemoryType::Text,
+        }
+    }
+
+    /// Finds the first part of the instruction at the PC that lies outside
+    /// the configured memories.
+    fn check_instruction(&mut self, instruction: &Instruction) -> Result<(), String> {
+        let text = self.memory_layout(MemoryType::Text).unwrap();
+        let end = usize::from(instruction.address) + usize::from(instruction.length);
+        if end > text.size {
+            return Err(format!(
+                "The instruction at {:#06x} runs past the {}-cell text memory",
+                instruction.address, text.size
+            ));
+        }
+
+        let mem_type = self.operand_memory();
+        let memory = self.memory_layout(mem_type).unwrap();
+        let mut registers: Option<Vec<RegisterState>> = None;
+        for operand in &instruction.operands {
+            let (register, offset) = match *operand {
+                Operand::Memory(address) => (None, address),
+                Operand::RegisterIndirect(name) => (Some(name), 0),
+                Operand::RegisterOffsetIndirect(name, offset) => (Some(name), offset),
+                _ => continue,
+            };
+            let base = match register {
+                Some(name) => registers
+                    .get_or_insert_with(|| self.inner.get_registers())
+                    .iter()
+                    .find(|register| register.name() == name)
+                    .map_or(0, |register| register.value()),
+                None => 0,
+            };
+            let address = base.wrapping_add(offset);
+            if usize::from(address) + memory.word_cells > memory.size {
+                return Err(format!(
+                    "{} accesses {:#06x}, outside the {}-cell {} memory",
+                    operand,
+                    address,
+                    memory.size,
+                    memory_name(mem_type)
+                ));
+            }
+        }
+
+        // Pops come before pushes, so the stack can underflow halfway
+        // through an instruction and overflow at its end.
+        for effect in stack_effects(self.inner.as_mut(), self.stack_machine, instruction) {
+            let Some(layout) = self
+                .stack_layouts()
+                .into_iter()
+                .find(|layout| layout.name == effect.stack)
+            else {
+                continue;
+            };
+            check_stack(&layout, effect.popped_pointer(&layout))?;
+            check_stack(&layout, effect.pushed_pointer(&layout))?;
+        }
+        if let Some(address) = stack_address(self.inner.as_mut(), self.stack_machine, instruction) {
+            if usize::from(address) + memory.word_cells > memory.size {
+                return Err(format!(
+                    "{} accesses {:#06x} from the stack, outside the {}-cell {} memory",
+                    instruction.mnemonic,
+                    address,
+                    memory.size,
+                    memory_name(mem_type)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_stacks(&self) -> Result<(), String> {
+        for layout in self.stack_layouts() {
+            check_stack(&layout, layout.pointer)?;
+        }
+        Ok(())
+    }
+
+    fn size(&self, mem_type: MemoryType) -> usize {
+        self.memory_layout(mem_type).map_or(0, |layout| layout.size)
+    }
+}
+
+/// Makes sure `pointer` lies within the stack `layout` describes.
+fn check_stack(layout: &StackLayout, pointer: u16) -> Result<(), String> {
+    let (low, high) = if layout.grows_down {
+        (layout.limit, layout.base)
+    } else {
+        (layout.base, layout.limit)
+    };
+    if pointer < low || pointer > high {
+        let past_limit = (pointer < low) == layout.grows_down;
+        return Err(format!(
+            "Stack {}: {} is {:#06x}, past the stack {} {:#06x}",
+            if past_limit { "overflow" } else { "underflow" },
+            layout.name,
+            pointer,
+            if past_limit { "limit" } else { "base" },
+            if past_limit {
+                layout.limit
+            } else {
+                layout.base
+            }
+        ));
+    }
+    Ok(())
+}
+
+impl WasmProcessor for ConfiguredProcessor {
+    fn run(&mut self, io: &mut dyn PortIo) -> WasmProcessorContinue {
+        self.fault = None;
+        let pc = self.inner.pc();
+        let checked = match self.inner.decode(pc) {
+            Some(instruction) => self.check_instruction(&instruction),
+            None if usize::from(pc) >= self.size(MemoryType::Text) => Err(format!(
+                "The PC {:#06x} is outside the {}-cell text memory",
+                pc,
+                self.size(MemoryType::Text)
+            )),
+            None => Ok(()),
+        };
+        if let Err(fault) = checked {
+            return self.fail(fault);
+        }
+        let result = self.inner.run(io);
+        if result != WasmProcessorContinue::Continue {
+            return result;
+        }
+        match self.check_stacks() {
+            Ok(()) => result,
+            Err(fault) => self.fail(fault),
+        }
+    }
+
+    fn get_memory(&mut self) -> Vec<MemoryBlock> {
+        let mut blocks = self.inner.get_memory();
+        for block in &mut blocks {
+            let size = self.size(block.memory_type);
+            block.values.truncate(size);
+        }
+        blocks
+    }
+
+    fn set_memory(&mut self, mem_type: MemoryType, index: usize, value: u8) -> bool {
+        index < self.size(mem_type) && self.inner.set_memory(mem_type, index, value)
+    }
+
+    fn read_memory(&self, mem_type: MemoryType, index: usize) -> Option<u8> {
+        if index < self.size(mem_type) {
+            self.inner.read_memory(mem_type, index)
+        } else {
+            None
+        }
+    }
+
+    fn memory_layout(&self, mem_type: MemoryType) -> Option<MemoryLayout> {
+        let size = match mem_type {
+            MemoryType::Text => self.config.text_memory_size,
+            MemoryType::Data => self.config.data_memory_size,
+        };
+        self.inner
+            .memory_layout(mem_type)
+            .map(|layout| MemoryLayout {
+                size: size.unwrap_or(layout.size),
+                ..layout
+            })
+    }
+
+    fn get_registers(&mut self) -> Vec<RegisterState> {
+        self.inner.get_registers()
+    }
+
+    fn set_register(&mut self, name: &str, value: u16) -> bool {
+        self.inner.set_register(name, value)
+    }
+
+    /// Loads the executable and then checks that nothing landed past the
+    /// end of the configured memories.
+    fn load_executable(&mut self, executable: &Executable) -> Result<(), String> {
+        self.inner.load_executable(executable)?;
+        for block in self.inner.get_memory() {
+            let size = self.size(block.memory_type);
+            if block.values[size..].iter().any(|cell| *cell != 0) {
+                return Err(format!(
+                    "The program doesn't fit into the {}-cell {} memory",
+                    size,
+                    memory_name(block.memory_type)
+                ));
+            }
+        }
+        if let Some(pc) = self.config.initial_pc {
+            self.inner.set_register("PC", pc);
+        }
+        Ok(())
+    }
+
+    fn peek_stack(&mut self, n: u8) -> u16 {
+        self.inner.peek_stack(n)
+    }
+
+    fn stack_layouts(&self) -> Vec<StackLayout> {
+        let mut layouts = self.inner.stack_layouts();
+        for layout in &mut layouts {
+            if let Some(stack) = self
+                .config
+                .stacks
+                .iter()
+                .find(|stack| stack.name == layout.name)
+            {
+                layout.base = stack.base;
+                layout.limit = stack.limit;
+            }
+        }
+        layouts
+    }
+
+    fn pc(&self) -> u16 {
+        self.inner.pc()
+    }
+
+    fn stack_pointer(&self) -> u16 {
+        self.inner.stack_pointer()
+    }
+
+    fn decode(&self, address: u16) -> Option<Instruction> {
+        self.inner.decode(address)
+    }
+
+    fn fault(&self) -> Option<String> {
+        self.fault.clone()
+    }
+}
@@ -0,0 +1,873 @@
+//! A Debug Adapter Protocol backend, so that editors such as VS Code can
+//! debug programs through the `Runner`.
+//!
+//! `DapSession` takes requests as JSON strings and gives back the JSON
+//! responses and events to send; `monistode-dap` serves it over stdio and
+//! `DapChannel` makes it available to JS.
+//!
+//! There is no source information in executables, so breakpoints are set
+//! by function (symbol) name or by instruction address. Their conditions
+//! and the `evaluate` request use `Runner::evaluate`'s expressions. The
+//! program reads the `input` string given to `launch` from `inputPort`, and
+//! writes to `outputPort` show up as text output; writes to other ports are
+//! reported one per line.
+
+use std::convert::TryFrom;
+
+use serde_json::{json, Value};
+use wasm_bindgen::prelude::*;
+
+use crate::expression::Expression;
+use crate::io::BufferedIo;
+use crate::logpoints::Template;
+use crate::memory::MemoryType;
+use crate::processor::WasmProcessorContinue;
+use crate::processors::{detect_processor_type, ProcessorType, FLAGS};
+use crate::Runner;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod transport;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use transport::{read_message, write_message};
+
+const THREAD_ID: u64 = 1;
+
+const REGISTERS_REFERENCE: u64 = 1;
+const FLAGS_REFERENCE: u64 = 2;
+const STACK_REFERENCE: u64 = 3;
+const MEMORY_REFERENCE: u64 = 4;
+
+/// Stepping over or out of a call that never returns gives up after this
+/// many instructions.
+const MAX_STEP_INSTRUCTIONS: usize = 1_000_000;
+
+/// A `disassemble` request decodes at most this many instructions, one for
+/// every address there is, however many the client asks for.
+const MAX_DISASSEMBLY: u64 = 0x10000;
+
+/// What the steps requested by the client run until.
+#[derive(Clone, Copy)]
+enum StepKind {
+    Continue,
+    Over,
+    Out,
+}
+
+pub struct DapSession {
+    runner: Option<Runner>,
+    io: BufferedIo,
+    input_port: u16,
+    input: String,
+    output_port: u16,
+    reported_outputs: usize,
+    seq: u64,
+    stop_on_entry: bool,
+    running: bool,
+    terminated: bool,
+    disconnected: bool,
+    function_breakpoints: Vec<BreakpointSpec>,
+    instruction_breakpoints: Vec<BreakpointSpec>,
+}
+
+/// A breakpoint as the editor set it, with its `condition` and
+/// `hitCondition` already checked.
+#[derive(Clone)]
+struct BreakpointSpec {
+    address: u16,
+    condition: Option<String>,
+    hit_count: Option<u32>,
+    /// Makes the breakpoint a logpoint that writes this and doesn't stop.
+    log_message: Option<String>,
+}
+
+impl BreakpointSpec {
+    fn new(address: u16, breakpoint: &Value) -> Result<Self, String> {
+        let condition = breakpoint["condition"]
+            .as_str()
+            .filter(|condition| !condition.trim().is_empty())
+            .map(str::to_string);
+        if let Some(condition) = &condition {
+            Expression::parse(condition)?;
+        }
+        let hit_count = match breakpoint["hitCondition"].as_str().map(str::trim) {
+            None | Some("") => None,
+            Some(count) => Some(
+                count
+                    .parse()
+                    .map_err(|_| format!("The hit condition {} isn't a count", count))?,
+            ),
+        };
+        let log_message = breakpoint["logMessage"].as_str().map(str::to_string);
+        if let Some(log_message) = &log_message {
+            Template::parse(log_message)?;
+        }
+        Ok(BreakpointSpec {
+            address,
+            condition,
+            hit_count,
+            log_message,
+        })
+    }
+}
+
+impl Default for DapSession {
+    fn default() -> Self {
+        DapSession {
+            runner: None,
+            io: BufferedIo::new(),
+            input_port: 0,
+            input: String::new(),
+            output_port: 0,
+            reported_outputs: 0,
+            seq: 0,
+            stop_on_entry: false,
+            running: false,
+            terminated: false,
+            disconnected: false,
+            function_breakpoints: Vec::new(),
+            instruction_breakpoints: Vec::new(),
+        }
+    }
+}
+
+impl DapSession {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the program was continued and hasn't stopped yet; `poll`
+    /// has to be called until it does.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Whether the program is over. The client can still restart it or
+    /// look at its final state until it disconnects.
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    /// Whether the client disconnected, so the adapter can exit.
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected
+    }
+
+    /// Handles one request, returning the messages to send back: the
+    /// response, followed by any events.
+    pub fn handle(&mut self, message: &str) -> Vec<String> {
+        let request: Value = match serde_json::from_str(message) {
+            Ok(request) => request,
+            Err(error) => {
+                return vec![self.event(
+                    "output",
+                    json!({
+                        "category": "console",
+                        "output": format!("Malformed request: {}\n", error),
+                    }),
+                )]
+            }
+        };
+        let command = request["command"].as_str().unwrap_or_default().to_string();
+        let arguments = &request["arguments"];
+        // The response goes out before the events, so it takes its number
+        // first.
+        self.seq += 1;
+        let seq = self.seq;
+        let mut events = Vec::new();
+        let result = self.dispatch(&command, arguments, &mut events);
+        let mut messages = vec![response(seq, &request, result)];
+        messages.extend(events);
+        messages
+    }
+
+    /// Runs a continued program for up to `max_steps` instructions,
+    /// returning the output and stop events that came up.
+    pub fn poll(&mut self, max_steps: usize) -> Vec<String> {
+        let mut events = Vec::new();
+        if self.running {
+            self.resume(StepKind::Continue, max_steps, &mut events);
+        }
+        events
+    }
+
+    fn dispatch(
+        &mut self,
+        command: &str,
+        arguments: &Value,
+        events: &mut Vec<String>,
+    ) -> Result<Value, String> {
+        match command {
+            "initialize" => {
+                events.push(self.event("initialized", json!({})));
+                Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsFunctionBreakpoints": true,
+                    "supportsConditionalBreakpoints": true,
+                    "supportsHitConditionalBreakpoints": true,
+                    "supportsLogPoints": true,
+                    "supportsInstructionBreakpoints": true,
+                    "supportsDisassembleRequest": true,
+                    "supportsReadMemoryRequest": true,
+                    "supportsSteppingGranularity": false,
+                    "supportsTerminateRequest": true,
+                    "supportsRestartRequest": true,
+                }))
+            }
+            "launch" => self.launch(arguments).map(|_| Value::Null),
+            "restart" => self.restart(events).map(|_| Value::Null),
+            "setBreakpoints" => {
+                let breakpoints: Vec<Value> = arguments["breakpoints"]
+                    .as_array()
+                    .map(|breakpoints| {
+                        breakpoints
+                            .iter()
+                            .map(|_| {
+                                json!({
+                                    "verified": false,
+                                    "message": "Executables carry no line information",
+                                })
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                Ok(json!({ "breakpoints": breakpoints }))
+            }
+            "setFunctionBreakpoints" => self.set_function_breakpoints(arguments),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(arguments),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "configurationDone" => {
+                self.runner()?;
+                if self.stop_on_entry {
+                    self.stop_at_entry(events)?;
+                } else {
+                    self.running = true;
+                }
+                Ok(Value::Null)
+            }
+            "threads" => Ok(json!({
+                "threads": [{ "id": THREAD_ID, "name": "main" }],
+            })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({
+                "scopes": [
+                    scope("Registers", REGISTERS_REFERENCE),
+                    scope("Flags", FLAGS_REFERENCE),
+                    scope("Stack", STACK_REFERENCE),
+                    scope("Memory", MEMORY_REFERENCE),
+                ],
+            })),
+            "variables" => self.variables(arguments),
+            "continue" => {
+                self.runner()?;
+                self.running = true;
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" => self.step(StepKind::Over, MAX_STEP_INSTRUCTIONS, events),
+            "stepIn" => self.step(StepKind::Continue, 1, events),
+            "stepOut" => self.step(StepKind::Out, MAX_STEP_INSTRUCTIONS, events),
+            "pause" => {
+                if self.running {
+                    self.running = false;
+                    events.push(self.stopped("pause"));
+                }
+                Ok(Value::Null)
+            }
+            "evaluate" => {
+                let expression = arguments["expression"].as_str().unwrap_or_default();
+                let value = self.runner()?.evaluate(expression)?;
+                Ok(json!({
+                    "result": format!("{} ({:#06x})", value, value),
+                    "variablesReference": 0,
+                }))
+            }
+            "readMemory" => self.read_memory(arguments),
+            "disassemble" => self.disassemble(arguments),
+            "disconnect" | "terminate" => {
+                self.running = false;
+                self.disconnected |= command == "disconnect";
+                if !self.terminated {
+                    self.terminated = true;
+                    events.push(self.event("terminated", json!({})));
+                }
+                Ok(Value::Null)
+            }
+            _ => Err(format!("Unsupported request {}", command)),
+        }
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<(), String> {
+        let program = match (&arguments["programBytes"], &arguments["program"]) {
+            (Value::Array(bytes), _) => bytes
+                .iter()
+                .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+                .collect::<Option<Vec<u8>>>()
+                .ok_or("programBytes must be an array of bytes")?,
+            (_, Value::String(path)) => read_program(path)?,
+            _ => return Err("Launch needs a program or programBytes".to_string()),
+        };
+        let processor_type = match arguments["processor"].as_str() {
+            Some(name) => parse_processor(name)?,
+            None => detect_processor_type(&program)?,
+        };
+        let mut runner = Runner::new(processor_type);
+        runner.load_program(&program)?;
+
+        self.input_port = port_argument(arguments, "inputPort")?;
+        self.output_port = port_argument(arguments, "outputPort")?;
+        self.input = arguments["input"].as_str().unwrap_or_default().to_string();
+        self.reset_io();
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        self.runner = Some(runner);
+        self.update_breakpoints();
+        Ok(())
+    }
+
+    /// Starts the program over from the state `launch` loaded, with the
+    /// same input and breakpoints.
+    fn restart(&mut self, events: &mut Vec<String>) -> Result<(), String> {
+        self.runner()?.reset()?;
+        self.reset_io();
+        self.terminated = false;
+        if self.stop_on_entry {
+            self.running = false;
+            self.stop_at_entry(events)?;
+        } else {
+            self.running = true;
+        }
+        Ok(())
+    }
+
+    /// Stops before the first instruction. That counts as arriving at the
+    /// entry point, so its logpoint writes a message and its breakpoint
+    /// doesn't stop the program a second time once it continues.
+    fn stop_at_entry(&mut self, events: &mut Vec<String>) -> Result<(), String> {
+        self.runner()?.check_entry();
+        self.report_log(events);
+        events.push(self.stopped("entry"));
+        Ok(())
+    }
+
+    fn reset_io(&mut self) {
+        self.io = BufferedIo::new();
+        self.io
+            .queue_input(self.input_port, self.input.bytes().map(u16::from));
+        self.reported_outputs = 0;
+    }
+
+    fn runner(&mut self) -> Result<&mut Runner, String> {
+        self.runner
+            .as_mut()
+            .ok_or_else(|| "No program has been launched".to_string())
+    }
+
+    fn set_function_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let runner = self.runner()?;
+        let mut addresses = Vec::new();
+        let mut breakpoints = Vec::new();
+        for breakpoint in arguments["breakpoints"].as_array().into_iter().flatten() {
+            let name = breakpoint["name"].as_str().unwrap_or_default();
+            let address = match runner.symbols.find(name) {
+                Some(symbol) if matches!(symbol.memory_type, MemoryType::Text) => symbol.address,
+                _ => {
+                    breakpoints.push(unverified(format!("No function named {}", name)));
+                    continue;
+                }
+            };
+            match BreakpointSpec::new(address, breakpoint) {
+                Ok(spec) => {
+                    addresses.push(spec);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "instructionReference": format!("{:#06x}", address),
+                    }));
+                }
+                Err(message) => breakpoints.push(unverified(message)),
+            }
+        }
+        self.function_breakpoints = addresses;
+        self.update_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_instruction_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        self.runner()?;
+        let mut addresses = Vec::new();
+        let mut breakpoints = Vec::new();
+        for breakpoint in arguments["breakpoints"].as_array().into_iter().flatten() {
+            let reference = breakpoint["instructionReference"]
+                .as_str()
+                .unwrap_or_default();
+            let offset = breakpoint["offset"].as_i64().unwrap_or(0);
+            let address = parse_address(reference)
+                .map(|address| address as i64 + offset)
+                .filter(|address| (0..=i64::from(u16::MAX)).contains(address));
+            let spec = address
+                .ok_or_else(|| format!("Invalid instruction reference {}", reference))
+                .and_then(|address| BreakpointSpec::new(address as u16, breakpoint));
+            match spec {
+                Ok(spec) => {
+                    addresses.push(spec);
+                    breakpoints.push(json!({ "verified": true }));
+                }
+                Err(message) => breakpoints.push(unverified(message)),
+            }
+        }
+        self.instruction_breakpoints = addresses;
+        self.update_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn update_breakpoints(&mut self) {
+        let specs: Vec<BreakpointSpec> = self
+            .function_breakpoints
+            .iter()
+            .chain(self.instruction_breakpoints.iter())
+            .cloned()
+            .collect();
+        if let Some(runner) = self.runner.as_mut() {
+            runner.clear_breakpoints();
+            runner.clear_logpoints();
+            for spec in specs {
+                // The condition and message were checked when the
+                // breakpoint was set
+                let _ = match spec.log_message {
+                    Some(message) => runner.add_logpoint(spec.address, &message),
+                    None => runner.add_conditional_breakpoint(
+                        spec.address,
+                        spec.condition,
+                        spec.hit_count,
+                    ),
+                };
+            }
+        }
+    }
+
+    fn step(
+        &mut self,
+        kind: StepKind,
+        max_steps: usize,
+        events: &mut Vec<String>,
+    ) -> Result<Value, String> {
+        self.runner()?;
+        if self.resume(kind, max_steps, events) == WasmProcessorContinue::Continue {
+            events.push(self.stopped("step"));
+        }
+        Ok(Value::Null)
+    }
+
+    /// Runs the program and queues the events for whatever happened; every
+    /// result but `Continue` has been reported once this returns.
+    fn resume(
+        &mut self,
+        kind: StepKind,
+        max_steps: usize,
+        events: &mut Vec<String>,
+    ) -> WasmProcessorContinue {
+        let Some(runner) = self.runner.as_mut() else {
+            return WasmProcessorContinue::Error;
+        };
+        let result = match kind {
+            StepKind::Continue => runner.run_n_with(&mut self.io, max_steps),
+            StepKind::Over => runner.step_over_with(&mut self.io, max_steps),
+            StepKind::Out => runner.step_out_with(&mut self.io, max_steps),
+        };
+        self.report_outputs(events);
+        self.report_log(events);
+        match result {
+            WasmProcessorContinue::Continue => {}
+            WasmProcessorContinue::Breakpoint => {
+                self.running = false;
+                events.push(self.stopped("breakpoint"));
+            }
+            WasmProcessorContinue::Catchpoint => {
+                self.running = false;
+                let description = self
+                    .runner
+                    .as_ref()
+                    .and_then(Runner::last_catch)
+                    .map(|event| event.description());
+                let event = self.event(
+                    "stopped",
+                    json!({
+                        "reason": "breakpoint",
+                        "description": description,
+                        "threadId": THREAD_ID,
+                        "allThreadsStopped": true,
+                    }),
+                );
+                events.push(event);
+            }
+            WasmProcessorContinue::Error => {
+                self.exception("The processor hit an invalid instruction", events)
+            }
+            WasmProcessorContinue::Timeout => {
+                self.exception("The program ran out of its time or step budget", events)
+            }
+            WasmProcessorContinue::Loop => self.exception("The program is stuck in a loop", events),
+            WasmProcessorContinue::IoError => {
+                let message = self
+                    .runner
+                    .as_ref()
+                    .and_then(Runner::io_failure)
+                    .map_or_else(|| "A port access failed".to_string(), |f| f.message());
+                self.exception(&message, events)
+            }
+            WasmProcessorContinue::Halt => {
+                self.running = false;
+                self.terminated = true;
+                events.push(self.event("exited", json!({ "exitCode": 0 })));
+                events.push(self.event("terminated", json!({})));
+            }
+        }
+        result
+    }
+
+    fn exception(&mut self, description: &str, events: &mut Vec<String>) {
+        self.running = false;
+        let event = self.event(
+            "stopped",
+            json!({
+                "reason": "exception",
+                "description": description,
+                "threadId": THREAD_ID,
+                "allThreadsStopped": true,
+            }),
+        );
+        events.push(event);
+    }
+
+    fn report_outputs(&mut self, events: &mut Vec<String>) {
+        let outputs = self.io.outputs()[self.reported_outputs..].to_vec();
+        self.reported_outputs += outputs.len();
+        let mut text = String::new();
+        for (port, value) in outputs {
+            if port == self.output_port {
+                text.push(char::from(value as u8));
+            } else {
+                if !text.is_empty() {
+                    events.push(self.output("stdout", std::mem::take(&mut text)));
+                }
+                let line = format!("port {}: {}\n", port, value);
+                events.push(self.output("console", line));
+            }
+        }
+        if !text.is_empty() {
+            events.push(self.output("stdout", text));
+        }
+    }
+
+    fn report_log(&mut self, events: &mut Vec<String>) {
+        let Some(runner) = self.runner.as_mut() else {
+            return;
+        };
+        for entry in runner.drain_log() {
+            let line = format!("{}\n", entry.message());
+            events.push(self.output("console", line));
+        }
+    }
+
+    fn stack_trace(&mut self) -> Result<Value, String> {
+        let runner = self.runner()?;
+        let pc = runner.pc();
+        let mut frames = vec![frame(0, runner.symbols.describe(pc), pc)];
+        for (index, call) in runner.backtrace().iter().enumerate() {
+            frames.push(frame(index + 1, call.caller(), call.call_site()));
+        }
+        let count = frames.len();
+        Ok(json!({ "stackFrames": frames, "totalFrames": count }))
+    }
+
+    fn variables(&mut self, arguments: &Value) -> Result<Value, String> {
+        let runner = self.runner()?;
+        let variables = match arguments["variablesReference"].as_u64() {
+            Some(REGISTERS_REFERENCE) => runner
+                .get_registers()
+                .iter()
+                .map(|register| variable(&register.name(), register.value()))
+                .collect(),
+            Some(FLAGS_REFERENCE) => {
+                let registers = runner.get_registers();
+                let flags = registers
+                    .iter()
+                    .find(|register| register.name() == "FR")
+                    .map_or(0, |register| register.value());
+                FLAGS
+                    .iter()
+                    .map(|(name, _, mask)| {
+                        json!({
+                            "name": name,
+                            "value": (flags & mask != 0).to_string(),
+                            "variablesReference": 0,
+                        })
+                    })
+                    .collect()
+            }
+            Some(STACK_REFERENCE) => {
+                let mut variables = Vec::new();
+                for stack in runner.stack_frames()? {
+                    for (index, value) in stack.values().iter().enumerate() {
+                        variables.push(variable(&format!("{}[{}]", stack.name(), index), *value));
+                    }
+                }
+                variables
+            }
+            Some(MEMORY_REFERENCE) => [("text", MemoryType::Text), ("data", MemoryType::Data)]
+                .iter()
+                .filter_map(|(name, mem_type)| {
+                    let layout = runner.processor.memory_layout(*mem_type)?;
+                    Some(json!({
+                        "name": name,
+                        "value": format!("{} {}-bit cells", layout.size, layout.cell_bits),
+                        "memoryReference": name,
+                        "variablesReference": 0,
+                    }))
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn read_memory(&mut self, arguments: &Value) -> Result<Value, String> {
+        let runner = self.runner()?;
+        let reference = arguments["memoryReference"].as_str().unwrap_or_default();
+        let (mem_type, base) = memory_reference(reference)?;
+        let layout = runner.memory_layout(mem_type)?;
+        let start = base as i64 + arguments["offset"].as_i64().unwrap_or(0);
+        if start < 0 || start as usize >= layout.size {
+            return Ok(json!({ "address": format!("{:#x}", start), "unreadableBytes": 0 }));
+        }
+        let start = start as usize;
+        let count = (arguments["count"].as_u64().unwrap_or(0) as usize).min(layout.size - start);
+        let cells = runner.read_cells(mem_type, start, count)?;
+        Ok(json!({
+            "address": format!("{:#x}", start),
+            "data": base64(&cells),
+        }))
+    }
+
+    fn disassemble(&mut self, arguments: &Value) -> Result<Value, String> {
+        let runner = self.runner()?;
+        let reference = arguments["memoryReference"].as_str().unwrap_or_default();
+        let start = parse_address(reference).ok_or("Invalid memory reference")? as i64
+            + arguments["offset"].as_i64().unwrap_or(0);
+        // Instructions differ in length, so going backwards isn't possible;
+        // a negative instruction offset starts at the address itself.
+        let mut skip = arguments["instructionOffset"]
+            .as_i64()
+            .unwrap_or(0)
+            .clamp(0, MAX_DISASSEMBLY as i64);
+        let count = arguments["instructionCount"]
+            .as_u64()
+            .unwrap_or(0)
+            .min(MAX_DISASSEMBLY);
+        let mut address = start.clamp(0, i64::from(u16::MAX)) as u16;
+        let mut instructions = Vec::new();
+        while (instructions.len() as u64) < count {
+            let decoded = runner.processor.decode(address);
+            let length = decoded.as_ref().map_or(1, |instruction| instruction.length);
+            if skip > 0 {
+                skip -= 1;
+            } else {
+                let text = decoded.map_or_else(|| "<invalid>".to_string(), |i| i.to_string());
+                let mut instruction = json!({
+                    "address": format!("{:#06x}", address),
+                    "instruction": text,
+                });
+                if let Some(symbol) = runner.symbols.describe(address) {
+                    instruction["symbol"] = json!(symbol);
+                }
+                instructions.push(instruction);
+            }
+            address = address.wrapping_add(length);
+        }
+        Ok(json!({ "instructions": instructions }))
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> String {
+        self.seq += 1;
+        json!({
+            "seq": self.seq,
+            "type": "event",
+            "event": event,
+            "body": body,
+        })
+        .to_string()
+    }
+
+    fn stopped(&mut self, reason: &str) -> String {
+        self.event(
+            "stopped",
+            json!({
+                "reason": reason,
+                "threadId": THREAD_ID,
+                "allThreadsStopped": true,
+            }),
+        )
+    }
+
+    fn output(&mut self, category: &str, output: String) -> String {
+        self.event("output", json!({ "category": category, "output": output }))
+    }
+}
+
+fn response(seq: u64, request: &Value, result: Result<Value, String>) -> String {
+    let mut response = json!({
+        "seq": seq,
+        "type": "response",
+        "request_seq": request["seq"],
+        "command": request["command"],
+        "success": result.is_ok(),
+    });
+    match result {
+        Ok(Value::Null) => {}
+        Ok(body) => response["body"] = body,
+        Err(message) => response["message"] = json!(message),
+    }
+    response.to_string()
+}
+
+fn scope(name: &str, reference: u64) -> Value {
+    json!({
+        "name": name,
+        "variablesReference": reference,
+        "expensive": false,
+    })
+}
+
+fn frame(id: usize, name: Option<String>, address: u16) -> Value {
+    json!({
+        "id": id,
+        "name": name.unwrap_or_else(|| format!("{:#06x}", address)),
+        "line": 0,
+        "column": 0,
+        "instructionPointerReference": format!("{:#06x}", address),
+    })
+}
+
+fn unverified(message: String) -> Value {
+    json!({ "verified": false, "message": message })
+}
+
+fn variable(name: &str, value: u16) -> Value {
+    json!({
+        "name": name,
+        "value": format!("{:#06x}", value),
+        "variablesReference": 0,
+    })
+}
+
+fn parse_address(reference: &str) -> Option<u16> {
+    match reference.strip_prefix("0x") {
+        Some(digits) => u16::from_str_radix(digits, 16).ok(),
+        None => reference.parse().ok(),
+    }
+}
+
+/// Memory references are either `text` or `data` for the start of a
+/// memory, or an address in text memory, like instruction references.
+fn memory_reference(reference: &str) -> Result<(MemoryType, u16), String> {
+    match reference {
+        "text" => Ok((MemoryType::Text, 0)),
+        "data" => Ok((MemoryType::Data, 0)),
+        _ => parse_address(reference)
+            .map(|address| (MemoryType::Text, address))
+            .ok_or_else(|| format!("Invalid memory reference {}", reference)),
+    }
+}
+
+fn port_argument(arguments: &Value, name: &str) -> Result<u16, String> {
+    match &arguments[name] {
+        Value::Null => Ok(0),
+        value => value
+            .as_u64()
+            .and_then(|port| u16::try_from(port).ok())
+            .ok_or_else(|| format!("{} must be a port number", name)),
+    }
+}
+
+fn parse_processor(name: &str) -> Result<ProcessorType, String> {
+    match name {
+        "stack" => Ok(ProcessorType::Stack),
+        "acc" => Ok(ProcessorType::Acc),
+        "risc" => Ok(ProcessorType::Risc),
+        "cisc" => Ok(ProcessorType::Cisc),
+        _ => Err(format!("Unknown processor {}", name)),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_program(path: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|error| format!("Failed to read {}: {}", path, error))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read_program(_path: &str) -> Result<Vec<u8>, String> {
+    Err("There is no file system here, pass programBytes instead".to_string())
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let triple = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |triple, (index, byte)| {
+                triple | u32::from(*byte) << (16 - 8 * index)
+            });
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(char::from(
+                    ALPHABET[(triple >> (18 - 6 * index)) as usize & 63],
+                ));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// A DAP session for JS: feed it the requests from the editor and send
+/// back whatever it returns.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct DapChannel {
+    session: DapSession,
+}
+
+#[wasm_bindgen]
+impl DapChannel {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> DapChannel {
+        Self::default()
+    }
+
+    #[wasm_bindgen]
+    pub fn handle(&mut self, message: &str) -> Vec<String> {
+        self.session.handle(message)
+    }
+
+    /// While the program runs, call this regularly to run it for a while.
+    #[wasm_bindgen]
+    pub fn poll(&mut self, max_steps: usize) -> Vec<String> {
+        self.session.poll(max_steps)
+    }
+
+    #[wasm_bindgen]
+    pub fn is_running(&self) -> bool {
+        self.session.is_running()
+    }
+
+    #[wasm_bindgen]
+    pub fn is_terminated(&self) -> bool {
+        self.session.is_terminated()
+    }
+
+    #[wasm_bindgen]
+    pub fn is_disconnected(&self) -> bool {
+        self.session.is_disconnected()
+    }
+}
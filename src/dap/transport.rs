@@ -0,0 +1,40 @@
+use std::io::{self, BufRead, ErrorKind, Write};
+
+/// Reads one message framed by a `Content-Length` header, or `None` once
+/// the stream ends.
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(ErrorKind::InvalidData, "Message without a Content-Length")
+    })?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|error| io::Error::new(ErrorKind::InvalidData, error))
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &str) -> io::Result<()> {
+    write!(
+        writer,
+        "Content-Length: {}\r\n\r\n{}",
+        message.len(),
+        message
+    )?;
+    writer.flush()
+}
@@ -0,0 +1,170 @@
+use super::{fetch_word, lookup, Instruction, InstructionKind, OpcodeInfo, Operand};
+use InstructionKind::*;
+use Operand::{Register, RegisterIndirect};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Extra {
+    None,
+    /// A two-byte immediate value
+    Immediate,
+    /// A two-byte memory address
+    Memory,
+    /// A two-byte code address
+    Target,
+    /// A two-byte port number
+    Port,
+}
+
+/// The accumulator instructions name their registers in the opcode itself;
+/// `fixed` lists those, `extra` what follows the opcode byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Format {
+    pub fixed: &'static [Operand],
+    pub extra: Extra,
+}
+
+macro_rules! opcodes {
+    ($($opcode:expr => $mnemonic:literal [$($fixed:expr),*] $extra:ident $kind:ident),* $(,)?) => {
+        pub const OPCODES: &[OpcodeInfo<Format>] = &[
+            $(OpcodeInfo {
+                opcode: $opcode,
+                mnemonic: $mnemonic,
+                format: Format {
+                    fixed: &[$($fixed),*],
+                    extra: Extra::$extra,
+                },
+                kind: $kind,
+            }),*
+        ];
+    };
+}
+
+const ACC: Operand = Register("ACC");
+const IR1: Operand = Register("IR1");
+const IR2: Operand = Register("IR2");
+const AT_ACC: Operand = RegisterIndirect("ACC");
+const AT_IR1: Operand = RegisterIndirect("IR1");
+const AT_IR2: Operand = RegisterIndirect("IR2");
+
+opcodes! {
+    0b000000 => "halt" [] None Halt,
+    0b000001 => "load" [AT_ACC] None Other,
+    0b10010000 => "load" [] Immediate Other,
+    0b000010 => "loadf" [] None Other,
+    0b1000001 => "load" [AT_IR1] None Other,
+    0b1000010 => "load" [AT_IR2] None Other,
+    0b011111 => "mov" [ACC, IR1] None Other,
+    0b100000 => "mov" [ACC, IR2] None Other,
+    0b000011 => "store" [AT_IR1] None Other,
+    0b10010001 => "store" [AT_IR1] Immediate Other,
+    0b100011 => "store" [AT_IR2] None Other,
+    0b11110010 => "store" [AT_IR2] Immediate Other,
+    0b000100 => "storef" [] None Other,
+    0b100001 => "mov" [IR1, ACC] None Other,
+    0b100010 => "mov" [IR2, ACC] None Other,
+    0b111111 => "mov" [IR2, IR1] None Other,
+    0b1000000 => "mov" [IR1, IR2] None Other,
+    0b10000000 => "mov" [] Immediate Other,
+    0b000101 => "push" [ACC] None Other,
+    0b000111 => "pop" [ACC] None Other,
+    0b000110 => "pushf" [] None Other,
+    0b001000 => "popf" [] None Other,
+    0b111101 => "push" [IR1] None Other,
+    0b001001 => "pop" [IR1] None Other,
+    0b100100 => "push" [IR2] None Other,
+    0b100101 => "pop" [IR2] None Other,
+    0b001010 => "add" [] Memory Other,
+    0b100110 => "add" [AT_IR1] None Other,
+    0b100111 => "add" [AT_IR2] None Other,
+    0b001011 => "sub" [] Memory Other,
+    0b101000 => "sub" [AT_IR1] None Other,
+    0b101001 => "sub" [AT_IR2] None Other,
+    0b001110 => "mul" [] Memory Other,
+    0b101010 => "mul" [AT_IR1] None Other,
+    0b101011 => "mul" [AT_IR2] None Other,
+    0b001111 => "div" [] Memory Other,
+    0b101100 => "div" [AT_IR1] None Other,
+    0b101101 => "div" [AT_IR2] None Other,
+    0b001100 => "inc" [ACC] None Other,
+    0b101110 => "inc" [IR1] None Other,
+    0b101111 => "inc" [IR2] None Other,
+    0b001101 => "dec" [ACC] None Other,
+    0b110000 => "dec" [IR1] None Other,
+    0b110001 => "dec" [IR2] None Other,
+    0b010000 => "and" [] Memory Other,
+    0b110010 => "and" [AT_IR1] None Other,
+    0b110011 => "and" [AT_IR2] None Other,
+    0b010001 => "or" [] Memory Other,
+    0b110100 => "or" [AT_IR1] None Other,
+    0b110101 => "or" [AT_IR2] None Other,
+    0b010010 => "xor" [] Memory Other,
+    0b110110 => "xor" [AT_IR1] None Other,
+    0b110111 => "xor" [AT_IR2] None Other,
+    0b010011 => "not" [AT_ACC] None Other,
+    0b111000 => "not" [AT_IR1] None Other,
+    0b111110 => "not" [AT_IR2] None Other,
+    0b10000001 => "lsh" [] Immediate Other,
+    0b10000010 => "rsh" [] Immediate Other,
+    0b10000100 => "call" [] Target Call,
+    0b010100 => "call" [ACC] None Call,
+    0b010101 => "ret" [] None Return,
+    0b010110 => "cmp" [] Memory Other,
+    0b10000101 => "cmp" [] Immediate Other,
+    0b111001 => "cmp" [AT_IR1] None Other,
+    0b111010 => "cmp" [AT_IR2] None Other,
+    0b10000110 => "test" [] Immediate Other,
+    0b10001111 => "test" [] Memory Other,
+    0b111011 => "test" [AT_IR1] None Other,
+    0b111100 => "test" [AT_IR2] None Other,
+    0b10000111 => "jmp" [] Target Jump,
+    0b010111 => "jmp" [ACC] None Jump,
+    0b10001000 => "je" [] Target Branch,
+    0b011000 => "je" [ACC] None Branch,
+    0b10001001 => "jne" [] Target Branch,
+    0b011001 => "jne" [ACC] None Branch,
+    0b10001010 => "jg" [] Target Branch,
+    0b011010 => "jg" [ACC] None Branch,
+    0b10001011 => "jge" [] Target Branch,
+    0b011011 => "jge" [ACC] None Branch,
+    0b10001100 => "jl" [] Target Branch,
+    0b011100 => "jl" [ACC] None Branch,
+    0b10001101 => "jle" [] Target Branch,
+    0b011101 => "jle" [ACC] None Branch,
+    0b10001110 => "in" [] Port Input,
+    0b10011000 => "out" [] Port Output,
+}
+
+/// Decodes the instruction at `address`; `fetch` reads one byte of memory.
+pub fn decode<F: Fn(u16) -> u8>(address: u16, fetch: F) -> Option<Instruction> {
+    let opcode = fetch(address);
+    let info = lookup(OPCODES, opcode)?;
+    let mut operands = info.format.fixed.to_vec();
+    let word = fetch_word(&fetch, address.wrapping_add(1));
+    let length = match info.format.extra {
+        Extra::None => 1,
+        Extra::Immediate => {
+            operands.push(Operand::Immediate(word));
+            3
+        }
+        Extra::Memory => {
+            operands.push(Operand::Memory(word));
+            3
+        }
+        Extra::Target => {
+            operands.push(Operand::Target(word));
+            3
+        }
+        Extra::Port => {
+            operands.push(Operand::Port(word));
+            3
+        }
+    };
+    Some(Instruction {
+        address,
+        opcode,
+        mnemonic: info.mnemonic,
+        operands,
+        length,
+        kind: info.kind,
+    })
+}
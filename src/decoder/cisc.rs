@@ -0,0 +1,228 @@
+use super::{fetch_word, lookup, Instruction, InstructionKind, OpcodeInfo, Operand};
+use InstructionKind::*;
+
+/// The operand layouts of the CISC instructions. `Reg` is a whole byte
+/// holding a register id, `Pair` a byte holding two of them (the first in the
+/// high nibble), `Word` a two-byte value. `@` marks a memory operand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    None,
+    /// `reg`
+    Reg,
+    /// `[reg]`
+    AtReg,
+    /// `[reg+word]`
+    AtRegOffset,
+    /// `reg+word`, a computed code address
+    RegOffset,
+    /// `reg, reg`
+    PairRegReg,
+    /// `reg, [reg]`
+    PairRegAtReg,
+    /// `[reg], reg`
+    PairAtRegReg,
+    /// `reg, [reg+word]`
+    PairRegAtRegOffset,
+    /// `reg, word`
+    RegWord,
+    /// `[reg], word`
+    AtRegWord,
+    /// `[reg+word], reg`
+    AtRegOffsetReg,
+    /// `[reg+word], word`
+    AtRegOffsetWord,
+    /// `word`
+    Word,
+    /// `word` as a code address
+    Target,
+    /// `reg, port`
+    RegPort,
+    /// `[reg], port`
+    AtRegPort,
+    /// `[reg+word], port`
+    AtRegOffsetPort,
+    /// `port, reg`
+    PortReg,
+    /// `port, [reg]`
+    PortAtReg,
+    /// `port, [reg+word]`
+    PortAtRegOffset,
+}
+
+macro_rules! opcodes {
+    ($($opcode:expr => $mnemonic:literal $format:ident $kind:ident),* $(,)?) => {
+        pub const OPCODES: &[OpcodeInfo<Format>] = &[
+            $(OpcodeInfo {
+                opcode: $opcode,
+                mnemonic: $mnemonic,
+                format: Format::$format,
+                kind: $kind,
+            }),*
+        ];
+    };
+}
+
+// `leave` and the offset form of `inc` have opcodes that don't fit into a
+// byte in the emulator, so they can never be executed and are left out.
+opcodes! {
+    0b00000000 => "halt" None Halt,
+    0b10000000 => "mov" RegWord Other,
+    0b01100000 => "mov" PairRegReg Other,
+    0b01100001 => "mov" PairRegAtReg Other,
+    0b10100000 => "mov" PairRegAtRegOffset Other,
+    // The emulator runs this one as a register copy, like 0b01100000, and
+    // never touches memory.
+    0b01100010 => "mov" PairRegReg Other,
+    0b10000001 => "mov" AtRegWord Other,
+    0b10100001 => "mov" AtRegOffsetReg Other,
+    0b11000000 => "mov" AtRegOffsetWord Other,
+    0b00000001 => "push" Reg Other,
+    0b01000000 => "push" Word Other,
+    0b00000010 => "pop" Reg Other,
+    0b01000001 => "enter" Word Other,
+    0b01100011 => "add" PairRegAtReg Other,
+    0b01100100 => "add" PairRegReg Other,
+    0b10100010 => "add" PairRegAtRegOffset Other,
+    0b01100101 => "add" PairAtRegReg Other,
+    0b01100110 => "sub" PairRegAtReg Other,
+    0b01100111 => "sub" PairRegReg Other,
+    0b10100011 => "sub" PairRegAtRegOffset Other,
+    0b01101000 => "sub" PairAtRegReg Other,
+    0b00011111 => "inc" Reg Other,
+    0b00000100 => "inc" AtReg Other,
+    0b00000101 => "dec" Reg Other,
+    0b00000110 => "dec" AtReg Other,
+    0b10000011 => "dec" AtRegOffset Other,
+    0b01101001 => "mul" PairRegReg Other,
+    0b01101010 => "mul" PairRegAtReg Other,
+    0b01101011 => "mul" PairAtRegReg Other,
+    0b10000100 => "mul" RegWord Other,
+    0b10100100 => "mul" PairRegAtRegOffset Other,
+    0b01101100 => "div" PairRegReg Other,
+    0b01101101 => "div" PairRegAtReg Other,
+    0b01101110 => "div" PairAtRegReg Other,
+    0b10000101 => "div" RegWord Other,
+    0b10100101 => "div" PairRegAtRegOffset Other,
+    0b01101111 => "and" PairRegReg Other,
+    0b01110000 => "and" PairRegAtReg Other,
+    0b01110001 => "or" PairRegReg Other,
+    0b01110010 => "or" PairRegAtReg Other,
+    0b01110011 => "xor" PairRegReg Other,
+    0b01110100 => "xor" PairRegAtReg Other,
+    0b00000111 => "not" Reg Other,
+    0b00001000 => "not" AtReg Other,
+    0b10000110 => "lsh" RegWord Other,
+    0b10000111 => "lsh" AtRegWord Other,
+    0b11000001 => "lsh" AtRegOffsetWord Other,
+    0b10001000 => "rsh" RegWord Other,
+    0b10001001 => "rsh" AtRegWord Other,
+    0b11000010 => "rsh" AtRegOffsetWord Other,
+    0b01000010 => "call" Target Call,
+    0b00001001 => "call" Reg Call,
+    145 => "call" RegOffset Call,
+    0b00100001 => "ret" None Return,
+    0b01110101 => "cmp" PairRegReg Other,
+    0b10001011 => "cmp" RegWord Other,
+    146 => "cmp" PairRegAtReg Other,
+    0b10100110 => "cmp" PairRegAtRegOffset Other,
+    0b01110111 => "test" PairRegReg Other,
+    0b01111000 => "test" PairRegAtReg Other,
+    0b10100111 => "test" PairRegAtRegOffset Other,
+    0b01000011 => "jmp" Target Jump,
+    0b00001010 => "jmp" Reg Jump,
+    0b10001100 => "jmp" RegOffset Jump,
+    0b01000100 => "je" Target Branch,
+    0b01000101 => "jne" Target Branch,
+    0b01000110 => "jg" Target Branch,
+    0b01000111 => "jge" Target Branch,
+    0b01001000 => "jl" Target Branch,
+    0b01001001 => "jle" Target Branch,
+    0b10001101 => "in" RegPort Input,
+    0b10001110 => "in" AtRegPort Input,
+    0b11000011 => "in" AtRegOffsetPort Input,
+    0b11001111 => "out" PortReg Output,
+    0b10001111 => "out" PortReg Output,
+    0b10010000 => "out" PortAtReg Output,
+    0b11000100 => "out" PortAtRegOffset Output,
+    0b00100010 => "nop" None Other,
+}
+
+pub const REGISTERS: [&str; 6] = ["R00", "R01", "R10", "R11", "BP", "SP"];
+
+pub fn register_name(id: u8) -> &'static str {
+    REGISTERS.get(id as usize).copied().unwrap_or("??")
+}
+
+/// Decodes the instruction at `address`; `fetch` reads one byte of memory.
+pub fn decode<F: Fn(u16) -> u8>(address: u16, fetch: F) -> Option<Instruction> {
+    use Operand::*;
+
+    let opcode = fetch(address);
+    let info = lookup(OPCODES, opcode)?;
+    let at = |offset: u16| address.wrapping_add(offset);
+    let reg = |offset: u16| register_name(fetch(at(offset)));
+    let pair = || {
+        let ids = fetch(at(1));
+        (register_name(ids >> 4), register_name(ids & 0b1111))
+    };
+    let word = |offset: u16| fetch_word(&fetch, at(offset));
+
+    let (operands, length) = match info.format {
+        Format::None => (vec![], 1),
+        Format::Reg => (vec![Register(reg(1))], 2),
+        Format::AtReg => (vec![RegisterIndirect(reg(1))], 2),
+        Format::AtRegOffset => (vec![RegisterOffsetIndirect(reg(1), word(2))], 4),
+        Format::RegOffset => (vec![RegisterOffset(reg(1), word(2))], 4),
+        Format::PairRegReg => {
+            let (first, second) = pair();
+            (vec![Register(first), Register(second)], 2)
+        }
+        Format::PairRegAtReg => {
+            let (first, second) = pair();
+            (vec![Register(first), RegisterIndirect(second)], 2)
+        }
+        Format::PairAtRegReg => {
+            let (first, second) = pair();
+            (vec![RegisterIndirect(first), Register(second)], 2)
+        }
+        Format::PairRegAtRegOffset => {
+            let (first, second) = pair();
+            (
+                vec![Register(first), RegisterOffsetIndirect(second, word(2))],
+                4,
+            )
+        }
+        Format::RegWord => (vec![Register(reg(1)), Immediate(word(2))], 4),
+        Format::AtRegWord => (vec![RegisterIndirect(reg(1)), Immediate(word(2))], 4),
+        Format::AtRegOffsetReg => (
+            vec![RegisterOffsetIndirect(reg(1), word(2)), Register(reg(4))],
+            5,
+        ),
+        Format::AtRegOffsetWord => (
+            vec![RegisterOffsetIndirect(reg(1), word(2)), Immediate(word(4))],
+            6,
+        ),
+        Format::Word => (vec![Immediate(word(1))], 3),
+        Format::Target => (vec![Target(word(1))], 3),
+        Format::RegPort => (vec![Register(reg(1)), Port(word(2))], 4),
+        Format::AtRegPort => (vec![RegisterIndirect(reg(1)), Port(word(2))], 4),
+        Format::AtRegOffsetPort => (
+            vec![RegisterOffsetIndirect(reg(1), word(2)), Port(word(4))],
+            6,
+        ),
+        Format::PortReg => (vec![Port(word(1)), Register(reg(3))], 4),
+        Format::PortAtReg => (vec![Port(word(1)), RegisterIndirect(reg(3))], 4),
+        Format::PortAtRegOffset => (
+            vec![Port(word(1)), RegisterOffsetIndirect(reg(3), word(4))],
+            6,
+        ),
+    };
+    Some(Instruction {
+        address,
+        opcode,
+        mnemonic: info.mnemonic,
+        operands,
+        length,
+        kind: info.kind,
+    })
+}
@@ -0,0 +1,118 @@
+use std::fmt;
+
+pub mod acc;
+pub mod cisc;
+pub mod risc;
+pub mod stack;
+
+/// What an instruction does to the flow of control.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InstructionKind {
+    Halt,
+    Call,
+    Return,
+    Jump,
+    Branch,
+    Input,
+    Output,
+    Other,
+}
+
+impl InstructionKind {
+    /// The lowercase name front ends see, such as `call` or `branch`.
+    pub fn name(self) -> &'static str {
+        match self {
+            InstructionKind::Halt => "halt",
+            InstructionKind::Call => "call",
+            InstructionKind::Return => "return",
+            InstructionKind::Jump => "jump",
+            InstructionKind::Branch => "branch",
+            InstructionKind::Input => "input",
+            InstructionKind::Output => "output",
+            InstructionKind::Other => "other",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Register(&'static str),
+    Immediate(u16),
+    /// A memory cell at a fixed address, `[0x0010]`
+    Memory(u16),
+    /// A memory cell addressed by a register, `[R00]`
+    RegisterIndirect(&'static str),
+    /// A register plus a constant, used as a code address, `R00+0x0004`
+    RegisterOffset(&'static str, u16),
+    /// A memory cell addressed by a register plus a constant, `[BP+0x0004]`
+    RegisterOffsetIndirect(&'static str, u16),
+    /// An absolute code address; relative jumps are resolved when decoding
+    Target(u16),
+    Port(u16),
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Register(name) => write!(f, "{}", name),
+            Operand::Immediate(value) => write!(f, "{}", value),
+            Operand::Memory(address) => write!(f, "[{:#06x}]", address),
+            Operand::RegisterIndirect(name) => write!(f, "[{}]", name),
+            Operand::RegisterOffset(name, offset) => write!(f, "{}+{:#06x}", name, offset),
+            Operand::RegisterOffsetIndirect(name, offset) => {
+                write!(f, "[{}+{:#06x}]", name, offset)
+            }
+            Operand::Target(address) => write!(f, "{:#06x}", address),
+            Operand::Port(port) => write!(f, "port {}", port),
+        }
+    }
+}
+
+/// A single decoded instruction. Addresses and lengths are counted in memory
+/// cells of the text memory, the same unit as the program counter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: u16,
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+    pub operands: Vec<Operand>,
+    pub length: u16,
+    pub kind: InstructionKind,
+}
+
+impl Instruction {
+    /// Where execution continues if the instruction doesn't jump.
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.length)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.mnemonic)?;
+        for (i, operand) in self.operands.iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { " " } else { ", " }, operand)?;
+        }
+        Ok(())
+    }
+}
+
+/// An entry of an instruction set's opcode table.
+pub struct OpcodeInfo<F: 'static> {
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+    pub format: F,
+    pub kind: InstructionKind,
+}
+
+pub(crate) fn lookup<F>(
+    table: &'static [OpcodeInfo<F>],
+    opcode: u8,
+) -> Option<&'static OpcodeInfo<F>> {
+    table.iter().find(|entry| entry.opcode == opcode)
+}
+
+/// Reads a big-endian 16-bit word out of two consecutive 8-bit cells.
+pub(crate) fn fetch_word<F: Fn(u16) -> u8>(fetch: &F, address: u16) -> u16 {
+    u16::from(fetch(address)) << 8 | u16::from(fetch(address.wrapping_add(1)))
+}
@@ -0,0 +1,171 @@
+use super::{lookup, Instruction, InstructionKind, OpcodeInfo, Operand};
+use InstructionKind::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    None,
+    Reg1,
+    Reg2,
+    Reg3,
+    /// `r1, [r2]`
+    Load,
+    /// `[r1], r2`
+    Store,
+    RegImmediate,
+    RegPort,
+    Target,
+    PortImmediate,
+    PortReg,
+}
+
+macro_rules! opcodes {
+    ($($opcode:expr => $mnemonic:literal $format:ident $kind:ident),* $(,)?) => {
+        pub const OPCODES: &[OpcodeInfo<Format>] = &[
+            $(OpcodeInfo {
+                opcode: $opcode,
+                mnemonic: $mnemonic,
+                format: Format::$format,
+                kind: $kind,
+            }),*
+        ];
+    };
+}
+
+opcodes! {
+    0b000000 => "halt" None Halt,
+    0b000001 => "load" Load Other,
+    0b000010 => "store" Store Other,
+    0b000110 => "mov" RegImmediate Other,
+    0b000101 => "mov" Reg2 Other,
+    0b101000 => "push" Reg1 Other,
+    0b101001 => "pop" Reg1 Other,
+    0b000011 => "add" Reg3 Other,
+    0b100100 => "addc" Reg3 Other,
+    0b000100 => "sub" Reg3 Other,
+    0b001010 => "mul" Reg3 Other,
+    0b001011 => "div" Reg3 Other,
+    0b001100 => "and" Reg3 Other,
+    0b001101 => "or" Reg3 Other,
+    0b001110 => "xor" Reg3 Other,
+    0b001111 => "not" Reg2 Other,
+    0b010000 => "lsh" Reg3 Other,
+    0b010001 => "rsh" Reg3 Other,
+    0b010010 => "call" Target Call,
+    0b010011 => "call" Reg1 Call,
+    0b010100 => "ret" None Return,
+    0b010101 => "cmp" Reg2 Other,
+    0b010110 => "cmp" RegImmediate Other,
+    0b010111 => "test" Reg2 Other,
+    0b011000 => "test" RegImmediate Other,
+    0b011001 => "jmp" Target Jump,
+    0b011010 => "jmp" Reg1 Jump,
+    0b001000 => "je" Target Branch,
+    0b011011 => "jne" Target Branch,
+    0b011100 => "jg" Target Branch,
+    0b011101 => "jge" Target Branch,
+    0b011110 => "jl" Target Branch,
+    0b011111 => "jle" Target Branch,
+    0b100000 => "in" RegPort Input,
+    0b100001 => "out" PortImmediate Output,
+    0b100010 => "out" PortReg Output,
+    0b100011 => "nop" None Other,
+}
+
+pub const REGISTERS: [&str; 5] = ["R00", "R01", "R10", "R11", "SP"];
+
+pub fn register_name(id: u8) -> &'static str {
+    REGISTERS.get(id as usize).copied().unwrap_or("??")
+}
+
+/// Decodes the instruction at `address`; `fetch` reads one byte of memory.
+///
+/// The opcode takes the top six bits of the first byte, and the arguments
+/// start in its low two bits, so most fields straddle byte boundaries.
+pub fn decode<F: Fn(u16) -> u8>(address: u16, fetch: F) -> Option<Instruction> {
+    let first = fetch(address);
+    let opcode = first >> 2;
+    let info = lookup(OPCODES, opcode)?;
+    let head = first & 0b11;
+    let byte = |offset: u16| fetch(address.wrapping_add(offset));
+
+    let registers = || {
+        let next = byte(1);
+        (
+            register_name((next >> 7) & 0b1 | head << 1),
+            register_name((next >> 4) & 0b111),
+            register_name((next >> 1) & 0b111),
+        )
+    };
+    let split_immediate = |head: u8, offset: u16| {
+        (head as u16) << 14 | (byte(offset) as u16) << 6 | (byte(offset + 1) as u16) >> 2
+    };
+    let word = |offset: u16| (byte(offset) as u16) << 8 | byte(offset + 1) as u16;
+
+    let (operands, length) = match info.format {
+        Format::None => (vec![], 1),
+        Format::Reg1 => (vec![Operand::Register(registers().0)], 2),
+        Format::Reg2 => {
+            let (r1, r2, _) = registers();
+            (vec![Operand::Register(r1), Operand::Register(r2)], 2)
+        }
+        Format::Reg3 => {
+            let (r1, r2, r3) = registers();
+            let operands = vec![
+                Operand::Register(r1),
+                Operand::Register(r2),
+                Operand::Register(r3),
+            ];
+            (operands, 2)
+        }
+        Format::Load => {
+            let (r1, r2, _) = registers();
+            (
+                vec![Operand::Register(r1), Operand::RegisterIndirect(r2)],
+                2,
+            )
+        }
+        Format::Store => {
+            let (r1, r2, _) = registers();
+            (
+                vec![Operand::RegisterIndirect(r1), Operand::Register(r2)],
+                2,
+            )
+        }
+        Format::RegImmediate => (
+            vec![
+                Operand::Register(registers().0),
+                Operand::Immediate(word(2)),
+            ],
+            4,
+        ),
+        Format::RegPort => (
+            vec![Operand::Register(registers().0), Operand::Port(word(2))],
+            4,
+        ),
+        Format::Target => (vec![Operand::Target(split_immediate(head, 1))], 3),
+        Format::PortImmediate => {
+            let port = split_immediate(head, 1);
+            let data = split_immediate(byte(2), 3);
+            (vec![Operand::Port(port), Operand::Immediate(data)], 5)
+        }
+        Format::PortReg => {
+            let port = split_immediate(head, 1);
+            let register = (byte(2) << 1) & 0b110 | (byte(3) >> 7) & 0b1;
+            (
+                vec![
+                    Operand::Port(port),
+                    Operand::Register(register_name(register)),
+                ],
+                4,
+            )
+        }
+    };
+    Some(Instruction {
+        address,
+        opcode,
+        mnemonic: info.mnemonic,
+        operands,
+        length,
+        kind: info.kind,
+    })
+}
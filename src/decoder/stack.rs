@@ -0,0 +1,98 @@
+use super::{lookup, Instruction, InstructionKind, OpcodeInfo, Operand};
+use InstructionKind::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    None,
+    /// A three-cell immediate value
+    Immediate,
+    /// A three-cell data memory address
+    Memory,
+    /// A three-cell offset relative to the next instruction
+    Relative,
+    /// A three-cell port number
+    Port,
+}
+
+macro_rules! opcodes {
+    ($($opcode:expr => $mnemonic:literal $format:ident $kind:ident),* $(,)?) => {
+        pub const OPCODES: &[OpcodeInfo<Format>] = &[
+            $(OpcodeInfo {
+                opcode: $opcode,
+                mnemonic: $mnemonic,
+                format: Format::$format,
+                kind: $kind,
+            }),*
+        ];
+    };
+}
+
+opcodes! {
+    0b000000 => "halt" None Halt,
+    0b000001 => "load" None Other,
+    0b000010 => "loadf" None Other,
+    0b100000 => "load" Memory Other,
+    0b000100 => "store" None Other,
+    0b100001 => "store" Immediate Other,
+    0b000101 => "storef" None Other,
+    0b000110 => "swap" None Other,
+    0b000111 => "dup" None Other,
+    0b001000 => "dup2" None Other,
+    0b100010 => "mov" Immediate Other,
+    0b001001 => "push" None Other,
+    0b001010 => "pushf" None Other,
+    0b001100 => "pop" None Other,
+    0b001101 => "popf" None Other,
+    0b001110 => "add" None Other,
+    0b001111 => "sub" None Other,
+    0b010000 => "mul" None Other,
+    0b010001 => "div" None Other,
+    0b010010 => "and" None Other,
+    0b010011 => "or" None Other,
+    0b010100 => "xor" None Other,
+    0b010101 => "not" None Other,
+    0b100011 => "lsh" Immediate Other,
+    0b100100 => "rsh" Immediate Other,
+    0b100101 => "call" Relative Call,
+    0b010110 => "call" None Call,
+    0b010111 => "ret" None Return,
+    0b011000 => "cmpe" None Other,
+    0b100110 => "cmpe" Immediate Other,
+    0b011001 => "cmpb" None Other,
+    0b100111 => "cmpb" Immediate Other,
+    0b011010 => "jmp" None Jump,
+    0b101000 => "jmp" Relative Jump,
+    0b011011 => "jc" None Branch,
+    0b101001 => "jc" Relative Branch,
+    0b101010 => "in" Port Input,
+    0b101011 => "out" Port Output,
+    0b011100 => "nop" None Other,
+}
+
+/// Decodes the instruction at `address`; `fetch` reads one 6-bit text cell.
+pub fn decode<F: Fn(u16) -> u8>(address: u16, fetch: F) -> Option<Instruction> {
+    let opcode = fetch(address) & 0b111111;
+    let info = lookup(OPCODES, opcode)?;
+    let immediate = || {
+        let high = u16::from(fetch(address.wrapping_add(1)));
+        let middle = u16::from(fetch(address.wrapping_add(2)));
+        let low = u16::from(fetch(address.wrapping_add(3)));
+        high << 12 | middle << 6 | low
+    };
+    let next = address.wrapping_add(4);
+    let (operands, length) = match info.format {
+        Format::None => (vec![], 1),
+        Format::Immediate => (vec![Operand::Immediate(immediate())], 4),
+        Format::Memory => (vec![Operand::Memory(immediate())], 4),
+        Format::Relative => (vec![Operand::Target(immediate().wrapping_add(next))], 4),
+        Format::Port => (vec![Operand::Port(immediate())], 4),
+    };
+    Some(Instruction {
+        address,
+        opcode,
+        mnemonic: info.mnemonic,
+        operands,
+        length,
+        kind: info.kind,
+    })
+}
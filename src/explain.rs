@@ -0,0 +1,439 @@
+//! Explanations of single steps in plain words, such as
+//! `ADD R00, R01 → R00 = 3 + 4 = 7; ZF cleared`, for people learning how the
+//! instruction sets work.
+
+use crate::decoder::{Instruction, InstructionKind, Operand};
+use crate::io::PortAccess;
+use crate::memory::{MemoryBlock, MemoryLayout, MemoryType};
+use crate::processor::WasmProcessorContinue;
+use crate::processors::FLAGS;
+use crate::registers::RegisterState;
+
+const TOP: &str = "top of stack";
+
+/// The machine around one step, kept by `Runner::step` so that the step can
+/// be explained afterwards.
+pub struct StepRecord {
+    pub status: WasmProcessorContinue,
+    pub instruction: Option<Instruction>,
+    pub pc_after: u16,
+    /// Whether the processor keeps its operands on a stack rather than in
+    /// registers.
+    pub stack_machine: bool,
+    pub registers_before: Vec<RegisterState>,
+    pub registers_after: Vec<RegisterState>,
+    pub memory_before: Vec<MemoryBlock>,
+    pub memory_after: Vec<MemoryBlock>,
+    pub layouts: Vec<(MemoryType, MemoryLayout)>,
+    /// The memory that operands such as `[R00]` address.
+    pub operand_memory: MemoryType,
+    /// The top two values of the stack before the step, and the top one
+    /// after; only filled in for stack machines.
+    pub stack_before: [u16; 2],
+    pub stack_after: u16,
+    pub ports: Vec<PortAccess>,
+}
+
+/// The symbol arithmetic and logic mnemonics are written with.
+fn operator(mnemonic: &str) -> Option<&'static str> {
+    Some(match mnemonic {
+        "add" | "addc" => "+",
+        "sub" => "-",
+        "mul" => "*",
+        "div" => "/",
+        "and" => "&",
+        "or" => "|",
+        "xor" => "^",
+        "lsh" => "<<",
+        "rsh" => ">>",
+        _ => return None,
+    })
+}
+
+impl StepRecord {
+    pub fn explain(&self) -> String {
+        let Some(instruction) = &self.instruction else {
+            return match self.status {
+                WasmProcessorContinue::Breakpoint => {
+                    "No instruction ran: the step stopped at a breakpoint".to_string()
+                }
+                WasmProcessorContinue::Timeout => {
+                    "No instruction ran: the budget ran out".to_string()
+                }
+                _ => "No instruction ran".to_string(),
+            };
+        };
+        let mut head = instruction.mnemonic.to_ascii_uppercase();
+        for (i, operand) in instruction.operands.iter().enumerate() {
+            head += if i == 0 { " " } else { ", " };
+            head += &operand.to_string();
+        }
+        if self.status == WasmProcessorContinue::Error {
+            return format!("{} → the processor failed while running it", head);
+        }
+
+        let (body, computes) = self.body(instruction);
+        let flags = self.flags();
+        let flags = match (flags.is_empty(), computes) {
+            (false, _) => format!("; {}", flags.join(", ")),
+            (true, true) => "; flags unchanged".to_string(),
+            (true, false) => String::new(),
+        };
+        let mut addresses = instruction
+            .operands
+            .iter()
+            .filter_map(|operand| match operand {
+                Operand::RegisterIndirect(_) | Operand::RegisterOffsetIndirect(..) => {
+                    Some(format!("{} is [{:#06x}]", operand, self.address(operand)?))
+                }
+                _ => None,
+            })
+            .peekable();
+        let addresses = match addresses.peek() {
+            Some(_) => format!(", where {}", addresses.collect::<Vec<_>>().join(" and ")),
+            None => String::new(),
+        };
+        format!("{} → {}{}{}", head, body, addresses, flags)
+    }
+
+    /// What the instruction did, and whether it computed something that
+    /// sets the flags.
+    fn body(&self, instruction: &Instruction) -> (String, bool) {
+        let operands = &instruction.operands;
+        let mnemonic = instruction.mnemonic;
+        match instruction.kind {
+            InstructionKind::Halt => return ("halts the processor".to_string(), false),
+            InstructionKind::Jump => {
+                return (format!("jumps to {:#06x}", self.pc_after), false);
+            }
+            InstructionKind::Branch if self.pc_after != instruction.next_address() => {
+                return (
+                    format!("the condition holds, so it jumps to {:#06x}", self.pc_after),
+                    false,
+                );
+            }
+            InstructionKind::Branch => {
+                return (
+                    format!(
+                        "the condition doesn't hold, so execution goes on at {:#06x}",
+                        self.pc_after
+                    ),
+                    false,
+                );
+            }
+            InstructionKind::Call => {
+                return (
+                    format!(
+                        "calls {:#06x}, which will return to {:#06x}",
+                        self.pc_after,
+                        instruction.next_address()
+                    ),
+                    false,
+                );
+            }
+            InstructionKind::Return => {
+                return (format!("returns to {:#06x}", self.pc_after), false);
+            }
+            InstructionKind::Input | InstructionKind::Output => {
+                let access = self.ports.iter().map(|access| match access.input {
+                    true => format!("reads {} from port {}", access.value, access.port),
+                    false => format!("writes {} to port {}", access.value, access.port),
+                });
+                let mut parts: Vec<String> = access.collect();
+                parts.extend(self.effects());
+                return (self.join(parts, "does nothing"), false);
+            }
+            _ => {}
+        }
+
+        if let Some(symbol) = operator(mnemonic) {
+            if let Some(body) = self.binary(mnemonic, symbol, operands) {
+                return (body, true);
+            }
+        }
+        let unary = match mnemonic {
+            "not" => Some(("~", "")),
+            "inc" => Some(("", " + 1")),
+            "dec" => Some(("", " - 1")),
+            _ => None,
+        };
+        if let Some((prefix, suffix)) = unary {
+            let (destination, source) = match operands.as_slice() {
+                [] if self.stack_machine => (None, None),
+                [operand] => (Some(operand), Some(operand)),
+                [destination, source] => (Some(destination), Some(source)),
+                _ => return (self.join(self.effects(), "does nothing"), true),
+            };
+            let value = match source {
+                Some(source) => self.value(source),
+                None => Some(self.stack_before[0]),
+            };
+            if let (Some(value), Some(result)) = (value, self.result(destination)) {
+                return (
+                    format!(
+                        "{} = {}{}{} = {}",
+                        self.name(destination),
+                        prefix,
+                        value,
+                        suffix,
+                        result
+                    ),
+                    true,
+                );
+            }
+        }
+        match mnemonic {
+            "cmp" | "test" | "cmpe" | "cmpb" => {
+                if let Some(body) = self.compare(mnemonic, operands) {
+                    return (body, true);
+                }
+            }
+            "mov" | "load" | "store" if !self.stack_machine => {
+                let (destination, source) = match (mnemonic, operands.as_slice()) {
+                    (_, [destination, source]) => (*destination, *source),
+                    ("load", [source]) => (Operand::Register("ACC"), *source),
+                    ("store", [destination]) => (*destination, Operand::Register("ACC")),
+                    _ => return (self.join(self.effects(), "does nothing"), false),
+                };
+                if let Some(result) = self.result(Some(&destination)) {
+                    let body = match source {
+                        Operand::Register(_) | Operand::Immediate(_) => {
+                            format!("{} = {}", destination, result)
+                        }
+                        _ => format!("{} = {} = {}", destination, source, result),
+                    };
+                    return (body, false);
+                }
+            }
+            _ => {}
+        }
+        (self.join(self.effects(), "does nothing"), false)
+    }
+
+    /// `DEST = a op b = result`, with the inputs each instruction set uses.
+    fn binary(&self, mnemonic: &str, symbol: &str, operands: &[Operand]) -> Option<String> {
+        let shift = matches!(mnemonic, "lsh" | "rsh");
+        let (destination, left, right) = match operands {
+            [] if self.stack_machine => (None, self.stack_before[0], self.stack_before[1]),
+            [operand] if self.stack_machine => {
+                let immediate = self.value(operand)?;
+                match shift {
+                    true => (None, self.stack_before[0], immediate),
+                    false => (None, immediate, self.stack_before[0]),
+                }
+            }
+            [operand] => (
+                Some(Operand::Register("ACC")),
+                self.register(&self.registers_before, "ACC")?,
+                self.value(operand)?,
+            ),
+            [destination, source] => (
+                Some(*destination),
+                self.value(destination)?,
+                self.value(source)?,
+            ),
+            [destination, left, right] => {
+                (Some(*destination), self.value(left)?, self.value(right)?)
+            }
+            _ => return None,
+        };
+        let result = self.result(destination.as_ref())?;
+        Some(format!(
+            "{} = {} {} {} = {}",
+            self.name(destination.as_ref()),
+            left,
+            symbol,
+            right,
+            result
+        ))
+    }
+
+    fn compare(&self, mnemonic: &str, operands: &[Operand]) -> Option<String> {
+        if self.stack_machine {
+            let (left, right) = match operands {
+                [] => (self.stack_before[0], self.stack_before[1]),
+                [operand] => (self.value(operand)?, self.stack_before[0]),
+                _ => return None,
+            };
+            let symbol = if mnemonic == "cmpb" { ">" } else { "==" };
+            return Some(format!(
+                "{} = ({} {} {}) = {:#06x}",
+                TOP, left, symbol, right, self.stack_after
+            ));
+        }
+        let (left, right) = match operands {
+            [operand] => (
+                self.register(&self.registers_before, "ACC")?,
+                self.value(operand)?,
+            ),
+            [left, right] => (self.value(left)?, self.value(right)?),
+            _ => return None,
+        };
+        Some(match mnemonic {
+            "test" => format!("tests {} & {} = {}", left, right, left & right),
+            _ => format!("compares {} with {}", left, right),
+        })
+    }
+
+    fn join(&self, parts: Vec<String>, otherwise: &str) -> String {
+        match parts.is_empty() {
+            true => otherwise.to_string(),
+            false => parts.join(", "),
+        }
+    }
+
+    /// The registers, memory and stack the instruction changed. On stack
+    /// machines, values moving on and off the stack are told as pushes and
+    /// pops rather than as changes to `TOS` and the cells it points at.
+    fn effects(&self) -> Vec<String> {
+        let tos = (
+            self.register(&self.registers_before, "TOS"),
+            self.register(&self.registers_after, "TOS"),
+        );
+        let stack_cells = match tos {
+            (Some(before), Some(after)) if self.stack_machine => {
+                let word_cells = self
+                    .layout(self.operand_memory)
+                    .map_or(1, |layout| layout.word_cells);
+                before.min(after) as usize..before.max(after) as usize + word_cells
+            }
+            _ => 0..0,
+        };
+        let mut effects: Vec<String> = self
+            .registers_before
+            .iter()
+            .zip(&self.registers_after)
+            .filter(|(old, new)| {
+                old.value() != new.value()
+                    && !matches!(new.name().as_str(), "PC" | "FR")
+                    && !(self.stack_machine && new.name() == "TOS")
+            })
+            .map(|(old, new)| format!("{} = {} (was {})", new.name(), new.value(), old.value()))
+            .collect();
+        for (old, new) in self.memory_before.iter().zip(&self.memory_after) {
+            let Some(layout) = self.layout(new.memory_type) else {
+                continue;
+            };
+            let operands = new.memory_type == self.operand_memory;
+            let prefix = if operands { "" } else { "text" };
+            let mut address = 0;
+            while address < new.values.len() {
+                if old.values[address] == new.values[address]
+                    || operands && stack_cells.contains(&address)
+                {
+                    address += 1;
+                    continue;
+                }
+                // Words are aligned, so a write that only changes a word's
+                // low cell still shows the whole word.
+                let start = address - address % layout.word_cells;
+                let cells = start..(start + layout.word_cells).min(new.values.len());
+                effects.push(format!(
+                    "{}[{:#06x}] = {} (was {})",
+                    prefix,
+                    start,
+                    layout.combine(&new.values[cells.clone()]),
+                    layout.combine(&old.values[cells.clone()])
+                ));
+                address = cells.end;
+            }
+        }
+        if let (true, (Some(before), Some(after))) = (self.stack_machine, tos) {
+            let word_cells = self
+                .layout(self.operand_memory)
+                .map_or(1, |layout| layout.word_cells);
+            let popped = (i32::from(after) - i32::from(before)) / word_cells as i32;
+            effects.push(match popped {
+                0 if self.stack_after == self.stack_before[0] => return effects,
+                0 => format!("the {} is now {}", TOP, self.stack_after),
+                -1 => format!("pushes {}", self.stack_after),
+                1 => format!("pops {}", self.stack_before[0]),
+                2 => format!("pops {} and {}", self.stack_before[0], self.stack_before[1]),
+                popped if popped < 0 => {
+                    format!(
+                        "pushes {} values, leaving {} on top",
+                        -popped, self.stack_after
+                    )
+                }
+                popped => format!("pops {} values", popped),
+            });
+        }
+        effects
+    }
+
+    /// The flags the instruction set and cleared, such as `ZF set`.
+    fn flags(&self) -> Vec<String> {
+        let before = self.register(&self.registers_before, "FR").unwrap_or(0);
+        let after = self.register(&self.registers_after, "FR").unwrap_or(0);
+        FLAGS
+            .iter()
+            .filter(|(_, _, mask)| (before ^ after) & mask != 0)
+            .map(|(name, _, mask)| match after & mask {
+                0 => format!("{} cleared", name),
+                _ => format!("{} set", name),
+            })
+            .collect()
+    }
+
+    fn name(&self, destination: Option<&Operand>) -> String {
+        destination.map_or(TOP.to_string(), ToString::to_string)
+    }
+
+    /// The value the destination holds after the step.
+    fn result(&self, destination: Option<&Operand>) -> Option<u16> {
+        match destination {
+            None => Some(self.stack_after),
+            Some(Operand::Register(name)) => self.register(&self.registers_after, name),
+            Some(operand) => self.word(&self.memory_after, self.address(operand)?),
+        }
+    }
+
+    /// The value an operand stood for before the step.
+    fn value(&self, operand: &Operand) -> Option<u16> {
+        match operand {
+            Operand::Register(name) => self.register(&self.registers_before, name),
+            Operand::Immediate(value) | Operand::Target(value) | Operand::Port(value) => {
+                Some(*value)
+            }
+            Operand::RegisterOffset(name, offset) => self
+                .register(&self.registers_before, name)
+                .map(|value| value.wrapping_add(*offset)),
+            _ => self.word(&self.memory_before, self.address(operand)?),
+        }
+    }
+
+    /// Where a memory operand points, with the registers before the step.
+    fn address(&self, operand: &Operand) -> Option<u16> {
+        match operand {
+            Operand::Memory(address) => Some(*address),
+            Operand::RegisterIndirect(name) => self.register(&self.registers_before, name),
+            Operand::RegisterOffsetIndirect(name, offset) => self
+                .register(&self.registers_before, name)
+                .map(|value| value.wrapping_add(*offset)),
+            _ => None,
+        }
+    }
+
+    fn register(&self, registers: &[RegisterState], name: &str) -> Option<u16> {
+        registers
+            .iter()
+            .find(|register| register.name() == name)
+            .map(|register| register.value())
+    }
+
+    fn layout(&self, memory_type: MemoryType) -> Option<MemoryLayout> {
+        self.layouts
+            .iter()
+            .find(|(found, _)| *found == memory_type)
+            .map(|(_, layout)| *layout)
+    }
+
+    fn word(&self, memory: &[MemoryBlock], address: u16) -> Option<u16> {
+        let layout = self.layout(self.operand_memory)?;
+        let block = memory
+            .iter()
+            .find(|block| block.memory_type == self.operand_memory)?;
+        let start = address as usize;
+        Some(layout.combine(block.values.get(start..start + layout.word_cells)?))
+    }
+}
@@ -0,0 +1,320 @@
+//! A small expression language over the machine state, used for breakpoint
+//! conditions and watch expressions.
+//!
+//! Expressions work on 16-bit words with wrapping arithmetic. They know
+//! numbers (`42`, `0x2a`, `0b101010`), names (registers such as `PC` or
+//! `R01`, the flags `CF`, `ZF`, `OF` and `SF`, and symbols of the loaded
+//! executable), memory words (`[SP+2]` in data memory, or `data[0x10]` and
+//! `text[0x10]`), and C's operators with C's precedence. Comparisons and
+//! logical operators give 1 or 0.
+
+use crate::memory::MemoryType;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOperator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    BitOr,
+    BitXor,
+    BitAnd,
+    ShiftLeft,
+    ShiftRight,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnaryOperator {
+    Negate,
+    Not,
+    Complement,
+}
+
+#[derive(Clone)]
+pub enum Expression {
+    Number(u16),
+    Name(String),
+    /// A word of memory; `None` is data memory, or text memory on
+    /// processors without a separate data memory.
+    Memory(Option<MemoryType>, Box<Expression>),
+    Unary(UnaryOperator, Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+}
+
+/// What an expression needs to know about the machine.
+pub trait Environment {
+    /// The value of a register, flag or symbol.
+    fn name(&mut self, name: &str) -> Result<u16, String>;
+    fn read(&mut self, memory: Option<MemoryType>, address: u16) -> Result<u16, String>;
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Expression, String> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+        };
+        let expression = parser.binary(0)?;
+        match parser.peek() {
+            None => Ok(expression),
+            Some(token) => Err(format!("Unexpected {} in {:?}", token, source)),
+        }
+    }
+
+    pub fn evaluate(&self, environment: &mut dyn Environment) -> Result<u16, String> {
+        Ok(match self {
+            Expression::Number(value) => *value,
+            Expression::Name(name) => environment.name(name)?,
+            Expression::Memory(memory, address) => {
+                let address = address.evaluate(environment)?;
+                environment.read(*memory, address)?
+            }
+            Expression::Unary(operator, operand) => {
+                let value = operand.evaluate(environment)?;
+                match operator {
+                    UnaryOperator::Negate => value.wrapping_neg(),
+                    UnaryOperator::Not => u16::from(value == 0),
+                    UnaryOperator::Complement => !value,
+                }
+            }
+            Expression::Binary(BinaryOperator::And, left, right) => {
+                u16::from(left.evaluate(environment)? != 0 && right.evaluate(environment)? != 0)
+            }
+            Expression::Binary(BinaryOperator::Or, left, right) => {
+                u16::from(left.evaluate(environment)? != 0 || right.evaluate(environment)? != 0)
+            }
+            Expression::Binary(operator, left, right) => {
+                let left = left.evaluate(environment)?;
+                let right = right.evaluate(environment)?;
+                apply(*operator, left, right)?
+            }
+        })
+    }
+}
+
+fn apply(operator: BinaryOperator, left: u16, right: u16) -> Result<u16, String> {
+    Ok(match operator {
+        BinaryOperator::Equal => u16::from(left == right),
+        BinaryOperator::NotEqual => u16::from(left != right),
+        BinaryOperator::Less => u16::from(left < right),
+        BinaryOperator::LessOrEqual => u16::from(left <= right),
+        BinaryOperator::Greater => u16::from(left > right),
+        BinaryOperator::GreaterOrEqual => u16::from(left >= right),
+        BinaryOperator::BitOr => left | right,
+        BinaryOperator::BitXor => left ^ right,
+        BinaryOperator::BitAnd => left & right,
+        BinaryOperator::ShiftLeft => left.checked_shl(u32::from(right)).unwrap_or(0),
+        BinaryOperator::ShiftRight => left.checked_shr(u32::from(right)).unwrap_or(0),
+        BinaryOperator::Add => left.wrapping_add(right),
+        BinaryOperator::Subtract => left.wrapping_sub(right),
+        BinaryOperator::Multiply => left.wrapping_mul(right),
+        BinaryOperator::Divide => left.checked_div(right).ok_or("Division by zero")?,
+        BinaryOperator::Remainder => left.checked_rem(right).ok_or("Division by zero")?,
+        BinaryOperator::And | BinaryOperator::Or => unreachable!("evaluated lazily"),
+    })
+}
+
+/// The binary operators from the loosest binding to the tightest.
+const PRECEDENCE: &[&[(&str, BinaryOperator)]] = &[
+    &[("||", BinaryOperator::Or)],
+    &[("&&", BinaryOperator::And)],
+    &[("|", BinaryOperator::BitOr)],
+    &[("^", BinaryOperator::BitXor)],
+    &[("&", BinaryOperator::BitAnd)],
+    &[
+        ("==", BinaryOperator::Equal),
+        ("!=", BinaryOperator::NotEqual),
+    ],
+    &[
+        ("<", BinaryOperator::Less),
+        ("<=", BinaryOperator::LessOrEqual),
+        (">", BinaryOperator::Greater),
+        (">=", BinaryOperator::GreaterOrEqual),
+    ],
+    &[
+        ("<<", BinaryOperator::ShiftLeft),
+        (">>", BinaryOperator::ShiftRight),
+    ],
+    &[("+", BinaryOperator::Add), ("-", BinaryOperator::Subtract)],
+    &[
+        ("*", BinaryOperator::Multiply),
+        ("/", BinaryOperator::Divide),
+        ("%", BinaryOperator::Remainder),
+    ],
+];
+
+/// Every operator and bracket, longer ones first so that `<=` isn't read
+/// as `<` followed by `=`.
+const SYMBOLS: &[&str] = &[
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "|", "^", "&", "<", ">", "+", "-", "*", "/",
+    "%", "!", "~", "(", ")", "[", "]",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Number(u16),
+    Name(String),
+    Symbol(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "{}", value),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Symbol(symbol) => write!(f, "'{}'", symbol),
+        }
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$'
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if is_name_char(c) {
+            let end = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+            let word = &rest[..end];
+            tokens.push(if c.is_ascii_digit() {
+                Token::Number(number(word)?)
+            } else {
+                Token::Name(word.to_string())
+            });
+            rest = &rest[end..];
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(*symbol))
+                .ok_or_else(|| format!("Unexpected {:?} in {:?}", c, source))?;
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+fn number(word: &str) -> Result<u16, String> {
+    let lower = word.to_ascii_lowercase();
+    let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+        u16::from_str_radix(hex, 16)
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        u16::from_str_radix(binary, 2)
+    } else {
+        lower.parse()
+    };
+    parsed.map_err(|_| format!("{} isn't a 16-bit number", word))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(found)) if *found == symbol) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(match self.peek() {
+                Some(token) => format!("Expected '{}' but found {}", symbol, token),
+                None => format!("Expected '{}' at the end", symbol),
+            })
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expression, String> {
+        let Some(operators) = PRECEDENCE.get(level) else {
+            return self.unary();
+        };
+        let mut left = self.binary(level + 1)?;
+        while let Some(operator) = operators
+            .iter()
+            .find(
+                |(symbol, _)| matches!(self.peek(), Some(Token::Symbol(found)) if found == symbol),
+            )
+            .map(|(_, operator)| *operator)
+        {
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        let operator = if self.eat("-") {
+            UnaryOperator::Negate
+        } else if self.eat("!") {
+            UnaryOperator::Not
+        } else if self.eat("~") {
+            UnaryOperator::Complement
+        } else {
+            return self.primary();
+        };
+        Ok(Expression::Unary(operator, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Expression::Number(value)),
+            Some(Token::Name(name)) => {
+                let memory = match name.to_ascii_lowercase().as_str() {
+                    "data" => Some(MemoryType::Data),
+                    "text" => Some(MemoryType::Text),
+                    _ => None,
+                };
+                match memory {
+                    Some(memory) if self.eat("[") => self.memory(Some(memory)),
+                    _ => Ok(Expression::Name(name)),
+                }
+            }
+            Some(Token::Symbol("(")) => {
+                let expression = self.binary(0)?;
+                self.expect(")")?;
+                Ok(expression)
+            }
+            Some(Token::Symbol("[")) => self.memory(None),
+            Some(token) => Err(format!("Unexpected {}", token)),
+            None => Err("The expression ends too early".to_string()),
+        }
+    }
+
+    /// The rest of a memory access, after its opening bracket.
+    fn memory(&mut self, memory: Option<MemoryType>) -> Result<Expression, String> {
+        let address = self.binary(0)?;
+        self.expect("]")?;
+        Ok(Expression::Memory(memory, Box::new(address)))
+    }
+}
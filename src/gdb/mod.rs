@@ -0,0 +1,486 @@
+//! A GDB remote serial protocol stub, so that programs can be debugged with
+//! a stock `gdb` (`target remote`).
+//!
+//! `GdbStub` only turns bytes from GDB into bytes for GDB; it's wired up to
+//! a TCP socket by `serve_tcp` on native builds and to whatever message
+//! channel the page uses by `GdbChannel` on wasm.
+//!
+//! GDB sees a single address space: text memory at 0, and data memory, if
+//! the processor has one, at `DATA_BASE`. Every memory cell is one byte, so
+//! six-bit text cells of the stack processor read as bytes below 0x40.
+
+use wasm_bindgen::prelude::*;
+
+use crate::io::{JsPortIo, PortIo};
+use crate::memory::MemoryType;
+use crate::processor::WasmProcessorContinue;
+use crate::Runner;
+
+mod target;
+#[cfg(not(target_arch = "wasm32"))]
+mod tcp;
+
+pub use target::target_xml;
+#[cfg(not(target_arch = "wasm32"))]
+pub use tcp::serve_tcp;
+
+/// Where data memory starts in the address space GDB sees.
+pub const DATA_BASE: u32 = 0x10000;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGIO: u8 = 23;
+const SIGXCPU: u8 = 24;
+
+/// The protocol state of one GDB session.
+pub struct GdbStub {
+    runner: Runner,
+    incoming: Vec<u8>,
+    no_ack: bool,
+    running: bool,
+    exited: bool,
+    detached: bool,
+    /// Whether GDB understands stop replies naming a software breakpoint.
+    swbreak: bool,
+    last_stop: WasmProcessorContinue,
+}
+
+impl GdbStub {
+    pub fn new(runner: Runner) -> Self {
+        GdbStub {
+            runner,
+            incoming: Vec::new(),
+            no_ack: false,
+            running: false,
+            exited: false,
+            detached: false,
+            swbreak: false,
+            last_stop: WasmProcessorContinue::Continue,
+        }
+    }
+
+    pub fn runner(&self) -> &Runner {
+        &self.runner
+    }
+
+    pub fn into_runner(self) -> Runner {
+        self.runner
+    }
+
+    /// Whether GDB has told the program to continue and it hasn't stopped
+    /// yet; `poll` has to be called until it does.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Whether GDB has detached or killed the program, ending the session.
+    pub fn is_detached(&self) -> bool {
+        self.detached
+    }
+
+    /// How the program stopped the last time GDB stepped or continued it:
+    /// `Halt` once it has exited, and `Continue` if it never ran or has
+    /// only been stepped since.
+    pub fn last_stop(&self) -> WasmProcessorContinue {
+        self.last_stop
+    }
+
+    /// Takes bytes received from GDB and returns the bytes to send back.
+    pub fn receive(&mut self, bytes: &[u8], io: &mut dyn PortIo) -> Vec<u8> {
+        let mut reply = Vec::new();
+        for byte in bytes {
+            match (self.incoming.is_empty(), byte) {
+                // An interrupt, sent outside of any packet.
+                (true, 0x03) => {
+                    if self.running {
+                        self.running = false;
+                        self.last_stop = WasmProcessorContinue::Continue;
+                        reply.extend(frame(&stop_reply(SIGINT)));
+                    }
+                }
+                // Acknowledgements of our own packets; we never resend.
+                (true, b'+') | (true, b'-') => {}
+                (true, b'$') => self.incoming.push(*byte),
+                (true, _) => {}
+                (false, _) => {
+                    self.incoming.push(*byte);
+                    let length = self.incoming.len();
+                    if length >= 4 && self.incoming[length - 3] == b'#' {
+                        let packet = std::mem::take(&mut self.incoming);
+                        reply.extend(self.handle_framed(&packet, io));
+                    }
+                }
+            }
+        }
+        reply
+    }
+
+    /// Runs up to `max_steps` instructions of a continued program, returning
+    /// the stop reply once it stops and nothing while it keeps going.
+    pub fn poll(&mut self, io: &mut dyn PortIo, max_steps: usize) -> Vec<u8> {
+        if !self.running {
+            return Vec::new();
+        }
+        match self.runner.run_n_with(io, max_steps) {
+            WasmProcessorContinue::Continue => Vec::new(),
+            result => {
+                self.running = false;
+                self.last_stop = result;
+                frame(&self.result_reply(result))
+            }
+        }
+    }
+
+    fn handle_framed(&mut self, packet: &[u8], io: &mut dyn PortIo) -> Vec<u8> {
+        let body = &packet[1..packet.len() - 3];
+        let checksum = std::str::from_utf8(&packet[packet.len() - 2..])
+            .ok()
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+        if !self.no_ack && checksum != Some(sum(body)) {
+            return b"-".to_vec();
+        }
+        let mut reply = if self.no_ack {
+            Vec::new()
+        } else {
+            b"+".to_vec()
+        };
+        let body = String::from_utf8_lossy(body).into_owned();
+        if let Some(response) = self.handle(&body, io) {
+            reply.extend(frame(&response));
+        }
+        reply
+    }
+
+    /// Answers a single packet; `None` means there's nothing to send yet,
+    /// as after `c`.
+    fn handle(&mut self, packet: &str, io: &mut dyn PortIo) -> Option<String> {
+        let (command, arguments) = packet.split_at(packet.len().min(1));
+        let response = match command {
+            "?" => self.result_reply(WasmProcessorContinue::Continue),
+            "g" => self.read_registers(),
+            "G" => self.write_registers(arguments),
+            "p" => self.read_register(arguments),
+            "P" => self.write_register(arguments),
+            "m" => self.read_memory(arguments),
+            "M" => self.write_memory(arguments),
+            "s" | "c" => {
+                if self.exited {
+                    return Some("W00".to_string());
+                }
+                if !arguments.is_empty() {
+                    let moved = parse_hex(arguments)
+                        .filter(|address| *address < DATA_BASE)
+                        .map(|address| self.runner.set_register("PC", address as u16));
+                    if !matches!(moved, Some(Ok(()))) {
+                        return Some("E01".to_string());
+                    }
+                }
+                if command == "c" {
+                    self.running = true;
+                    return None;
+                }
+                let result = self.runner.run_with(io);
+                self.last_stop = result;
+                self.result_reply(result)
+            }
+            "Z" | "z" => self.breakpoint(command == "Z", arguments),
+            "D" => {
+                self.detached = true;
+                "OK".to_string()
+            }
+            "k" => {
+                self.detached = true;
+                return None;
+            }
+            "H" => "OK".to_string(),
+            _ => self.query(packet),
+        };
+        Some(response)
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if let Some(features) = packet.strip_prefix("qSupported") {
+            self.swbreak = features
+                .trim_start_matches(':')
+                .split(';')
+                .any(|feature| feature == "swbreak+");
+            return "PacketSize=4000;qXfer:features:read+;swbreak+;QStartNoAckMode+".to_string();
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let xml = target_xml(&self.runner.processor_type());
+            return match parse_pair(range) {
+                Some((offset, length)) => {
+                    let start = (offset as usize).min(xml.len());
+                    let end = start.saturating_add(length as usize).min(xml.len());
+                    let marker = if end == xml.len() { 'l' } else { 'm' };
+                    format!("{}{}", marker, &xml[start..end])
+                }
+                None => "E01".to_string(),
+            };
+        }
+        match packet {
+            "QStartNoAckMode" => {
+                self.no_ack = true;
+                "OK".to_string()
+            }
+            "qAttached" => "1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            "qC" => "QC1".to_string(),
+            "qSymbol::" => "OK".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn result_reply(&mut self, result: WasmProcessorContinue) -> String {
+        match result {
+            WasmProcessorContinue::Halt => {
+                self.exited = true;
+                "W00".to_string()
+            }
+            _ if self.exited => "W00".to_string(),
+            WasmProcessorContinue::Error => stop_reply(SIGILL),
+            WasmProcessorContinue::Timeout => stop_reply(SIGXCPU),
+            WasmProcessorContinue::IoError => stop_reply(SIGIO),
+            WasmProcessorContinue::Breakpoint if self.swbreak => {
+                format!("T{:02x}swbreak:;", SIGTRAP)
+            }
+            WasmProcessorContinue::Continue
+            | WasmProcessorContinue::Breakpoint
+            | WasmProcessorContinue::Catchpoint
+            | WasmProcessorContinue::Loop => stop_reply(SIGTRAP),
+        }
+    }
+
+    fn read_registers(&mut self) -> String {
+        self.runner
+            .get_registers()
+            .iter()
+            .map(|register| hex_le(register.value()))
+            .collect()
+    }
+
+    fn write_registers(&mut self, arguments: &str) -> String {
+        let names: Vec<String> = self
+            .runner
+            .get_registers()
+            .iter()
+            .map(|register| register.name())
+            .collect();
+        for (name, digits) in names.iter().zip(chunks(arguments, 4)) {
+            let written = parse_le(digits)
+                .ok_or_else(String::new)
+                .and_then(|value| self.runner.set_register(name, value));
+            if written.is_err() {
+                return "E01".to_string();
+            }
+        }
+        "OK".to_string()
+    }
+
+    fn read_register(&mut self, arguments: &str) -> String {
+        let registers = self.runner.get_registers();
+        match parse_hex(arguments).and_then(|number| registers.get(number as usize)) {
+            Some(register) => hex_le(register.value()),
+            None => "E01".to_string(),
+        }
+    }
+
+    fn write_register(&mut self, arguments: &str) -> String {
+        let Some((number, digits)) = arguments.split_once('=') else {
+            return "E01".to_string();
+        };
+        let registers = self.runner.get_registers();
+        let register = parse_hex(number).and_then(|number| registers.get(number as usize));
+        match (register, parse_le(digits)) {
+            (Some(register), Some(value)) => {
+                match self.runner.set_register(&register.name(), value) {
+                    Ok(()) => "OK".to_string(),
+                    Err(_) => "E01".to_string(),
+                }
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn read_memory(&mut self, arguments: &str) -> String {
+        let Some((address, length)) = parse_pair(arguments) else {
+            return "E01".to_string();
+        };
+        let Some((mem_type, address)) = self.locate(address) else {
+            return "E01".to_string();
+        };
+        match self.runner.read_cells(mem_type, address, length as usize) {
+            Ok(cells) => cells.iter().map(|cell| format!("{:02x}", cell)).collect(),
+            Err(_) => "E01".to_string(),
+        }
+    }
+
+    fn write_memory(&mut self, arguments: &str) -> String {
+        let Some((range, digits)) = arguments.split_once(':') else {
+            return "E01".to_string();
+        };
+        let Some((address, length)) = parse_pair(range) else {
+            return "E01".to_string();
+        };
+        let cells: Option<Vec<u8>> = chunks(digits, 2)
+            .map(|byte| u8::from_str_radix(byte, 16).ok())
+            .collect();
+        let (Some((mem_type, address)), Some(cells)) = (self.locate(address), cells) else {
+            return "E01".to_string();
+        };
+        if cells.len() != length as usize {
+            return "E01".to_string();
+        }
+        match self.runner.write_cells(mem_type, address, &cells) {
+            Ok(()) => "OK".to_string(),
+            Err(_) => "E01".to_string(),
+        }
+    }
+
+    fn breakpoint(&mut self, insert: bool, arguments: &str) -> String {
+        let mut fields = arguments.split(',');
+        let kind = fields.next();
+        let address = fields.next().and_then(parse_hex);
+        match (kind, address) {
+            // Software and hardware breakpoints work the same here.
+            (Some("0"), Some(address)) | (Some("1"), Some(address)) => {
+                if address >= DATA_BASE {
+                    return "E01".to_string();
+                }
+                if insert {
+                    self.runner.add_breakpoint(address as u16);
+                } else {
+                    self.runner.remove_breakpoint(address as u16);
+                }
+                "OK".to_string()
+            }
+            // Watchpoints aren't supported.
+            _ => String::new(),
+        }
+    }
+
+    /// Splits a GDB address into a memory and an address within it.
+    fn locate(&self, address: u32) -> Option<(MemoryType, usize)> {
+        if address < DATA_BASE {
+            Some((MemoryType::Text, address as usize))
+        } else {
+            let address = (address - DATA_BASE) as usize;
+            self.runner
+                .processor
+                .memory_layout(MemoryType::Data)
+                .map(|_| (MemoryType::Data, address))
+        }
+    }
+}
+
+fn sum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn frame(body: &str) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(body.len() + 4);
+    for byte in body.bytes() {
+        if matches!(byte, b'$' | b'#' | b'}' | b'*') {
+            escaped.push(b'}');
+            escaped.push(byte ^ 0x20);
+        } else {
+            escaped.push(byte);
+        }
+    }
+    let mut packet = vec![b'$'];
+    packet.extend(&escaped);
+    packet.extend(format!("#{:02x}", sum(&escaped)).bytes());
+    packet
+}
+
+fn stop_reply(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+fn hex_le(value: u16) -> String {
+    value
+        .to_le_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn parse_le(digits: &str) -> Option<u16> {
+    if digits.len() != 4 {
+        return None;
+    }
+    let low = u8::from_str_radix(&digits[..2], 16).ok()?;
+    let high = u8::from_str_radix(&digits[2..], 16).ok()?;
+    Some(u16::from_le_bytes([low, high]))
+}
+
+fn parse_hex(digits: &str) -> Option<u32> {
+    u32::from_str_radix(digits, 16).ok()
+}
+
+fn parse_pair(arguments: &str) -> Option<(u32, u32)> {
+    let (first, second) = arguments.split_once(',')?;
+    Some((parse_hex(first)?, parse_hex(second)?))
+}
+
+fn chunks(digits: &str, size: usize) -> impl Iterator<Item = &str> {
+    (0..digits.len() / size).map(move |index| &digits[index * size..(index + 1) * size])
+}
+
+/// A GDB session for JS, which passes the bytes between GDB and the stub
+/// over whatever channel it has, such as a WebSocket to a proxy.
+#[wasm_bindgen]
+pub struct GdbChannel {
+    stub: GdbStub,
+}
+
+#[wasm_bindgen]
+impl GdbChannel {
+    #[wasm_bindgen(constructor)]
+    pub fn new(runner: Runner) -> GdbChannel {
+        GdbChannel {
+            stub: GdbStub::new(runner),
+        }
+    }
+
+    /// Takes bytes received from GDB and returns the bytes to send back.
+    #[wasm_bindgen]
+    pub fn receive(
+        &mut self,
+        bytes: &[u8],
+        output: &js_sys::Function,
+        input: &js_sys::Function,
+    ) -> Vec<u8> {
+        self.stub.receive(bytes, &mut JsPortIo::new(output, input))
+    }
+
+    /// While the program runs, call this regularly to run it for a while;
+    /// whatever it returns goes to GDB.
+    #[wasm_bindgen]
+    pub fn poll(
+        &mut self,
+        output: &js_sys::Function,
+        input: &js_sys::Function,
+        max_steps: usize,
+    ) -> Vec<u8> {
+        self.stub.poll(&mut JsPortIo::new(output, input), max_steps)
+    }
+
+    #[wasm_bindgen]
+    pub fn is_running(&self) -> bool {
+        self.stub.is_running()
+    }
+
+    #[wasm_bindgen]
+    pub fn is_detached(&self) -> bool {
+        self.stub.is_detached()
+    }
+
+    /// Ends the session, handing the runner back.
+    #[wasm_bindgen]
+    pub fn into_runner(self) -> Runner {
+        self.stub.into_runner()
+    }
+}
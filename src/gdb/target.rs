@@ -0,0 +1,49 @@
+use crate::processors::{create_processor, ProcessorType};
+
+fn feature_name(processor_type: &ProcessorType) -> &'static str {
+    match processor_type {
+        ProcessorType::Stack => "org.monistode.stack",
+        ProcessorType::Acc => "org.monistode.acc",
+        ProcessorType::Risc => "org.monistode.risc",
+        ProcessorType::Cisc => "org.monistode.cisc",
+    }
+}
+
+/// The names of the registers in the order of the `g` packet, which is
+/// the order `get_registers` lists them in.
+pub fn register_names(processor_type: &ProcessorType) -> Vec<String> {
+    create_processor(processor_type.clone())
+        .get_registers()
+        .iter()
+        .map(|register| register.name())
+        .collect()
+}
+
+/// The target description GDB asks for with `qXfer:features:read`. Every
+/// register is 16 bits wide.
+pub fn target_xml(processor_type: &ProcessorType) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n",
+    );
+    xml.push_str(&format!(
+        "  <feature name=\"{}\">\n",
+        feature_name(processor_type)
+    ));
+    for (number, name) in register_names(processor_type).iter().enumerate() {
+        let register_type = match name.as_str() {
+            "PC" => "code_ptr",
+            "SP" | "BP" | "TOS" => "data_ptr",
+            _ => "uint16",
+        };
+        xml.push_str(&format!(
+            "    <reg name=\"{}\" bitsize=\"16\" type=\"{}\" regnum=\"{}\"/>\n",
+            name.to_lowercase(),
+            register_type,
+            number
+        ));
+    }
+    xml.push_str("  </feature>\n</target>\n");
+    xml
+}
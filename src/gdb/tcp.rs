@@ -0,0 +1,52 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+
+use super::GdbStub;
+use crate::io::PortIo;
+use crate::Runner;
+
+/// How many instructions a continued program runs between checks for an
+/// interrupt from GDB.
+const STEPS_PER_POLL: usize = 10_000;
+
+/// Waits for GDB to connect to `address` and serves that one session until
+/// GDB detaches or disconnects. Returns the stub, which holds the runner in
+/// its final state and how the program last stopped.
+pub fn serve_tcp<A: ToSocketAddrs>(
+    runner: Runner,
+    address: A,
+    io: &mut dyn PortIo,
+) -> io::Result<GdbStub> {
+    let listener = TcpListener::bind(address)?;
+    let (mut stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    let mut stub = GdbStub::new(runner);
+    let mut buffer = [0; 4096];
+    while !stub.is_detached() {
+        let received = if stub.is_running() {
+            // Only look for an interrupt, without blocking the program.
+            stream.set_nonblocking(true)?;
+            let received = match stream.read(&mut buffer) {
+                Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(None),
+                other => other.map(Some),
+            };
+            stream.set_nonblocking(false)?;
+            match received? {
+                Some(count) => count,
+                None => {
+                    let reply = stub.poll(io, STEPS_PER_POLL);
+                    stream.write_all(&reply)?;
+                    continue;
+                }
+            }
+        } else {
+            stream.read(&mut buffer)?
+        };
+        if received == 0 {
+            break;
+        }
+        let reply = stub.receive(&buffer[..received], io);
+        stream.write_all(&reply)?;
+    }
+    Ok(stub)
+}
@@ -0,0 +1,368 @@
+//! Runs an executable against a set of test cases, for grading submissions.
+
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+use crate::io::BufferedIo;
+use crate::memory::MemoryType;
+use crate::processor::WasmProcessorContinue;
+use crate::processors::detect_processor_type;
+use crate::Runner;
+
+/// How many instructions a case may run when it doesn't set a limit.
+const DEFAULT_MAX_STEPS: usize = 1_000_000;
+
+struct MemoryRange {
+    mem_type: MemoryType,
+    address: usize,
+    cells: Vec<u8>,
+}
+
+impl MemoryRange {
+    fn location(&self, offset: usize) -> String {
+        let memory = match self.mem_type {
+            MemoryType::Text => "text",
+            MemoryType::Data => "data",
+        };
+        format!("{} memory at {:#06x}", memory, self.address + offset)
+    }
+}
+
+/// The setup for one run of the program and what it should end up doing.
+/// Ports that have no expected output aren't checked.
+#[wasm_bindgen]
+pub struct TestCase {
+    name: String,
+    max_steps: usize,
+    inputs: Vec<(u16, Vec<u16>)>,
+    memory: Vec<MemoryRange>,
+    registers: Vec<(String, u16)>,
+    expected_outputs: Vec<(u16, Vec<u16>)>,
+    expected_registers: Vec<(String, u16)>,
+    expected_memory: Vec<MemoryRange>,
+}
+
+#[wasm_bindgen]
+impl TestCase {
+    #[wasm_bindgen(constructor)]
+    pub fn new(name: &str) -> TestCase {
+        TestCase {
+            name: name.to_string(),
+            max_steps: DEFAULT_MAX_STEPS,
+            inputs: Vec::new(),
+            memory: Vec::new(),
+            registers: Vec::new(),
+            expected_outputs: Vec::new(),
+            expected_registers: Vec::new(),
+            expected_memory: Vec::new(),
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// The program fails the case if it hasn't halted after this many
+    /// instructions.
+    #[wasm_bindgen]
+    pub fn set_max_steps(&mut self, max_steps: usize) {
+        self.max_steps = max_steps;
+    }
+
+    /// Queues values for the program to read from `port`; once they run
+    /// out, the port reads 0.
+    #[wasm_bindgen]
+    pub fn queue_input(&mut self, port: u16, values: Vec<u16>) {
+        self.inputs.push((port, values));
+    }
+
+    /// Writes cells into memory after the program is loaded.
+    #[wasm_bindgen]
+    pub fn preset_memory(&mut self, mem_type: MemoryType, address: usize, cells: Vec<u8>) {
+        self.memory.push(MemoryRange {
+            mem_type,
+            address,
+            cells,
+        });
+    }
+
+    /// Sets a register after the program is loaded.
+    #[wasm_bindgen]
+    pub fn preset_register(&mut self, name: &str, value: u16) {
+        self.registers.push((name.to_string(), value));
+    }
+
+    /// Expects exactly these values to be written to `port`, in order.
+    /// Calling this again for the same port extends the expected values.
+    #[wasm_bindgen]
+    pub fn expect_output(&mut self, port: u16, values: Vec<u16>) {
+        match self.expected_outputs.iter_mut().find(|(p, _)| *p == port) {
+            Some((_, expected)) => expected.extend(values),
+            None => self.expected_outputs.push((port, values)),
+        }
+    }
+
+    /// Expects a register to hold `value` once the program halts.
+    #[wasm_bindgen]
+    pub fn expect_register(&mut self, name: &str, value: u16) {
+        self.expected_registers.push((name.to_string(), value));
+    }
+
+    /// Expects memory to hold `cells` from `address` on once the program
+    /// halts.
+    #[wasm_bindgen]
+    pub fn expect_memory(&mut self, mem_type: MemoryType, address: usize, cells: Vec<u8>) {
+        self.expected_memory.push(MemoryRange {
+            mem_type,
+            address,
+            cells,
+        });
+    }
+}
+
+/// The first difference between what a case expected and what happened.
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    location: String,
+    expected: String,
+    actual: String,
+}
+
+#[wasm_bindgen]
+impl Mismatch {
+    /// What was checked, such as `port 1 output 3` or `register R00`.
+    #[wasm_bindgen]
+    pub fn location(&self) -> String {
+        self.location.clone()
+    }
+
+    #[wasm_bindgen]
+    pub fn expected(&self) -> String {
+        self.expected.clone()
+    }
+
+    #[wasm_bindgen]
+    pub fn actual(&self) -> String {
+        self.actual.clone()
+    }
+
+    /// The mismatch as a single line of text.
+    #[wasm_bindgen]
+    pub fn describe(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: expected {}, got {}",
+            self.location, self.expected, self.actual
+        )
+    }
+}
+
+fn mismatch(location: String, expected: String, actual: String) -> Mismatch {
+    Mismatch {
+        location,
+        expected,
+        actual,
+    }
+}
+
+#[wasm_bindgen]
+pub struct TestResult {
+    name: String,
+    status: WasmProcessorContinue,
+    outputs: Vec<(u16, u16)>,
+    mismatch: Option<Mismatch>,
+}
+
+#[wasm_bindgen]
+impl TestResult {
+    #[wasm_bindgen]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[wasm_bindgen]
+    pub fn passed(&self) -> bool {
+        self.mismatch.is_none()
+    }
+
+    /// How the run ended; `Timeout` means the step limit ran out.
+    #[wasm_bindgen]
+    pub fn status(&self) -> WasmProcessorContinue {
+        self.status
+    }
+
+    /// The values written to `port` during the run.
+    #[wasm_bindgen]
+    pub fn outputs_on(&self, port: u16) -> Vec<u16> {
+        self.outputs
+            .iter()
+            .filter(|(written, _)| *written == port)
+            .map(|(_, value)| *value)
+            .collect()
+    }
+
+    #[wasm_bindgen]
+    pub fn mismatch(&self) -> Option<Mismatch> {
+        self.mismatch.clone()
+    }
+}
+
+/// Runs every case from the state right after loading `executable`. Fails
+/// only if the executable can't be loaded; a case whose presets can't be
+/// applied fails on its own.
+#[wasm_bindgen]
+pub fn run_tests(executable: &[u8], cases: Vec<TestCase>) -> Result<Vec<TestResult>, String> {
+    let mut runner = Runner::new(detect_processor_type(executable)?);
+    runner.load_program(executable)?;
+    let mut results = Vec::with_capacity(cases.len());
+    for case in &cases {
+        runner.reset()?;
+        results.push(run_case(&mut runner, case));
+    }
+    Ok(results)
+}
+
+fn run_case(runner: &mut Runner, case: &TestCase) -> TestResult {
+    let mut io = BufferedIo::new();
+    for (port, values) in &case.inputs {
+        io.queue_input(*port, values.iter().copied());
+    }
+    let status = match preset(runner, case) {
+        Ok(()) => run_to_halt(runner, &mut io, case.max_steps),
+        Err(error) => {
+            return TestResult {
+                name: case.name.clone(),
+                status: WasmProcessorContinue::Error,
+                outputs: Vec::new(),
+                mismatch: Some(mismatch(
+                    "setup".to_string(),
+                    "presets to apply".to_string(),
+                    error,
+                )),
+            }
+        }
+    };
+    let mismatch = check(runner, &io, case, status).err();
+    TestResult {
+        name: case.name.clone(),
+        status,
+        outputs: io.outputs().to_vec(),
+        mismatch,
+    }
+}
+
+fn preset(runner: &mut Runner, case: &TestCase) -> Result<(), String> {
+    for range in &case.memory {
+        runner.write_cells(range.mem_type, range.address, &range.cells)?;
+    }
+    for (name, value) in &case.registers {
+        runner.set_register(name, *value)?;
+    }
+    Ok(())
+}
+
+/// Breakpoints don't apply to graded runs, so only halts, errors and the
+/// step budget end them.
+fn run_to_halt(
+    runner: &mut Runner,
+    io: &mut BufferedIo,
+    max_steps: usize,
+) -> WasmProcessorContinue {
+    runner.set_step_budget(Some(max_steps as u64));
+    loop {
+        match runner.run_with(io) {
+            WasmProcessorContinue::Continue
+            | WasmProcessorContinue::Breakpoint
+            | WasmProcessorContinue::Catchpoint => {}
+            status => return status,
+        }
+    }
+}
+
+fn check(
+    runner: &mut Runner,
+    io: &BufferedIo,
+    case: &TestCase,
+    status: WasmProcessorContinue,
+) -> Result<(), Mismatch> {
+    if status != WasmProcessorContinue::Halt {
+        let actual = match status {
+            WasmProcessorContinue::Timeout => {
+                format!("still running after {} steps", case.max_steps)
+            }
+            _ => format!("an error at pc {:#06x}", runner.pc()),
+        };
+        return Err(mismatch("run".to_string(), "a halt".to_string(), actual));
+    }
+
+    for (port, expected) in &case.expected_outputs {
+        let actual = io.outputs_on(*port);
+        if let Some(index) = (0..expected.len().max(actual.len()))
+            .find(|index| expected.get(*index) != actual.get(*index))
+        {
+            let value = |values: &[u16]| {
+                values
+                    .get(index)
+                    .map_or_else(|| "nothing".to_string(), |value| format!("{:#06x}", value))
+            };
+            return Err(mismatch(
+                format!("port {} output {}", port, index),
+                value(expected),
+                value(&actual),
+            ));
+        }
+    }
+
+    let registers = runner.get_registers();
+    for (name, expected) in &case.expected_registers {
+        let actual = registers.iter().find(|register| register.name() == *name);
+        match actual {
+            Some(register) if register.value() == *expected => {}
+            Some(register) => {
+                return Err(mismatch(
+                    format!("register {}", name),
+                    format!("{:#06x}", expected),
+                    format!("{:#06x}", register.value()),
+                ))
+            }
+            None => {
+                return Err(mismatch(
+                    format!("register {}", name),
+                    format!("{:#06x}", expected),
+                    "no such register".to_string(),
+                ))
+            }
+        }
+    }
+
+    for range in &case.expected_memory {
+        let actual = runner
+            .read_cells(range.mem_type, range.address, range.cells.len())
+            .map_err(|error| {
+                mismatch(
+                    range.location(0),
+                    format!("{} cells", range.cells.len()),
+                    error,
+                )
+            })?;
+        if let Some(offset) =
+            (0..actual.len()).find(|offset| actual[*offset] != range.cells[*offset])
+        {
+            return Err(mismatch(
+                range.location(offset),
+                format!("{:#04x}", range.cells[offset]),
+                format!("{:#04x}", actual[offset]),
+            ));
+        }
+    }
+    Ok(())
+}
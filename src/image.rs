@@ -0,0 +1,398 @@
+//! Memory images in formats other than binutils executables: raw flat
+//! binaries, Intel HEX and Motorola S-records, and writing memory back out in
+//! those formats or as an executable. Every byte of an image is one memory
+//! cell, so on memories with cells narrower than 8 bits, such as the stack
+//! processor's 6-bit text memory, each byte must fit into a cell.
+
+use bitvec::vec::BitVec;
+use monistode_binutils::executable::segments::flags::SegmentFlags;
+use monistode_binutils::executable::Segment;
+use monistode_binutils::{Address, Architecture, Executable, Serializable, Symbol};
+use wasm_bindgen::prelude::*;
+
+use crate::memory::{MemoryLayout, MemoryType};
+
+/// How many cells go into one Intel HEX or S-record data record.
+const RECORD_CELLS: usize = 16;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// The cells one after another, starting at `ImageOptions::base`.
+    Flat,
+    IntelHex,
+    SRecord,
+    /// A binutils executable, as `Runner::load_program` takes.
+    Executable,
+}
+
+/// How `Runner::load_image` places an image.
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct ImageOptions {
+    memory_type: MemoryType,
+    base: usize,
+    entry_point: Option<u16>,
+    keep_memory: bool,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        ImageOptions {
+            memory_type: MemoryType::Text,
+            base: 0,
+            entry_point: None,
+            keep_memory: false,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl ImageOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> ImageOptions {
+        Self::default()
+    }
+
+    /// The memory the image goes into; text memory unless set.
+    #[wasm_bindgen]
+    pub fn set_memory_type(&mut self, memory_type: MemoryType) {
+        self.memory_type = memory_type;
+    }
+
+    /// Where a flat image starts. Intel HEX and S-records carry their own
+    /// addresses.
+    #[wasm_bindgen]
+    pub fn set_base(&mut self, base: usize) {
+        self.base = base;
+    }
+
+    /// Starts the program at `pc`, instead of at the start address the
+    /// image gives, if any.
+    #[wasm_bindgen]
+    pub fn set_entry_point(&mut self, pc: u16) {
+        self.entry_point = Some(pc);
+    }
+
+    /// Writes the image over the machine as it is, registers included,
+    /// instead of into a fresh one, such as a data image after the
+    /// program's text.
+    #[wasm_bindgen]
+    pub fn set_keep_memory(&mut self, keep_memory: bool) {
+        self.keep_memory = keep_memory;
+    }
+
+    #[wasm_bindgen]
+    pub fn memory_type(&self) -> MemoryType {
+        self.memory_type
+    }
+
+    #[wasm_bindgen]
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    #[wasm_bindgen]
+    pub fn entry_point(&self) -> Option<u16> {
+        self.entry_point
+    }
+
+    #[wasm_bindgen]
+    pub fn keep_memory(&self) -> bool {
+        self.keep_memory
+    }
+}
+
+/// The cells of an image, by the address of the first of each run, and the
+/// start address it gives.
+pub struct Image {
+    pub chunks: Vec<(usize, Vec<u8>)>,
+    pub entry_point: Option<u64>,
+}
+
+impl Image {
+    pub fn parse(format: ImageFormat, bytes: &[u8], base: usize) -> Result<Image, String> {
+        match format {
+            ImageFormat::Flat => Ok(Image {
+                chunks: vec![(base, bytes.to_vec())],
+                entry_point: None,
+            }),
+            ImageFormat::IntelHex => intel_hex(text(bytes)?),
+            ImageFormat::SRecord => s_records(text(bytes)?),
+            ImageFormat::Executable => {
+                Err("Executables are loaded with Runner::load_program".to_string())
+            }
+        }
+    }
+}
+
+fn text(bytes: &[u8]) -> Result<&str, String> {
+    std::str::from_utf8(bytes).map_err(|_| "The image isn't text".to_string())
+}
+
+/// The bytes of a record written as pairs of hex digits.
+fn hex_bytes(digits: &str) -> Result<Vec<u8>, String> {
+    if digits.len() % 2 == 1 || !digits.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("{:?} isn't a sequence of hex bytes", digits));
+    }
+    Ok((0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+        .collect())
+}
+
+fn big_endian(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0, |value, byte| value << 8 | u64::from(*byte))
+}
+
+fn intel_hex(source: &str) -> Result<Image, String> {
+    let mut image = Image {
+        chunks: Vec::new(),
+        entry_point: None,
+    };
+    let mut upper = 0usize;
+    for (number, line) in source
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+    {
+        if line.is_empty() {
+            continue;
+        }
+        let record = line
+            .strip_prefix(':')
+            .ok_or_else(|| format!("Line {}: a record starts with ':'", number))
+            .and_then(hex_bytes)
+            .map_err(|message| format!("Line {}: {}", number, message))?;
+        if record.len() < 5 || record.len() != usize::from(record[0]) + 5 {
+            return Err(format!("Line {}: the record's length is wrong", number));
+        }
+        if record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(format!("Line {}: the checksum is wrong", number));
+        }
+        let address = usize::from(record[1]) << 8 | usize::from(record[2]);
+        let data = &record[4..record.len() - 1];
+        match (record[3], data.len()) {
+            (0x00, _) => image.chunks.push((upper + address, data.to_vec())),
+            (0x01, _) => return Ok(image),
+            (0x02, 2) => upper = (big_endian(data) as usize) << 4,
+            (0x03, 4) => {
+                let (segment, offset) = (big_endian(&data[..2]), big_endian(&data[2..]));
+                image.entry_point = Some(segment << 4 | offset);
+            }
+            (0x04, 2) => upper = (big_endian(data) as usize) << 16,
+            (0x05, 4) => image.entry_point = Some(big_endian(data)),
+            (kind @ 0x02..=0x05, _) => {
+                return Err(format!(
+                    "Line {}: a record of type {:02X} holds the wrong number of bytes",
+                    number, kind
+                ))
+            }
+            (kind, _) => return Err(format!("Line {}: unknown record type {:02X}", number, kind)),
+        }
+    }
+    Err("The Intel HEX data ends without an end-of-file record".to_string())
+}
+
+fn s_records(source: &str) -> Result<Image, String> {
+    let mut image = Image {
+        chunks: Vec::new(),
+        entry_point: None,
+    };
+    for (number, line) in source
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+    {
+        if line.is_empty() {
+            continue;
+        }
+        let mut chars = line.chars();
+        let kind = match (chars.next(), chars.next()) {
+            (Some('S'), Some(kind)) if kind.is_ascii_digit() => kind,
+            _ => {
+                return Err(format!(
+                    "Line {}: a record starts with S and a digit",
+                    number
+                ))
+            }
+        };
+        let record =
+            hex_bytes(chars.as_str()).map_err(|message| format!("Line {}: {}", number, message))?;
+        if record.is_empty() || record.len() != usize::from(record[0]) + 1 {
+            return Err(format!("Line {}: the record's length is wrong", number));
+        }
+        let sum = record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        if sum != 0xff {
+            return Err(format!("Line {}: the checksum is wrong", number));
+        }
+        let address_bytes = match kind {
+            '0' | '1' | '5' | '9' => 2,
+            '2' | '6' | '8' => 3,
+            '3' | '7' => 4,
+            _ => return Err(format!("Line {}: unknown record type S{}", number, kind)),
+        };
+        if record.len() < address_bytes + 2 {
+            return Err(format!("Line {}: the record is too short", number));
+        }
+        let address = big_endian(&record[1..=address_bytes]);
+        let data = &record[address_bytes + 1..record.len() - 1];
+        match kind {
+            '1' | '2' | '3' => image.chunks.push((address as usize, data.to_vec())),
+            '7' | '8' | '9' => image.entry_point = Some(address),
+            _ => {}
+        }
+    }
+    Ok(image)
+}
+
+fn hex_line(prefix: &str, bytes: &[u8]) -> String {
+    let mut line = prefix.to_string();
+    for byte in bytes {
+        line += &format!("{:02X}", byte);
+    }
+    line + "\n"
+}
+
+/// Writes `cells`, starting at `start`, as Intel HEX, with a start address
+/// record if there is an entry point.
+pub fn write_intel_hex(start: usize, cells: &[u8], entry_point: Option<u16>) -> String {
+    let record = |kind: u8, address: u16, data: &[u8]| {
+        let mut bytes = vec![data.len() as u8, (address >> 8) as u8, address as u8, kind];
+        bytes.extend(data);
+        bytes.push(bytes.iter().fold(0u8, |sum, byte| sum.wrapping_sub(*byte)));
+        hex_line(":", &bytes)
+    };
+    let mut text = String::new();
+    for (i, chunk) in cells.chunks(RECORD_CELLS).enumerate() {
+        text += &record(0x00, (start + i * RECORD_CELLS) as u16, chunk);
+    }
+    if let Some(pc) = entry_point {
+        text += &record(0x05, 0, &u32::from(pc).to_be_bytes());
+    }
+    text + &record(0x01, 0, &[])
+}
+
+/// Writes `cells`, starting at `start`, as S1 records, ending with an S9
+/// record holding the entry point if there is one.
+pub fn write_s_records(start: usize, cells: &[u8], entry_point: Option<u16>) -> String {
+    let record = |kind: char, address: u16, data: &[u8]| {
+        let mut bytes = vec![data.len() as u8 + 3, (address >> 8) as u8, address as u8];
+        bytes.extend(data);
+        bytes.push(!bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)));
+        hex_line(&format!("S{}", kind), &bytes)
+    };
+    let mut text = String::new();
+    for (i, chunk) in cells.chunks(RECORD_CELLS).enumerate() {
+        text += &record('1', (start + i * RECORD_CELLS) as u16, chunk);
+    }
+    if let Some(pc) = entry_point {
+        text += &record('9', pc, &[]);
+    }
+    text
+}
+
+/// The cells of a memory from `start` on, to go into one executable
+/// segment.
+pub struct SegmentCells {
+    pub memory_type: MemoryType,
+    pub layout: MemoryLayout,
+    pub start: usize,
+    pub cells: Vec<u8>,
+}
+
+impl SegmentCells {
+    fn holds(&self, memory_type: MemoryType, address: u16) -> bool {
+        self.memory_type == memory_type
+            && (self.start..self.start + self.cells.len()).contains(&usize::from(address))
+    }
+
+    /// A segment holding `cells` and the `symbols` of its memory that fall
+    /// inside.
+    fn segment(self, symbols: &[(MemoryType, String, u16)]) -> Segment {
+        let SegmentCells {
+            memory_type,
+            layout,
+            start,
+            cells,
+        } = self;
+        // The stack processor copies data segments as the bytes binutils
+        // writes to disk, which hold each bit from the lowest up, while text
+        // cells are read from the highest bit down.
+        let text = memory_type == MemoryType::Text;
+        let mut data = BitVec::new();
+        for cell in &cells {
+            for bit in 0..layout.cell_bits {
+                let bit = if text {
+                    layout.cell_bits - 1 - bit
+                } else {
+                    bit
+                };
+                data.push(cell >> bit & 1 == 1);
+            }
+        }
+        let symbols = symbols
+            .iter()
+            .filter(|(mem_type, _, address)| {
+                *mem_type == memory_type
+                    && (start..start + cells.len()).contains(&usize::from(*address))
+            })
+            .map(|(_, name, address)| Symbol {
+                name: name.clone(),
+                address: Address((usize::from(*address) - start) * layout.cell_bits as usize),
+            })
+            .collect();
+        let flags = SegmentFlags {
+            executable: text,
+            writable: !text,
+            readable: true,
+            special: false,
+        };
+        Segment::new(
+            start as u64,
+            cells.len() as u64,
+            data.len(),
+            flags,
+            data,
+            symbols,
+        )
+    }
+}
+
+/// Writes an executable with a segment for each of `segments`, holding the
+/// `symbols` that fall inside it. binutils can't read an executable without
+/// symbols back, so if none fall inside, the entry point is named `_start`;
+/// that fails if no text segment holds it either.
+pub fn write_executable(
+    architecture: Architecture,
+    segments: Vec<SegmentCells>,
+    symbols: &[(MemoryType, String, u16)],
+    entry_point: u16,
+) -> Result<Vec<u8>, String> {
+    let inside = |memory_type, address| {
+        segments
+            .iter()
+            .any(|cells| cells.holds(memory_type, address))
+    };
+    let mut symbols = symbols.to_vec();
+    if !symbols
+        .iter()
+        .any(|(memory_type, _, address)| inside(*memory_type, *address))
+    {
+        if !inside(MemoryType::Text, entry_point) {
+            return Err("An executable needs a symbol, and none fall inside the range".to_string());
+        }
+        symbols.push((MemoryType::Text, "_start".to_string(), entry_point));
+    }
+    let segments = segments
+        .into_iter()
+        .map(|cells| cells.segment(&symbols))
+        .collect();
+    let mut bytes = Executable::new(architecture, segments).serialize();
+    // `Executable::new` always starts at 0, so the entry point goes straight
+    // into the header, after the architecture and the segment count.
+    bytes[9..17].copy_from_slice(&u64::from(entry_point).to_le_bytes());
+    Ok(bytes)
+}
@@ -0,0 +1,230 @@
+//! Reading an executable's header, segments and symbols without loading it,
+//! so that front ends can show what a file holds before running it.
+
+use monistode_binutils::executable::Segment;
+use monistode_binutils::{Executable, Serializable};
+use wasm_bindgen::prelude::*;
+
+use crate::memory::{MemoryLayout, MemoryType};
+use crate::panics;
+use crate::processors::{create_processor, ProcessorType};
+use crate::symbols::SymbolTable;
+
+/// Deserializes an executable, turning the panics binutils raises on some
+/// malformed files into errors.
+pub(crate) fn parse_executable(program: &[u8]) -> Result<Executable, String> {
+    panics::catch(|| Executable::deserialize(program))
+        .ok()
+        .and_then(Result::ok)
+        .map(|(_, executable)| executable)
+        .ok_or_else(|| "Failed to load executable".to_string())
+}
+
+/// The memory a segment is loaded into: executable segments go into text
+/// memory, the rest into data memory if the processor has a separate one.
+pub(crate) fn segment_memory(segment: &Segment, has_data: bool) -> MemoryType {
+    match has_data && !segment.flags.executable {
+        true => MemoryType::Data,
+        false => MemoryType::Text,
+    }
+}
+
+/// Makes sure a segment lands inside memory and holds as many cells as it
+/// claims, which the emulator's loaders take for granted.
+pub(crate) fn check_segment(segment: &Segment, layout: MemoryLayout) -> Result<(), String> {
+    let start = segment.address_space_start;
+    let size = segment.address_space_size;
+    if !matches!(start.checked_add(size), Some(end) if end <= layout.size as u64) {
+        return Err(format!(
+            "The segment at {:#x} with {} cells doesn't fit into memory of size {:#x}",
+            start, size, layout.size
+        ));
+    }
+    if (segment.data.len() as u64) < size * u64::from(layout.cell_bits) {
+        return Err(format!(
+            "The segment at {:#x} holds less data than its {} cells",
+            start, size
+        ));
+    }
+    Ok(())
+}
+
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct SegmentInfo {
+    address: u64,
+    size: u64,
+    memory_type: MemoryType,
+    executable: bool,
+    writable: bool,
+    readable: bool,
+}
+
+#[wasm_bindgen]
+impl SegmentInfo {
+    /// The first cell the segment is loaded at.
+    #[wasm_bindgen]
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    /// How many cells the segment takes up.
+    #[wasm_bindgen]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The memory the segment is loaded into.
+    #[wasm_bindgen]
+    pub fn memory_type(&self) -> MemoryType {
+        self.memory_type
+    }
+
+    #[wasm_bindgen]
+    pub fn is_executable(&self) -> bool {
+        self.executable
+    }
+
+    #[wasm_bindgen]
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    #[wasm_bindgen]
+    pub fn is_readable(&self) -> bool {
+        self.readable
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct SymbolInfo {
+    name: String,
+    memory_type: MemoryType,
+    address: u16,
+}
+
+#[wasm_bindgen]
+impl SymbolInfo {
+    #[wasm_bindgen]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[wasm_bindgen]
+    pub fn memory_type(&self) -> MemoryType {
+        self.memory_type
+    }
+
+    /// The cell the symbol names, in its memory.
+    #[wasm_bindgen]
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+}
+
+#[wasm_bindgen]
+pub struct ExecutableInfo {
+    processor_type: ProcessorType,
+    entry_point: u64,
+    segments: Vec<SegmentInfo>,
+    symbols: Vec<SymbolInfo>,
+    problems: Vec<String>,
+}
+
+#[wasm_bindgen]
+impl ExecutableInfo {
+    /// The processor the executable was built for.
+    #[wasm_bindgen]
+    pub fn processor_type(&self) -> ProcessorType {
+        self.processor_type.clone()
+    }
+
+    #[wasm_bindgen]
+    pub fn entry_point(&self) -> u64 {
+        self.entry_point
+    }
+
+    #[wasm_bindgen]
+    pub fn segments(&self) -> Vec<SegmentInfo> {
+        self.segments.clone()
+    }
+
+    /// The symbols, by address.
+    #[wasm_bindgen]
+    pub fn symbols(&self) -> Vec<SymbolInfo> {
+        self.symbols.clone()
+    }
+
+    /// Whether the executable fits the default memory of the processor it
+    /// was built for.
+    #[wasm_bindgen]
+    pub fn fits(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Why the executable doesn't fit: segments that fall outside memory or
+    /// are cut short, and an entry point outside text memory.
+    #[wasm_bindgen]
+    pub fn problems(&self) -> Vec<String> {
+        self.problems.clone()
+    }
+}
+
+/// Reads an executable's architecture, segments, entry point and symbols,
+/// and checks it against the memory of the processor it was built for.
+#[wasm_bindgen]
+pub fn inspect_executable(program: &[u8]) -> Result<ExecutableInfo, String> {
+    let executable = parse_executable(program)?;
+    let processor_type = ProcessorType::from(executable.architecture());
+    let processor = create_processor(processor_type.clone());
+    let has_data = processor.memory_layout(MemoryType::Data).is_some();
+
+    let mut problems = Vec::new();
+    let segments = executable
+        .segments()
+        .iter()
+        .map(|segment| {
+            let memory_type = segment_memory(segment, has_data);
+            if let Some(layout) = processor.memory_layout(memory_type) {
+                if let Err(problem) = check_segment(segment, layout) {
+                    problems.push(problem);
+                }
+            }
+            SegmentInfo {
+                address: segment.address_space_start,
+                size: segment.address_space_size,
+                memory_type,
+                executable: segment.flags.executable,
+                writable: segment.flags.writable,
+                readable: segment.flags.readable,
+            }
+        })
+        .collect();
+    let entry_point = executable.entry_point();
+    if let Some(text) = processor.memory_layout(MemoryType::Text) {
+        if entry_point >= text.size as u64 {
+            problems.push(format!(
+                "The entry point {:#x} is outside of text memory of size {:#x}",
+                entry_point, text.size
+            ));
+        }
+    }
+    let symbols =
+        SymbolTable::from_executable(&executable, |mem_type| processor.memory_layout(mem_type))
+            .entries()
+            .iter()
+            .map(|entry| SymbolInfo {
+                name: entry.name.clone(),
+                memory_type: entry.memory_type,
+                address: entry.address,
+            })
+            .collect();
+    Ok(ExecutableInfo {
+        processor_type,
+        entry_point,
+        segments,
+        symbols,
+        problems,
+    })
+}
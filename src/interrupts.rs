@@ -0,0 +1,152 @@
+//! Interrupt lines and a timer that raises one of them, delivered by the
+//! runner between instructions.
+
+/// How many interrupt lines there are; line 0 has the highest priority.
+pub const INTERRUPT_LINES: u8 = 8;
+
+struct Timer {
+    period: u32,
+    line: u8,
+    remaining: u32,
+}
+
+/// The handler that is running, and the stack pointer from before the
+/// interrupt pushed its return address.
+struct InService {
+    line: u8,
+    stack_pointer: u16,
+}
+
+/// Latches raised lines until they are delivered or cleared. An interrupt
+/// is only delivered while interrupts are enabled and no handler is
+/// running, so handlers never nest.
+#[derive(Default)]
+pub struct InterruptController {
+    vectors: [Option<u16>; INTERRUPT_LINES as usize],
+    pending: u8,
+    disabled: bool,
+    in_service: Option<InService>,
+    timer: Option<Timer>,
+}
+
+fn check_line(line: u8) -> Result<(), String> {
+    if line < INTERRUPT_LINES {
+        Ok(())
+    } else {
+        Err(format!(
+            "There is no interrupt line {}, only lines 0 to {}",
+            line,
+            INTERRUPT_LINES - 1
+        ))
+    }
+}
+
+impl InterruptController {
+    pub fn set_vector(&mut self, line: u8, address: u16) -> Result<(), String> {
+        check_line(line)?;
+        self.vectors[line as usize] = Some(address);
+        Ok(())
+    }
+
+    fn check_vector(&self, line: u8) -> Result<(), String> {
+        check_line(line)?;
+        match self.vectors[line as usize] {
+            Some(_) => Ok(()),
+            None => Err(format!("Interrupt line {} has no vector", line)),
+        }
+    }
+
+    pub fn raise(&mut self, line: u8) -> Result<(), String> {
+        self.check_vector(line)?;
+        self.pending |= 1 << line;
+        Ok(())
+    }
+
+    pub fn clear(&mut self, line: u8) -> Result<(), String> {
+        check_line(line)?;
+        self.pending &= !(1 << line);
+        Ok(())
+    }
+
+    pub fn pending(&self) -> Vec<u8> {
+        (0..INTERRUPT_LINES)
+            .filter(|line| self.pending & 1 << line != 0)
+            .collect()
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.disabled = !enabled;
+    }
+
+    pub fn in_service(&self) -> Option<u8> {
+        self.in_service.as_ref().map(|service| service.line)
+    }
+
+    pub fn set_timer(&mut self, period: u32, line: u8) -> Result<(), String> {
+        self.check_vector(line)?;
+        if period == 0 {
+            return Err("The timer period must be at least one instruction".to_string());
+        }
+        self.timer = Some(Timer {
+            period,
+            line,
+            remaining: period,
+        });
+        Ok(())
+    }
+
+    pub fn stop_timer(&mut self) {
+        self.timer = None;
+    }
+
+    /// Whether an interrupt may still change the course of the program, in
+    /// which case a repeated state doesn't mean it's stuck.
+    pub fn armed(&self) -> bool {
+        !self.disabled && (self.timer.is_some() || self.pending != 0)
+    }
+
+    /// Counts an instruction that has just run against the timer period.
+    pub fn tick(&mut self) {
+        if let Some(timer) = &mut self.timer {
+            timer.remaining -= 1;
+            if timer.remaining == 0 {
+                timer.remaining = timer.period;
+                self.pending |= 1 << timer.line;
+            }
+        }
+    }
+
+    /// Takes the interrupt to deliver next, if one can be, marking it in
+    /// service. Returns its line and vector.
+    pub fn take(&mut self, stack_pointer: u16) -> Option<(u8, u16)> {
+        if self.disabled || self.in_service.is_some() || self.pending == 0 {
+            return None;
+        }
+        let line = self.pending.trailing_zeros() as u8;
+        let vector = self.vectors[line as usize]?;
+        self.pending &= !(1 << line);
+        self.in_service = Some(InService {
+            line,
+            stack_pointer,
+        });
+        Some((line, vector))
+    }
+
+    /// Ends the handler once a return has popped its return address, given
+    /// the stack pointer the return left behind.
+    pub fn returned(&mut self, stack_pointer: u16) {
+        if matches!(&self.in_service, Some(service) if service.stack_pointer == stack_pointer) {
+            self.in_service = None;
+        }
+    }
+
+    /// Forgets raised lines and the running handler, and starts the timer
+    /// period over. Vectors and the timer setting stay.
+    pub fn reset(&mut self) {
+        self.pending = 0;
+        self.in_service = None;
+        if let Some(timer) = &mut self.timer {
+            timer.remaining = timer.period;
+        }
+    }
+}
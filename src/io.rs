@@ -0,0 +1,284 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use wasm_bindgen::prelude::*;
+
+/// Where the processor's IN and OUT instructions go.
+pub trait PortIo {
+    fn output(&mut self, port: u16, value: u16);
+    fn input(&mut self, port: u16) -> u16;
+    /// The access that failed during the last instruction, if any. The
+    /// runner takes it after every instruction and stops with `IoError`.
+    fn take_failure(&mut self) -> Option<IoFailure> {
+        None
+    }
+}
+
+/// Ports backed by memory: every port reads from its own queue, giving 0
+/// once the queue runs dry, and writes are recorded in order.
+#[derive(Clone, Debug, Default)]
+pub struct BufferedIo {
+    inputs: BTreeMap<u16, VecDeque<u16>>,
+    outputs: Vec<(u16, u16)>,
+}
+
+impl BufferedIo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends values for the program to read from `port`.
+    pub fn queue_input<I: IntoIterator<Item = u16>>(&mut self, port: u16, values: I) {
+        self.inputs.entry(port).or_default().extend(values);
+    }
+
+    /// Every write so far, as `(port, value)` pairs.
+    pub fn outputs(&self) -> &[(u16, u16)] {
+        &self.outputs
+    }
+
+    /// The values written to a single port.
+    pub fn outputs_on(&self, port: u16) -> Vec<u16> {
+        self.outputs
+            .iter()
+            .filter(|(written, _)| *written == port)
+            .map(|(_, value)| *value)
+            .collect()
+    }
+}
+
+impl PortIo for BufferedIo {
+    fn output(&mut self, port: u16, value: u16) {
+        self.outputs.push((port, value));
+    }
+
+    fn input(&mut self, port: u16) -> u16 {
+        self.inputs
+            .get_mut(&port)
+            .and_then(|queue| queue.pop_front())
+            .unwrap_or(0)
+    }
+}
+
+/// A value that went through a port.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PortAccess {
+    pub(crate) port: u16,
+    pub(crate) input: bool,
+    pub(crate) value: u16,
+}
+
+#[wasm_bindgen]
+impl PortAccess {
+    #[wasm_bindgen]
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Whether it was an IN rather than an OUT.
+    #[wasm_bindgen]
+    pub fn is_input(&self) -> bool {
+        self.input
+    }
+
+    #[wasm_bindgen]
+    pub fn value(&self) -> u16 {
+        self.value
+    }
+}
+
+/// Passes port accesses on to `inner`, keeping a record of them.
+pub(crate) struct PortRecorder<'a> {
+    pub inner: &'a mut dyn PortIo,
+    pub accesses: Vec<PortAccess>,
+}
+
+impl PortIo for PortRecorder<'_> {
+    fn output(&mut self, port: u16, value: u16) {
+        self.accesses.push(PortAccess {
+            port,
+            input: false,
+            value,
+        });
+        self.inner.output(port, value);
+    }
+
+    fn input(&mut self, port: u16) -> u16 {
+        let value = self.inner.input(port);
+        self.accesses.push(PortAccess {
+            port,
+            input: true,
+            value,
+        });
+        value
+    }
+
+    fn take_failure(&mut self) -> Option<IoFailure> {
+        self.inner.take_failure()
+    }
+}
+
+/// A port access the host couldn't complete: a callback threw, or the input
+/// callback returned something that isn't a port value. The instruction
+/// has run regardless, reading 0 in place of a bad input.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct IoFailure {
+    /// Kept out of Rust-made failures, since native builds can't clone a
+    /// `JsValue`.
+    exception: Option<JsValue>,
+    message: String,
+    port: u16,
+    input: bool,
+    pub(crate) pc: u16,
+}
+
+#[wasm_bindgen]
+impl IoFailure {
+    /// What the callback threw, or the value the input callback returned;
+    /// `undefined` for failures a Rust `PortIo` reported.
+    #[wasm_bindgen]
+    pub fn exception(&self) -> JsValue {
+        self.exception.clone().unwrap_or(JsValue::UNDEFINED)
+    }
+
+    #[wasm_bindgen]
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+
+    #[wasm_bindgen]
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Whether the failed access was an IN rather than an OUT.
+    #[wasm_bindgen]
+    pub fn is_input(&self) -> bool {
+        self.input
+    }
+
+    /// The address of the instruction that accessed the port.
+    #[wasm_bindgen]
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+}
+
+impl IoFailure {
+    /// A failed access for a `PortIo` to hand out from `take_failure`. The
+    /// runner fills in the address of the instruction.
+    pub fn new(message: impl Into<String>, port: u16, input: bool) -> Self {
+        IoFailure {
+            exception: None,
+            message: message.into(),
+            port,
+            input,
+            pc: 0,
+        }
+    }
+}
+
+/// Checks a number the input callback returned: it has to be an integer
+/// from 0 to 65535 to be read from a port.
+pub(crate) fn input_value(value: f64) -> Result<u16, String> {
+    if value.fract() == 0.0 && (0.0..=f64::from(u16::MAX)).contains(&value) {
+        Ok(value as u16)
+    } else {
+        Err(format!("{} isn't an integer from 0 to 65535", value))
+    }
+}
+
+/// Forwards port accesses to a pair of JS callbacks, `output(port, value)`
+/// and `input(port)`. For a core of a `System` the core's index is passed
+/// as an extra last argument. The first access that fails is kept for the
+/// runner to stop at.
+pub(crate) struct JsPortIo<'a> {
+    output: &'a js_sys::Function,
+    input: &'a js_sys::Function,
+    core: Option<usize>,
+    failure: Option<IoFailure>,
+}
+
+impl<'a> JsPortIo<'a> {
+    pub fn new(output: &'a js_sys::Function, input: &'a js_sys::Function) -> Self {
+        JsPortIo {
+            output,
+            input,
+            core: None,
+            failure: None,
+        }
+    }
+
+    pub fn for_core(
+        output: &'a js_sys::Function,
+        input: &'a js_sys::Function,
+        core: usize,
+    ) -> Self {
+        JsPortIo {
+            core: Some(core),
+            ..JsPortIo::new(output, input)
+        }
+    }
+
+    fn fail(&mut self, exception: JsValue, message: String, port: u16, input: bool) {
+        if self.failure.is_none() {
+            self.failure = Some(IoFailure {
+                exception: Some(exception),
+                ..IoFailure::new(message, port, input)
+            });
+        }
+    }
+}
+
+impl PortIo for JsPortIo<'_> {
+    fn output(&mut self, port: u16, value: u16) {
+        let port_value = JsValue::from_f64(port as f64);
+        let value = JsValue::from_f64(value as f64);
+        let result = match self.core {
+            None => self.output.call2(&JsValue::NULL, &port_value, &value),
+            Some(core) => self.output.call3(
+                &JsValue::NULL,
+                &port_value,
+                &value,
+                &JsValue::from_f64(core as f64),
+            ),
+        };
+        if let Err(exception) = result {
+            let message = format!("The output callback threw writing to port {}", port);
+            self.fail(exception, message, port, false);
+        }
+    }
+
+    fn input(&mut self, port: u16) -> u16 {
+        let port_value = JsValue::from_f64(port as f64);
+        let result = match self.core {
+            None => self.input.call1(&JsValue::NULL, &port_value),
+            Some(core) => {
+                self.input
+                    .call2(&JsValue::NULL, &port_value, &JsValue::from_f64(core as f64))
+            }
+        };
+        let value = match result {
+            Ok(value) => value,
+            Err(exception) => {
+                let message = format!("The input callback threw reading port {}", port);
+                self.fail(exception, message, port, true);
+                return 0;
+            }
+        };
+        let checked = match value.as_f64() {
+            Some(number) => input_value(number),
+            None => Err(format!("{:?} isn't a number", value)),
+        };
+        checked.unwrap_or_else(|reason| {
+            let message = format!("The input callback for port {} returned {}", port, reason);
+            self.fail(value, message, port, true);
+            0
+        })
+    }
+
+    fn take_failure(&mut self) -> Option<IoFailure> {
+        self.failure.take()
+    }
+}
@@ -1,34 +1,408 @@
 mod utils;
-use std::u8;
 
-use crate::memory::{MemoryBlock, MemoryType};
-use crate::processor::{WasmProcessor, WasmProcessorContinue};
-use processors::{create_processor, ProcessorType};
-use registers::RegisterState;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use crate::breakpoints::Breakpoint;
+use crate::call_stack::{CallFrame, CallStack};
+use crate::catchpoints::{Catchpoint, Catchpoints, Step, Watched};
+use crate::config::{memory_name, ConfiguredProcessor};
+use crate::decoder::{Instruction, InstructionKind, Operand};
+use crate::explain::StepRecord;
+use crate::expression::{Environment, Expression};
+use crate::image::{write_executable, write_intel_hex, write_s_records, Image, SegmentCells};
+use crate::inspect::{check_segment, parse_executable, segment_memory};
+use crate::interrupts::InterruptController;
+use crate::io::{JsPortIo, PortRecorder};
+use crate::logpoints::{Log, Template};
+use crate::memory::{MemoryBlock, MemoryLayout};
+use crate::processor::WasmProcessor;
+use crate::processors::FLAGS;
+use crate::snapshot::MachineState;
+use crate::symbols::SymbolTable;
+use crate::watchdog::Watchdog;
+use monistode_binutils::Executable;
+use processors::create_processor;
+use stack::{stack_address, stack_effects, StackFrame};
 use wasm_bindgen::prelude::*;
 
-pub use processors::available_processors;
+pub use catchpoints::CatchEvent;
+pub use config::ProcessorConfig;
+pub use grading::{run_tests, Mismatch, TestCase, TestResult};
+pub use image::{ImageFormat, ImageOptions};
+pub use inspect::{inspect_executable, ExecutableInfo, SegmentInfo, SymbolInfo};
+pub use io::{BufferedIo, IoFailure, PortAccess, PortIo};
+pub use logpoints::LogEntry;
+pub use memory::MemoryType;
+pub use processor::WasmProcessorContinue;
+pub use processors::{
+    available_processors, detect_processor_type, FlagInfo, MemoryInfo, OpcodeEntry,
+    ProcessorMetadata, ProcessorType, RegisterInfo,
+};
+pub use registers::RegisterState;
+pub use step_info::{MemoryAccess, RegisterChange, StepInfo};
+pub use system::{SchedulePolicy, System};
+mod breakpoints;
+mod call_stack;
+mod catchpoints;
+mod config;
+#[cfg(feature = "dap")]
+pub mod dap;
+mod decoder;
+mod explain;
+mod expression;
+#[cfg(feature = "gdb")]
+pub mod gdb;
+mod grading;
+mod image;
+mod inspect;
+mod interrupts;
+mod io;
+mod logpoints;
 mod memory;
+mod panics;
 mod processor;
 mod processors;
 mod registers;
+mod snapshot;
+mod stack;
+mod step_info;
+mod symbols;
+mod system;
+mod watchdog;
+
+/// The words an instruction reads and writes, by memory and the address of
+/// their first cell, as worked out just before it runs.
+#[derive(Default)]
+struct Accesses {
+    reads: Vec<(MemoryType, u16)>,
+    writes: Vec<(MemoryType, u16)>,
+}
+
+/// The addresses the memory operands of `instruction` refer to, with
+/// registers as they are in `registers`, along with each operand's position.
+fn operand_addresses(instruction: &Instruction, registers: &[RegisterState]) -> Vec<(usize, u16)> {
+    let register = |name: &str| {
+        registers
+            .iter()
+            .find(|register| register.name() == name)
+            .map(|register| register.value())
+    };
+    instruction
+        .operands
+        .iter()
+        .enumerate()
+        .filter_map(|(index, operand)| match operand {
+            Operand::Memory(address) => Some((index, *address)),
+            Operand::RegisterIndirect(name) => register(name).map(|value| (index, value)),
+            Operand::RegisterOffsetIndirect(name, offset) => {
+                register(name).map(|value| (index, value.wrapping_add(*offset)))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Whether `instruction` reads and whether it writes the memory operand at
+/// `index`. Only a first operand is ever a destination: `store`, `mov`,
+/// `in` and `pop` overwrite it, and arithmetic works on it in place. The
+/// accumulator's arithmetic reads its memory operand into ACC instead.
+fn operand_use(accumulator: bool, instruction: &Instruction, index: usize) -> (bool, bool) {
+    match (index, instruction.mnemonic) {
+        (0, "store") => (false, true),
+        _ if accumulator => (true, false),
+        (0, "mov") | (0, "in") | (0, "pop") => (false, true),
+        (0, "add")
+        | (0, "sub")
+        | (0, "mul")
+        | (0, "div")
+        | (0, "and")
+        | (0, "or")
+        | (0, "xor")
+        | (0, "not")
+        | (0, "inc")
+        | (0, "dec")
+        | (0, "lsh")
+        | (0, "rsh") => (true, true),
+        _ => (true, false),
+    }
+}
 
 #[wasm_bindgen]
 pub struct Runner {
+    processor_type: ProcessorType,
     processor: Box<dyn WasmProcessor>,
+    symbols: SymbolTable,
+    call_stack: CallStack,
+    breakpoints: BTreeMap<u16, Breakpoint>,
+    watchdog: Watchdog,
+    /// The state right after `load_program`, for `reset`.
+    pristine: Option<MachineState>,
+    config: Option<ProcessorConfig>,
+    interrupts: InterruptController,
+    /// Why the last step failed, when the runner itself noticed.
+    fault: Option<String>,
+    /// Set once the emulator has panicked, after which nothing runs until
+    /// the program is loaded or reset again.
+    crashed: bool,
+    /// The port access the last step stopped at.
+    io_failure: Option<IoFailure>,
+    logpoints: BTreeMap<u16, Template>,
+    log: Log,
+    catchpoints: Catchpoints,
+    /// The catchpoint the last step stopped at.
+    caught: Option<CatchEvent>,
+    /// The instruction the last step ran, if it got that far.
+    executed: Option<Instruction>,
+    /// The last step made with `step`, for `explain_last_step`.
+    last_step: Option<StepRecord>,
+    /// Set by `step_with` while it steps, so that the words the instruction
+    /// accesses are kept in `accesses`.
+    noting_accesses: bool,
+    accesses: Option<Accesses>,
+    /// Set while the PC is where loading or resetting put it, so that the
+    /// breakpoint and logpoint there are checked before the first step.
+    at_entry: bool,
 }
 
 #[wasm_bindgen]
 impl Runner {
     #[wasm_bindgen(constructor)]
     pub fn new(processor_type: ProcessorType) -> Self {
-        let processor = create_processor(processor_type);
-        Runner { processor }
+        utils::set_panic_hook();
+        let processor = create_processor(processor_type.clone());
+        Runner {
+            processor_type,
+            processor,
+            symbols: SymbolTable::default(),
+            call_stack: CallStack::default(),
+            breakpoints: BTreeMap::new(),
+            watchdog: Watchdog::default(),
+            pristine: None,
+            config: None,
+            interrupts: InterruptController::default(),
+            fault: None,
+            crashed: false,
+            io_failure: None,
+            logpoints: BTreeMap::new(),
+            log: Log::default(),
+            catchpoints: Catchpoints::default(),
+            caught: None,
+            executed: None,
+            last_step: None,
+            noting_accesses: false,
+            accesses: None,
+            at_entry: true,
+        }
+    }
+
+    /// A runner whose processor is held to the limits of `config`. Fails if
+    /// the limits don't fit the processor.
+    #[wasm_bindgen]
+    pub fn with_config(
+        processor_type: ProcessorType,
+        config: &ProcessorConfig,
+    ) -> Result<Runner, String> {
+        let mut runner = Runner::new(processor_type.clone());
+        runner.processor = Box::new(ConfiguredProcessor::new(processor_type, config.clone())?);
+        runner.config = Some(config.clone());
+        Ok(runner)
+    }
+
+    #[wasm_bindgen]
+    pub fn processor_type(&self) -> ProcessorType {
+        self.processor_type.clone()
     }
 
+    /// Loads an executable. Segments that don't fit into memory are
+    /// rejected before anything is written; should the emulator panic
+    /// anyway in a native build, the runner is left with empty memory and
+    /// no program.
     #[wasm_bindgen]
     pub fn load_program(&mut self, program: &[u8]) -> Result<(), String> {
-        self.processor.load_executable(program)
+        let executable = parse_executable(program)?;
+        self.check_segments(&executable)?;
+        let processor = &mut self.processor;
+        match panics::catch(|| processor.load_executable(&executable)) {
+            Ok(loaded) => loaded?,
+            Err(message) => {
+                self.processor = self.fresh_processor();
+                self.symbols = SymbolTable::default();
+                self.pristine = None;
+                return Err(format!("The emulator panicked while loading: {}", message));
+            }
+        }
+        self.symbols = SymbolTable::from_executable(&executable, |mem_type| {
+            self.processor.memory_layout(mem_type)
+        });
+        self.loaded();
+        Ok(())
+    }
+
+    /// Loads a memory image: a flat binary, Intel HEX or S-records, or an
+    /// executable the way `load_program` does. Each byte of the image is one
+    /// cell, and has to fit into it. Unless `options` say to keep memory,
+    /// the image goes into empty memory and the symbols are dropped. The
+    /// image is checked against memory before anything is written.
+    #[wasm_bindgen]
+    pub fn load_image(
+        &mut self,
+        format: ImageFormat,
+        bytes: &[u8],
+        options: &ImageOptions,
+    ) -> Result<(), String> {
+        if format == ImageFormat::Executable {
+            return self.load_program(bytes);
+        }
+        let image = Image::parse(format, bytes, options.base())?;
+        let mem_type = options.memory_type();
+        let layout = self
+            .processor
+            .memory_layout(mem_type)
+            .ok_or_else(|| format!("This processor has no {} memory", memory_name(mem_type)))?;
+        for (address, cells) in &image.chunks {
+            layout.check_range(*address, cells.len())?;
+            if let Some(offset) = cells.iter().position(|cell| *cell > layout.max_cell()) {
+                return Err(format!(
+                    "The value {:#x} at {:#x} doesn't fit into a {}-bit cell",
+                    cells[offset],
+                    address + offset,
+                    layout.cell_bits
+                ));
+            }
+        }
+        let entry_point = match options.entry_point() {
+            Some(pc) => Some(pc),
+            None => image
+                .entry_point
+                .map(|pc| {
+                    u16::try_from(pc)
+                        .map_err(|_| format!("The start address {:#x} isn't a 16-bit address", pc))
+                })
+                .transpose()?,
+        };
+        let text = self.memory_layout(MemoryType::Text)?;
+        if let Some(pc) = entry_point.filter(|pc| usize::from(*pc) >= text.size) {
+            return Err(format!(
+                "The entry point {:#06x} is outside the {}-cell text memory",
+                pc, text.size
+            ));
+        }
+
+        if !options.keep_memory() {
+            self.processor = self.fresh_processor();
+            self.symbols = SymbolTable::default();
+        }
+        for (address, cells) in image.chunks {
+            for (offset, cell) in cells.into_iter().enumerate() {
+                self.processor.set_memory(mem_type, address + offset, cell);
+            }
+        }
+        if let Some(pc) = entry_point {
+            self.set_register("PC", pc)?;
+        }
+        self.loaded();
+        Ok(())
+    }
+
+    /// Writes the cells from `start` up to `end` of a memory out as an
+    /// image, to save a program patched in the debugger. Text memory in
+    /// Intel HEX and S-records, and executables, start at the PC the program
+    /// was loaded with. Executables keep the symbols inside the range, also
+    /// hold a separate memory of the other kind from its first cell that
+    /// isn't zero to its last, and can be loaded back with `load_program`.
+    #[wasm_bindgen]
+    pub fn export_image(
+        &self,
+        format: ImageFormat,
+        mem_type: MemoryType,
+        start: usize,
+        end: usize,
+    ) -> Result<Vec<u8>, String> {
+        if end <= start {
+            return Err(format!("The range {:#x}..{:#x} is empty", start, end));
+        }
+        let layout = self.memory_layout(mem_type)?;
+        let cells = self.read_cells(mem_type, start, end - start)?;
+        let entry_point = self
+            .pristine
+            .as_ref()
+            .and_then(MachineState::pc)
+            .unwrap_or_else(|| self.processor.pc());
+        let text_entry = Some(entry_point).filter(|_| mem_type == MemoryType::Text);
+        Ok(match format {
+            ImageFormat::Flat => cells,
+            ImageFormat::IntelHex => write_intel_hex(start, &cells, text_entry).into_bytes(),
+            ImageFormat::SRecord => write_s_records(start, &cells, text_entry).into_bytes(),
+            ImageFormat::Executable => {
+                let architecture = self
+                    .processor_type
+                    .architecture()
+                    .ok_or("This processor has no executable format")?;
+                let symbols: Vec<_> = self
+                    .symbols
+                    .entries()
+                    .iter()
+                    .map(|entry| (entry.memory_type, entry.name.clone(), entry.address))
+                    .collect();
+                let mut segments = vec![SegmentCells {
+                    memory_type: mem_type,
+                    layout,
+                    start,
+                    cells,
+                }];
+                if let Some(other) = self.used_cells(match mem_type {
+                    MemoryType::Text => MemoryType::Data,
+                    MemoryType::Data => MemoryType::Text,
+                })? {
+                    segments.push(other);
+                }
+                segments.sort_by_key(|segment| segment.memory_type == MemoryType::Data);
+                write_executable(architecture, segments, &symbols, entry_point)?
+            }
+        })
+    }
+
+    /// Puts memory and registers back the way `load_program` left them, and
+    /// starts the call stack and the watchdog over. This also recovers from
+    /// a panic in the emulator. Raised interrupts are dropped; breakpoints,
+    /// vectors and the timer stay, with breakpoint hit counts starting over.
+    #[wasm_bindgen]
+    pub fn reset(&mut self) -> Result<(), String> {
+        let pristine = self.pristine.as_ref().ok_or("No program has been loaded")?;
+        pristine.restore(self.processor.as_mut())?;
+        self.breakpoints
+            .values_mut()
+            .for_each(Breakpoint::reset_hits);
+        self.crashed = false;
+        self.fault = None;
+        self.last_step = None;
+        self.at_entry = true;
+        self.call_stack.clear();
+        self.watchdog.reset();
+        self.interrupts.reset();
+        Ok(())
+    }
+
+    /// Brings the runner back to how it was created: empty memory, no
+    /// program, symbols, breakpoints, logpoints, catchpoints or interrupt
+    /// vectors. The watchdog keeps its budgets.
+    #[wasm_bindgen]
+    pub fn hard_reset(&mut self) {
+        self.processor = self.fresh_processor();
+        self.crashed = false;
+        self.fault = None;
+        self.last_step = None;
+        self.at_entry = true;
+        self.symbols = SymbolTable::default();
+        self.call_stack.clear();
+        self.breakpoints.clear();
+        self.logpoints.clear();
+        self.log.clear();
+        self.catchpoints.clear();
+        self.watchdog.reset();
+        self.interrupts = InterruptController::default();
+        self.pristine = None;
     }
 
     #[wasm_bindgen]
@@ -37,7 +411,25 @@ impl Runner {
         output: &js_sys::Function,
         input: &js_sys::Function,
     ) -> WasmProcessorContinue {
-        self.processor.run(output, input)
+        self.run_with(&mut JsPortIo::new(output, input))
+    }
+
+    /// Runs one instruction like `run`, and tells what it did: the
+    /// instruction, the registers and memory it changed, the memory its
+    /// operands read, its port I/O and whether it branched. This costs a
+    /// copy of all memory, so it is meant for stepping by hand.
+    #[wasm_bindgen]
+    pub fn step(&mut self, output: &js_sys::Function, input: &js_sys::Function) -> StepInfo {
+        self.step_with(&mut JsPortIo::new(output, input))
+    }
+
+    /// A sentence telling what the last `step` did, with the values it
+    /// worked on, the addresses its operands pointed at and the flags it
+    /// changed, such as `ADD R00, R01 → R00 = 3 + 4 = 7; ZF cleared`.
+    /// `None` if the last thing that ran wasn't a `step`.
+    #[wasm_bindgen]
+    pub fn explain_last_step(&self) -> Option<String> {
+        self.last_step.as_ref().map(StepRecord::explain)
     }
 
     #[wasm_bindgen]
@@ -47,7 +439,337 @@ impl Runner {
         input: &js_sys::Function,
         n: usize,
     ) -> WasmProcessorContinue {
-        self.processor.run_n(output, input, n)
+        self.run_n_with(&mut JsPortIo::new(output, input), n)
+    }
+
+    /// Runs one instruction, or a whole call if the instruction is a CALL.
+    /// Gives up after `max_steps` instructions, returning `Continue`.
+    #[wasm_bindgen]
+    pub fn step_over(
+        &mut self,
+        output: &js_sys::Function,
+        input: &js_sys::Function,
+        max_steps: usize,
+    ) -> WasmProcessorContinue {
+        self.step_over_with(&mut JsPortIo::new(output, input), max_steps)
+    }
+
+    /// Runs until the current function returns to its caller. Gives up after
+    /// `max_steps` instructions, returning `Continue`.
+    #[wasm_bindgen]
+    pub fn step_out(
+        &mut self,
+        output: &js_sys::Function,
+        input: &js_sys::Function,
+        max_steps: usize,
+    ) -> WasmProcessorContinue {
+        self.step_out_with(&mut JsPortIo::new(output, input), max_steps)
+    }
+
+    /// Makes execution stop with `Breakpoint` whenever it reaches `address`,
+    /// before the instruction there runs. The address the program starts at,
+    /// after loading or `reset`, counts as reached by the first step.
+    #[wasm_bindgen]
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address, Breakpoint::default());
+    }
+
+    /// A breakpoint that only stops when `condition`, an expression like the
+    /// ones `evaluate` takes, isn't zero, and only from the `hit_count`th
+    /// time that happens on. A condition that can't be evaluated, such as
+    /// one naming an unknown register, stops execution as if it held.
+    #[wasm_bindgen]
+    pub fn add_conditional_breakpoint(
+        &mut self,
+        address: u16,
+        condition: Option<String>,
+        hit_count: Option<u32>,
+    ) -> Result<(), String> {
+        let condition = condition.as_deref().map(Expression::parse).transpose()?;
+        self.breakpoints
+            .insert(address, Breakpoint::new(condition, hit_count));
+        Ok(())
+    }
+
+    /// How many times execution reached the breakpoint at `address` with
+    /// its condition holding, since it was added or the program was reset.
+    #[wasm_bindgen]
+    pub fn breakpoint_hits(&self, address: u16) -> Option<u32> {
+        self.breakpoints.get(&address).map(Breakpoint::hits)
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address).is_some()
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    #[wasm_bindgen]
+    pub fn breakpoints(&self) -> Vec<u16> {
+        self.breakpoints.keys().copied().collect()
+    }
+
+    /// Writes `template` to the log whenever execution reaches `address`,
+    /// without stopping. Expressions in braces are filled in the way
+    /// `evaluate` computes them, in hex with `:x`: `i = {R01}, top = {[SP]:x}`.
+    /// A logpoint replaces the one at the same address.
+    #[wasm_bindgen]
+    pub fn add_logpoint(&mut self, address: u16, template: &str) -> Result<(), String> {
+        self.logpoints.insert(address, Template::parse(template)?);
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_logpoint(&mut self, address: u16) -> bool {
+        self.logpoints.remove(&address).is_some()
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_logpoints(&mut self) {
+        self.logpoints.clear();
+    }
+
+    #[wasm_bindgen]
+    pub fn logpoints(&self) -> Vec<u16> {
+        self.logpoints.keys().copied().collect()
+    }
+
+    /// Takes the messages logpoints have written since the last call. The
+    /// log keeps the latest 10000; `dropped_log_entries` counts the rest.
+    #[wasm_bindgen]
+    pub fn drain_log(&mut self) -> Vec<LogEntry> {
+        self.log.drain()
+    }
+
+    #[wasm_bindgen]
+    pub fn dropped_log_entries(&self) -> u64 {
+        self.log.dropped()
+    }
+
+    /// Makes execution stop with `Catchpoint` after an IN from `port`
+    /// (with `input`) or an OUT to it (with `output`), or to any port when
+    /// `port` is `None`. Returns the catchpoint's id.
+    #[wasm_bindgen]
+    pub fn catch_port(&mut self, port: Option<u16>, input: bool, output: bool) -> u32 {
+        self.catchpoints.add(Catchpoint::Port {
+            port,
+            input,
+            output,
+        })
+    }
+
+    /// Makes execution stop with `Catchpoint` after every CALL (with
+    /// `calls`) or RET (with `returns`).
+    #[wasm_bindgen]
+    pub fn catch_calls(&mut self, calls: bool, returns: bool) -> u32 {
+        self.catchpoints.add(Catchpoint::Call { calls, returns })
+    }
+
+    /// Makes execution stop with `Catchpoint` after an instruction changes
+    /// the register, or, given a `threshold`, only after it moves from
+    /// below the threshold to at or above it, or back.
+    #[wasm_bindgen]
+    pub fn catch_register(&mut self, name: &str, threshold: Option<u16>) -> Result<u32, String> {
+        let name = name.to_ascii_uppercase();
+        if !self
+            .processor
+            .get_registers()
+            .iter()
+            .any(|register| register.name() == name)
+        {
+            return Err(format!("There is no register named {}", name));
+        }
+        Ok(self
+            .catchpoints
+            .add(Catchpoint::Register { name, threshold }))
+    }
+
+    /// Makes execution stop with `Catchpoint` after an instruction changes
+    /// a text cell from `start` up to, but not including, `end`, such as
+    /// the program's own code. Writes of the value a cell already holds go
+    /// unnoticed. The accumulator, RISC and CISC processors keep data and
+    /// stacks in text memory too, so stores and pushes into the range stop
+    /// execution as well; the stack processor's text memory is never
+    /// written.
+    #[wasm_bindgen]
+    pub fn catch_text_writes(&mut self, start: usize, end: usize) -> Result<u32, String> {
+        self.memory_layout(MemoryType::Text)?
+            .check_range(start, end.saturating_sub(start))?;
+        if start >= end {
+            return Err("The range of text memory to watch is empty".to_string());
+        }
+        Ok(self.catchpoints.add(Catchpoint::TextWrite(start..end)))
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_catchpoint(&mut self, id: u32) -> bool {
+        self.catchpoints.remove(id)
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_catchpoints(&mut self) {
+        self.catchpoints.clear();
+    }
+
+    #[wasm_bindgen]
+    pub fn catchpoints(&self) -> Vec<u32> {
+        self.catchpoints.ids()
+    }
+
+    /// What the catchpoint that last stopped execution saw.
+    #[wasm_bindgen]
+    pub fn last_catch(&self) -> Option<CatchEvent> {
+        self.caught.clone()
+    }
+
+    /// What went wrong when execution last stopped with `Error`, where that
+    /// is known, such as an access outside the configured memory or an
+    /// instruction the emulator would panic on. Native builds also catch
+    /// panics the runner didn't foresee, after which every run stops with
+    /// `Error` right away until `reset`, `hard_reset` or `load_program`, but
+    /// the machine can still be inspected; WebAssembly builds abort instead.
+    #[wasm_bindgen]
+    pub fn fault(&self) -> Option<String> {
+        self.fault.clone().or_else(|| self.processor.fault())
+    }
+
+    /// The exception or bad input value behind the last `IoError`, with
+    /// the port and the instruction involved.
+    #[wasm_bindgen]
+    pub fn io_failure(&self) -> Option<IoFailure> {
+        self.io_failure.clone()
+    }
+
+    /// Makes execution stop with `Timeout` once this many instructions
+    /// have run since the program was loaded or the watchdog was reset.
+    /// `None` lifts the limit.
+    #[wasm_bindgen]
+    pub fn set_step_budget(&mut self, steps: Option<u64>) {
+        self.watchdog.set_step_budget(steps);
+    }
+
+    /// Makes execution stop with `Timeout` once this many milliseconds have
+    /// passed since the first instruction after a load or reset, counting
+    /// the time between calls too. `None` lifts the limit.
+    #[wasm_bindgen]
+    pub fn set_time_budget(&mut self, milliseconds: Option<f64>) {
+        self.watchdog.set_time_budget(milliseconds);
+    }
+
+    /// Makes execution stop with `Loop` when the registers and memory come
+    /// back to a state they were in before, with no input read since. The
+    /// program counter is then somewhere inside the loop. This compares the
+    /// registers after every instruction, so it slows execution down.
+    #[wasm_bindgen]
+    pub fn set_loop_detection(&mut self, enabled: bool) {
+        self.watchdog.set_loop_detection(enabled);
+    }
+
+    /// Starts the step and time budgets over.
+    #[wasm_bindgen]
+    pub fn reset_watchdog(&mut self) {
+        self.watchdog.reset();
+    }
+
+    /// How many instructions have run since the program was loaded or the
+    /// watchdog was reset.
+    #[wasm_bindgen]
+    pub fn steps(&self) -> u64 {
+        self.watchdog.steps()
+    }
+
+    /// Names an address after the closest symbol at or before it, as
+    /// `name` or `name+0x4`.
+    #[wasm_bindgen]
+    pub fn describe_address(&self, address: u16) -> Option<String> {
+        self.symbols.describe(address)
+    }
+
+    /// Makes a raised interrupt on `line`, 0 to 7, jump to `address`.
+    #[wasm_bindgen]
+    pub fn set_interrupt_vector(&mut self, line: u8, address: u16) -> Result<(), String> {
+        self.interrupts.set_vector(line, address)
+    }
+
+    /// Raises `line`. Before the next instruction the processor pushes the
+    /// program counter the way CALL does and jumps to the line's vector; the
+    /// handler ends with RET. Flags aren't saved, so handlers that change
+    /// them must save them themselves. Lower lines go first, and an
+    /// interrupt waits while another handler runs or interrupts are
+    /// disabled.
+    #[wasm_bindgen]
+    pub fn raise_interrupt(&mut self, line: u8) -> Result<(), String> {
+        self.interrupts.raise(line)
+    }
+
+    /// Withdraws an interrupt on `line` that hasn't been delivered yet.
+    #[wasm_bindgen]
+    pub fn clear_interrupt(&mut self, line: u8) -> Result<(), String> {
+        self.interrupts.clear(line)
+    }
+
+    /// The lines raised but not delivered yet.
+    #[wasm_bindgen]
+    pub fn pending_interrupts(&self) -> Vec<u8> {
+        self.interrupts.pending()
+    }
+
+    /// The line whose handler is running, until its RET.
+    #[wasm_bindgen]
+    pub fn interrupt_in_service(&self) -> Option<u8> {
+        self.interrupts.in_service()
+    }
+
+    /// Holds raised interrupts back while `false`, without dropping them.
+    #[wasm_bindgen]
+    pub fn set_interrupts_enabled(&mut self, enabled: bool) {
+        self.interrupts.set_enabled(enabled);
+    }
+
+    /// Starts a timer that raises `line` every `period` instructions. A
+    /// running timer can always break a loop, so loop detection never
+    /// fires while it's on and interrupts are enabled.
+    #[wasm_bindgen]
+    pub fn set_timer(&mut self, period: u32, line: u8) -> Result<(), String> {
+        self.interrupts.set_timer(period, line)
+    }
+
+    #[wasm_bindgen]
+    pub fn stop_timer(&mut self) {
+        self.interrupts.stop_timer();
+    }
+
+    /// Evaluates an expression over the machine state, such as
+    /// `R01 == 0 && [SP+2] > 10`, for watch panels. Names are registers,
+    /// flags (`CF`, `ZF`, `OF`, `SF`) or symbols; `[addr]` reads a word of
+    /// data memory, `data[addr]` and `text[addr]` a word of either memory.
+    #[wasm_bindgen]
+    pub fn evaluate(&mut self, expression: &str) -> Result<u16, String> {
+        Expression::parse(expression)?.evaluate(self)
+    }
+
+    #[wasm_bindgen]
+    pub fn pc(&self) -> u16 {
+        self.processor.pc()
+    }
+
+    /// Disassembles the instruction at `address` in text memory.
+    #[wasm_bindgen]
+    pub fn disassemble(&self, address: u16) -> Option<String> {
+        self.processor
+            .decode(address)
+            .map(|instruction| instruction.to_string())
+    }
+
+    /// The calls that are currently active, innermost first, as seen by
+    /// following the CALL and RET instructions executed since loading.
+    #[wasm_bindgen]
+    pub fn backtrace(&self) -> Vec<CallFrame> {
+        self.call_stack.backtrace(&self.symbols)
     }
 
     #[wasm_bindgen]
@@ -65,8 +787,814 @@ impl Runner {
         self.processor.get_registers()
     }
 
+    #[wasm_bindgen]
+    pub fn set_register(&mut self, name: &str, value: u16) -> Result<(), String> {
+        if self.processor.set_register(name, value) {
+            Ok(())
+        } else {
+            Err(format!("Cannot set register {} to {:#x}", name, value))
+        }
+    }
+
     #[wasm_bindgen]
     pub fn peek_stack(&mut self, n: u8) -> u16 {
         self.processor.peek_stack(n)
     }
+
+    /// The whole stack `peek_stack` looks at, along with its pointers and
+    /// limits. Fails if the stack pointer has run past either end.
+    #[wasm_bindgen]
+    pub fn stack_frame(&self) -> Result<StackFrame, String> {
+        let layouts = self.processor.stack_layouts();
+        let layout = layouts.first().ok_or("This processor has no stack")?;
+        layout.read(|mem_type, address| self.read_u16(mem_type, address))
+    }
+
+    /// Every stack of the processor - the stack processor has both a
+    /// register stack and a memory stack.
+    #[wasm_bindgen]
+    pub fn stack_frames(&self) -> Result<Vec<StackFrame>, String> {
+        self.processor
+            .stack_layouts()
+            .iter()
+            .map(|layout| layout.read(|mem_type, address| self.read_u16(mem_type, address)))
+            .collect()
+    }
+
+    /// Reads a 16-bit word using the processor's own word layout.
+    #[wasm_bindgen]
+    pub fn read_u16(&self, mem_type: MemoryType, address: usize) -> Result<u16, String> {
+        let layout = self.memory_layout(mem_type)?;
+        let cells = self.read_cells(mem_type, address, layout.word_cells)?;
+        Ok(layout.combine(&cells))
+    }
+
+    #[wasm_bindgen]
+    pub fn read_i16(&self, mem_type: MemoryType, address: usize) -> Result<i16, String> {
+        self.read_u16(mem_type, address).map(|value| value as i16)
+    }
+
+    /// Reads `count` consecutive words starting at `address`.
+    #[wasm_bindgen]
+    pub fn read_array(
+        &self,
+        mem_type: MemoryType,
+        address: usize,
+        count: usize,
+    ) -> Result<Vec<u16>, String> {
+        let layout = self.memory_layout(mem_type)?;
+        let length = count
+            .checked_mul(layout.word_cells)
+            .ok_or("Array is too long")?;
+        let cells = self.read_cells(mem_type, address, length)?;
+        Ok(cells
+            .chunks(layout.word_cells)
+            .map(|word| layout.combine(word))
+            .collect())
+    }
+
+    /// Reads a null-terminated string, one character per memory cell.
+    /// Stops after `max_length` characters if no terminator was found.
+    #[wasm_bindgen]
+    pub fn read_string(
+        &self,
+        mem_type: MemoryType,
+        address: usize,
+        max_length: usize,
+    ) -> Result<String, String> {
+        let layout = self.memory_layout(mem_type)?;
+        layout.check_range(address, 1)?;
+        let end = address.saturating_add(max_length).min(layout.size);
+        let mut result = String::new();
+        for index in address..end {
+            match self.processor.read_memory(mem_type, index) {
+                Some(0) => break,
+                Some(cell) => result.push(cell as char),
+                None => break,
+            }
+        }
+        Ok(result)
+    }
+
+    #[wasm_bindgen]
+    pub fn write_u16(
+        &mut self,
+        mem_type: MemoryType,
+        address: usize,
+        value: u16,
+    ) -> Result<(), String> {
+        let layout = self.memory_layout(mem_type)?;
+        self.write_cells(mem_type, address, &layout.split(value))
+    }
+
+    #[wasm_bindgen]
+    pub fn write_i16(
+        &mut self,
+        mem_type: MemoryType,
+        address: usize,
+        value: i16,
+    ) -> Result<(), String> {
+        self.write_u16(mem_type, address, value as u16)
+    }
+
+    #[wasm_bindgen]
+    pub fn write_array(
+        &mut self,
+        mem_type: MemoryType,
+        address: usize,
+        values: &[u16],
+    ) -> Result<(), String> {
+        let layout = self.memory_layout(mem_type)?;
+        let cells: Vec<u8> = values
+            .iter()
+            .flat_map(|value| layout.split(*value))
+            .collect();
+        self.write_cells(mem_type, address, &cells)
+    }
+
+    /// Writes a string followed by a null terminator.
+    #[wasm_bindgen]
+    pub fn write_string(
+        &mut self,
+        mem_type: MemoryType,
+        address: usize,
+        value: &str,
+    ) -> Result<(), String> {
+        let mut cells = Vec::with_capacity(value.len() + 1);
+        for character in value.chars() {
+            let cell = u8::try_from(u32::from(character))
+                .map_err(|_| format!("Character {:?} does not fit into a cell", character))?;
+            cells.push(cell);
+        }
+        cells.push(0);
+        self.write_cells(mem_type, address, &cells)
+    }
+}
+
+/// The same execution methods as above, with the ports going to any
+/// `PortIo` rather than to JS callbacks.
+impl Runner {
+    pub fn run_with(&mut self, io: &mut dyn PortIo) -> WasmProcessorContinue {
+        self.advance(io)
+    }
+
+    pub fn step_with(&mut self, io: &mut dyn PortIo) -> StepInfo {
+        let pc = self.processor.pc();
+        let stack_machine = matches!(self.processor_type, ProcessorType::Stack);
+        let stack_top = |runner: &mut Runner| match stack_machine {
+            true => [
+                runner.processor.peek_stack(0),
+                runner.processor.peek_stack(1),
+            ],
+            false => [0, 0],
+        };
+        let stack_before = stack_top(self);
+        let registers = self.processor.get_registers();
+        let memory = self.processor.get_memory();
+        let mut recorder = PortRecorder {
+            inner: io,
+            accesses: Vec::new(),
+        };
+        self.noting_accesses = true;
+        let status = self.advance(&mut recorder);
+        self.noting_accesses = false;
+        let ports = recorder.accesses;
+        let pc_after = self.processor.pc();
+        let registers_after = self.processor.get_registers();
+        let memory_after = self.processor.get_memory();
+
+        let changes = registers
+            .iter()
+            .zip(&registers_after)
+            .filter(|(old, new)| old.value() != new.value())
+            .map(|(old, new)| RegisterChange {
+                name: new.name(),
+                old: old.value(),
+                new: new.value(),
+            })
+            .collect();
+        let accesses = self.accesses.take().unwrap_or_default();
+        let failed = status == WasmProcessorContinue::Error;
+        let writes = self.written_words(&accesses.writes, &memory, &memory_after, failed);
+        let reads = self.unwritten_words(&accesses.reads, &memory, &writes);
+
+        let executed = self.executed.clone();
+        let branch_taken = executed
+            .as_ref()
+            .filter(|instruction| {
+                matches!(
+                    instruction.kind,
+                    InstructionKind::Jump
+                        | InstructionKind::Branch
+                        | InstructionKind::Call
+                        | InstructionKind::Return
+                )
+            })
+            .map(|instruction| pc_after != instruction.next_address());
+        let info = StepInfo {
+            status,
+            pc_before: executed
+                .as_ref()
+                .map_or(pc, |instruction| instruction.address),
+            pc_after,
+            instruction: executed.as_ref().map(ToString::to_string),
+            kind: executed
+                .as_ref()
+                .map(|instruction| instruction.kind.name().to_string()),
+            registers: changes,
+            reads,
+            writes,
+            ports: ports.clone(),
+            branch_taken,
+        };
+        let layouts = [MemoryType::Text, MemoryType::Data]
+            .iter()
+            .filter_map(|mem_type| Some((*mem_type, self.processor.memory_layout(*mem_type)?)))
+            .collect();
+        self.last_step = Some(StepRecord {
+            status,
+            instruction: executed,
+            pc_after,
+            stack_machine,
+            registers_before: registers,
+            registers_after,
+            memory_before: memory,
+            memory_after,
+            layouts,
+            operand_memory: self.operand_memory(),
+            stack_before,
+            stack_after: stack_top(self)[0],
+            ports,
+        });
+        info
+    }
+
+    pub fn run_n_with(&mut self, io: &mut dyn PortIo, n: usize) -> WasmProcessorContinue {
+        self.run_while(io, n, |_| true)
+    }
+
+    pub fn step_over_with(
+        &mut self,
+        io: &mut dyn PortIo,
+        max_steps: usize,
+    ) -> WasmProcessorContinue {
+        let instruction = self.processor.decode(self.processor.pc());
+        if !matches!(
+            instruction,
+            Some(Instruction {
+                kind: InstructionKind::Call,
+                ..
+            })
+        ) {
+            return self.advance(io);
+        }
+        let depth = self.call_stack.depth();
+        let result = self.advance(io);
+        if result != WasmProcessorContinue::Continue {
+            return result;
+        }
+        self.run_while(io, max_steps.saturating_sub(1), |runner| {
+            runner.call_stack.depth() > depth
+        })
+    }
+
+    pub fn step_out_with(
+        &mut self,
+        io: &mut dyn PortIo,
+        max_steps: usize,
+    ) -> WasmProcessorContinue {
+        let depth = self.call_stack.depth();
+        self.run_while(io, max_steps, |runner| runner.call_stack.depth() >= depth)
+    }
+}
+
+impl Runner {
+    /// Starts over with a freshly loaded program, which `reset` goes back
+    /// to.
+    fn loaded(&mut self) {
+        self.crashed = false;
+        self.fault = None;
+        self.last_step = None;
+        self.at_entry = true;
+        self.call_stack.clear();
+        self.watchdog.reset();
+        self.interrupts.reset();
+        self.breakpoints
+            .values_mut()
+            .for_each(Breakpoint::reset_hits);
+        self.pristine = Some(MachineState::capture(self.processor.as_mut()));
+    }
+
+    fn advance(&mut self, io: &mut dyn PortIo) -> WasmProcessorContinue {
+        self.executed = None;
+        self.accesses = None;
+        self.last_step = None;
+        if self.crashed {
+            return WasmProcessorContinue::Error;
+        }
+        let budget = self.watchdog.check_budget();
+        if budget != WasmProcessorContinue::Continue {
+            return budget;
+        }
+        self.fault = None;
+        self.io_failure = None;
+        self.caught = None;
+        if self.check_entry() {
+            return WasmProcessorContinue::Breakpoint;
+        }
+        let interrupted = match self.deliver_interrupt() {
+            Ok(interrupted) => interrupted,
+            Err(fault) => {
+                self.fault = Some(fault);
+                return WasmProcessorContinue::Error;
+            }
+        };
+        if interrupted {
+            self.write_log();
+            if self.breakpoint_hit() {
+                return WasmProcessorContinue::Breakpoint;
+            }
+        }
+        let pc = self.processor.pc();
+        let instruction = self.processor.decode(pc);
+        if let Some(instruction) = &instruction {
+            if let Err(fault) = panics::check_instruction(instruction, self.processor.as_mut()) {
+                self.fault = Some(fault);
+                return WasmProcessorContinue::Error;
+            }
+        }
+        let mut accesses = match &instruction {
+            Some(instruction) if self.noting_accesses || self.catchpoints.watches_text() => {
+                self.accesses(instruction)
+            }
+            _ => Accesses::default(),
+        };
+        let text_cells = self.watched_text_cells(&accesses.writes);
+        if self.noting_accesses {
+            if interrupted {
+                // The return address has been pushed onto SP already.
+                let stacks = self.processor.stack_layouts();
+                if let Some(layout) = stacks.iter().find(|layout| layout.name == "SP") {
+                    accesses.writes.push((layout.memory_type, layout.pointer));
+                }
+            }
+            self.accesses = Some(accesses);
+        }
+        let before = self.watch(&text_cells);
+        let kind = instruction.as_ref().map(|instruction| instruction.kind);
+        self.executed = instruction.clone();
+        let processor = &mut self.processor;
+        let mut recorder = PortRecorder {
+            inner: io,
+            accesses: Vec::new(),
+        };
+        let result = match panics::catch(|| processor.run(&mut recorder)) {
+            Ok(result) => result,
+            Err(message) => {
+                self.fault = Some(format!("The emulator panicked: {}", message));
+                self.crashed = true;
+                return WasmProcessorContinue::Error;
+            }
+        };
+        // A failed port access outranks whatever else stopped the step.
+        if let Some(mut failure) = recorder.take_failure() {
+            failure.pc = pc;
+            self.io_failure = Some(failure);
+        }
+        let ports = recorder.accesses;
+        if result != WasmProcessorContinue::Continue {
+            return match self.io_failure {
+                Some(_) => WasmProcessorContinue::IoError,
+                None => result,
+            };
+        }
+        let mut read_input = false;
+        if let Some(instruction) = instruction {
+            self.call_stack.track(
+                &instruction,
+                self.processor.pc(),
+                self.processor.stack_pointer(),
+            );
+            if instruction.kind == InstructionKind::Return {
+                self.interrupts.returned(self.processor.stack_pointer());
+            }
+            read_input = instruction.kind == InstructionKind::Input;
+        }
+        self.interrupts.tick();
+        let watched = self.watchdog.record_step(
+            self.processor.as_mut(),
+            read_input || interrupted || self.interrupts.armed(),
+        );
+        if watched != WasmProcessorContinue::Continue && self.io_failure.is_none() {
+            return watched;
+        }
+        self.write_log();
+        if self.io_failure.is_some() {
+            return WasmProcessorContinue::IoError;
+        }
+        if !self.catchpoints.is_empty() {
+            let after = self.watch(&text_cells);
+            let step = Step {
+                kind,
+                pc: self.processor.pc(),
+                ports: &ports,
+                before: &before,
+                after: &after,
+            };
+            if let Some(event) = self.catchpoints.check(&step) {
+                self.caught = Some(event);
+                return WasmProcessorContinue::Catchpoint;
+            }
+        }
+        if self.breakpoint_hit() {
+            return WasmProcessorContinue::Breakpoint;
+        }
+        result
+    }
+
+    /// The memory that operands such as `[R00]` address: data memory, or
+    /// text memory on processors without one.
+    fn operand_memory(&self) -> MemoryType {
+        match self.processor.memory_layout(MemoryType::Data) {
+            Some(_) => MemoryType::Data,
+            None => MemoryType::Text,
+        }
+    }
+
+    /// The words the instruction about to run reads and may write: those
+    /// its memory operands address, the ones it pops off and pushes onto
+    /// stacks, and the one the stack processor's `load` or `store` takes the
+    /// address of from the stack.
+    fn accesses(&mut self, instruction: &Instruction) -> Accesses {
+        let mem_type = self.operand_memory();
+        let registers = self.processor.get_registers();
+        let accumulator = matches!(self.processor_type, ProcessorType::Acc);
+        let mut accesses = Accesses::default();
+        for (index, address) in operand_addresses(instruction, &registers) {
+            let (reads, writes) = operand_use(accumulator, instruction, index);
+            if reads {
+                accesses.reads.push((mem_type, address));
+            }
+            if writes {
+                accesses.writes.push((mem_type, address));
+            }
+        }
+        let stack_machine = matches!(self.processor_type, ProcessorType::Stack);
+        let layouts = self.processor.stack_layouts();
+        for effect in stack_effects(self.processor.as_mut(), stack_machine, instruction) {
+            if let Some(layout) = layouts.iter().find(|layout| layout.name == effect.stack) {
+                let popped = effect.popped_words(layout).into_iter();
+                accesses
+                    .reads
+                    .extend(popped.map(|address| (layout.memory_type, address)));
+                let pushed = effect.pushed_words(layout).into_iter();
+                accesses
+                    .writes
+                    .extend(pushed.map(|address| (layout.memory_type, address)));
+            }
+        }
+        if let Some(address) = stack_address(self.processor.as_mut(), stack_machine, instruction) {
+            match instruction.mnemonic {
+                "load" => accesses.reads.push((mem_type, address)),
+                _ => accesses.writes.push((mem_type, address)),
+            }
+        }
+        accesses
+    }
+
+    /// The words out of `words`, as writes of their values in `after` with
+    /// what they held in `before`, even where that is the same. An
+    /// instruction that `failed` may have stopped before writing, so then
+    /// only the words that changed count.
+    fn written_words(
+        &self,
+        words: &[(MemoryType, u16)],
+        before: &[MemoryBlock],
+        after: &[MemoryBlock],
+        failed: bool,
+    ) -> Vec<MemoryAccess> {
+        let mut writes: Vec<MemoryAccess> = Vec::new();
+        for &(memory_type, address) in words {
+            let old = self.word_in(before, memory_type, address);
+            let new = self.word_in(after, memory_type, address);
+            let (Some(old), Some(new)) = (old, new) else {
+                continue;
+            };
+            let seen = writes
+                .iter()
+                .any(|write| (write.memory_type, write.address) == (memory_type, address));
+            if !seen && (!failed || old != new) {
+                writes.push(MemoryAccess {
+                    memory_type,
+                    address,
+                    value: new,
+                    previous: Some(old),
+                });
+            }
+        }
+        writes
+    }
+
+    /// The words out of `words` that none of `writes` touches, as reads of
+    /// their values in `memory`.
+    fn unwritten_words(
+        &self,
+        words: &[(MemoryType, u16)],
+        memory: &[MemoryBlock],
+        writes: &[MemoryAccess],
+    ) -> Vec<MemoryAccess> {
+        let mut reads: Vec<MemoryAccess> = Vec::new();
+        for &(memory_type, address) in words {
+            let Some(value) = self.word_in(memory, memory_type, address) else {
+                continue;
+            };
+            let word_cells = self
+                .memory_layout(memory_type)
+                .map_or(1, |layout| layout.word_cells);
+            let overlaps = |other: u16| {
+                let (start, other) = (usize::from(address), usize::from(other));
+                start < other + word_cells && other < start + word_cells
+            };
+            let written = writes
+                .iter()
+                .any(|write| write.memory_type == memory_type && overlaps(write.address));
+            let seen = reads
+                .iter()
+                .any(|read| (read.memory_type, read.address) == (memory_type, address));
+            if !written && !seen {
+                reads.push(MemoryAccess {
+                    memory_type,
+                    address,
+                    value,
+                    previous: None,
+                });
+            }
+        }
+        reads
+    }
+
+    /// The word at `address` in the `mem_type` block of `memory`.
+    fn word_in(&self, memory: &[MemoryBlock], mem_type: MemoryType, address: u16) -> Option<u16> {
+        let layout = self.memory_layout(mem_type).ok()?;
+        let block = memory.iter().find(|block| block.memory_type == mem_type)?;
+        let start = usize::from(address);
+        Some(layout.combine(block.values.get(start..start + layout.word_cells)?))
+    }
+
+    /// The text cells in `words` that text write catchpoints watch.
+    fn watched_text_cells(&self, words: &[(MemoryType, u16)]) -> Vec<usize> {
+        let layout = match self.memory_layout(MemoryType::Text) {
+            Ok(layout) if self.catchpoints.watches_text() => layout,
+            _ => return Vec::new(),
+        };
+        let mut cells: Vec<usize> = words
+            .iter()
+            .filter(|(mem_type, _)| *mem_type == MemoryType::Text)
+            .flat_map(|(_, address)| {
+                let start = usize::from(*address);
+                start..start + layout.word_cells
+            })
+            .filter(|cell| {
+                self.catchpoints
+                    .text_ranges()
+                    .any(|(_, range)| range.contains(cell))
+            })
+            .collect();
+        cells.sort_unstable();
+        cells.dedup();
+        cells
+    }
+
+    /// The registers the catchpoints compare across an instruction, and
+    /// those of `text_cells` each text write catchpoint watches; nothing
+    /// when no catchpoint needs them.
+    fn watch(&mut self, text_cells: &[usize]) -> Watched {
+        let mut watched = Watched::default();
+        if self.catchpoints.watches_registers() {
+            watched.registers = self
+                .processor
+                .get_registers()
+                .iter()
+                .map(|register| (register.name(), register.value()))
+                .collect();
+        }
+        for (id, range) in self.catchpoints.text_ranges() {
+            let cells = text_cells
+                .iter()
+                .filter(|cell| range.contains(cell))
+                .map(|&cell| {
+                    let value = self.processor.read_memory(MemoryType::Text, cell);
+                    (cell, value.unwrap_or(0))
+                })
+                .collect();
+            watched.text.insert(id, cells);
+        }
+        watched
+    }
+
+    /// Writes the message of the logpoint at the program counter, if there
+    /// is one.
+    fn write_log(&mut self) {
+        let pc = self.processor.pc();
+        let Some(template) = self.logpoints.get(&pc).cloned() else {
+            return;
+        };
+        let message = template.render(self);
+        self.log.push(pc, self.watchdog.steps(), message);
+    }
+
+    /// Writes the logpoint at the PC the program was loaded or reset to, which
+    /// no step has arrived at, and tells whether the breakpoint there stops
+    /// execution. Only the first call after loading or resetting does so.
+    pub(crate) fn check_entry(&mut self) -> bool {
+        if !std::mem::take(&mut self.at_entry) {
+            return false;
+        }
+        self.write_log();
+        self.breakpoint_hit()
+    }
+
+    /// Whether the breakpoint at the program counter, if there is one,
+    /// stops execution now.
+    fn breakpoint_hit(&mut self) -> bool {
+        let pc = self.processor.pc();
+        let Some(condition) = self.breakpoints.get(&pc).map(|b| b.condition().cloned()) else {
+            return false;
+        };
+        let holds = match condition {
+            Some(condition) => condition.evaluate(self) != Ok(0),
+            None => true,
+        };
+        holds && self.breakpoints.get_mut(&pc).is_some_and(Breakpoint::hit)
+    }
+
+    fn fresh_processor(&self) -> Box<dyn WasmProcessor> {
+        match &self.config {
+            Some(config) => Box::new(
+                ConfiguredProcessor::new(self.processor_type.clone(), config.clone())
+                    .expect("the configuration was checked when the runner was made"),
+            ),
+            None => create_processor(self.processor_type.clone()),
+        }
+    }
+
+    /// Makes sure every segment of `executable` lands inside memory and
+    /// holds as many cells as it claims.
+    fn check_segments(&self, executable: &Executable) -> Result<(), String> {
+        let has_data = self.processor.memory_layout(MemoryType::Data).is_some();
+        for segment in executable.segments() {
+            check_segment(
+                segment,
+                self.memory_layout(segment_memory(segment, has_data))?,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Delivers the next interrupt, if one can be delivered, and tells
+    /// whether it did.
+    fn deliver_interrupt(&mut self) -> Result<bool, String> {
+        let stack_pointer = self.processor.stack_pointer();
+        let Some((_, vector)) = self.interrupts.take(stack_pointer) else {
+            return Ok(false);
+        };
+        let pc = self.processor.pc();
+        self.push_return_address(pc)?;
+        self.call_stack
+            .interrupt(pc, vector, self.processor.stack_pointer());
+        self.processor.set_register("PC", vector);
+        Ok(true)
+    }
+
+    /// Pushes `address` the way CALL does, onto the SP stack.
+    fn push_return_address(&mut self, address: u16) -> Result<(), String> {
+        let layout = self
+            .processor
+            .stack_layouts()
+            .into_iter()
+            .find(|layout| layout.name == "SP")
+            .ok_or("This processor has no SP stack")?;
+        let pointer = if layout.grows_down {
+            layout.pointer.wrapping_sub(2)
+        } else {
+            layout.pointer.wrapping_add(2)
+        };
+        let cells = self.memory_layout(layout.memory_type)?.split(address);
+        self.write_cells(layout.memory_type, usize::from(pointer), &cells)
+            .map_err(|error| format!("Failed to push the return address: {}", error))?;
+        self.processor.set_register("SP", pointer);
+        Ok(())
+    }
+
+    /// Steps up to `n` times, stopping early once `condition` no longer holds
+    /// after a step.
+    fn run_while<F>(&mut self, io: &mut dyn PortIo, n: usize, condition: F) -> WasmProcessorContinue
+    where
+        F: Fn(&Runner) -> bool,
+    {
+        for _ in 0..n {
+            let result = self.advance(io);
+            if result != WasmProcessorContinue::Continue || !condition(self) {
+                return result;
+            }
+        }
+        WasmProcessorContinue::Continue
+    }
+
+    fn memory_layout(&self, mem_type: MemoryType) -> Result<MemoryLayout, String> {
+        self.processor
+            .memory_layout(mem_type)
+            .ok_or_else(|| "This processor has no such memory".to_string())
+    }
+
+    /// The cells of a processor's separate memory of `mem_type`, from its
+    /// first cell that isn't zero to its last, or `None` if there is no such
+    /// memory or it's empty.
+    fn used_cells(&self, mem_type: MemoryType) -> Result<Option<SegmentCells>, String> {
+        let layout = match self.processor.memory_layout(MemoryType::Data) {
+            Some(_) => self.memory_layout(mem_type)?,
+            None => return Ok(None),
+        };
+        let cells = self.read_cells(mem_type, 0, layout.size)?;
+        let start = match cells.iter().position(|cell| *cell != 0) {
+            Some(start) => start,
+            None => return Ok(None),
+        };
+        let end = cells.iter().rposition(|cell| *cell != 0).unwrap() + 1;
+        Ok(Some(SegmentCells {
+            memory_type: mem_type,
+            layout,
+            start,
+            cells: cells[start..end].to_vec(),
+        }))
+    }
+
+    fn read_cells(
+        &self,
+        mem_type: MemoryType,
+        address: usize,
+        count: usize,
+    ) -> Result<Vec<u8>, String> {
+        self.memory_layout(mem_type)?.check_range(address, count)?;
+        (address..address + count)
+            .map(|index| {
+                self.processor
+                    .read_memory(mem_type, index)
+                    .ok_or_else(|| format!("Failed to read memory at {:#x}", index))
+            })
+            .collect()
+    }
+
+    /// Validates the whole range before writing anything, so a failed write
+    /// never leaves memory half-updated.
+    fn write_cells(
+        &mut self,
+        mem_type: MemoryType,
+        address: usize,
+        cells: &[u8],
+    ) -> Result<(), String> {
+        let layout = self.memory_layout(mem_type)?;
+        layout.check_range(address, cells.len())?;
+        if let Some(cell) = cells.iter().find(|cell| **cell > layout.max_cell()) {
+            return Err(format!(
+                "Value {:#x} does not fit into a {}-bit cell",
+                cell, layout.cell_bits
+            ));
+        }
+        for (offset, cell) in cells.iter().enumerate() {
+            if !self.processor.set_memory(mem_type, address + offset, *cell) {
+                return Err(format!("Failed to write memory at {:#x}", address + offset));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Environment for Runner {
+    fn name(&mut self, name: &str) -> Result<u16, String> {
+        let upper = name.to_ascii_uppercase();
+        let registers = self.processor.get_registers();
+        let register = |wanted: &str| {
+            registers
+                .iter()
+                .find(|register| register.name() == wanted)
+                .map(|register| register.value())
+        };
+        if let Some(value) = register(&upper) {
+            return Ok(value);
+        }
+        if let Some((_, _, mask)) = FLAGS.iter().find(|(flag, _, _)| *flag == upper) {
+            let flags = register("FR").ok_or("The processor has no flag register")?;
+            return Ok(u16::from(flags & mask != 0));
+        }
+        self.symbols
+            .find(name)
+            .map(|symbol| symbol.address)
+            .ok_or_else(|| format!("There is no register, flag or symbol named {}", name))
+    }
+
+    fn read(&mut self, memory: Option<MemoryType>, address: u16) -> Result<u16, String> {
+        let mem_type = memory.unwrap_or_else(|| self.operand_memory());
+        self.read_u16(mem_type, address.into())
+    }
 }
@@ -0,0 +1,155 @@
+//! Logpoints, which write a message when execution reaches them instead of
+//! stopping it.
+
+use std::collections::VecDeque;
+
+use wasm_bindgen::prelude::*;
+
+use crate::expression::{Environment, Expression};
+
+/// How many messages the log keeps before dropping the oldest ones.
+pub const LOG_CAPACITY: usize = 10_000;
+
+#[derive(Clone)]
+enum Piece {
+    Text(String),
+    Value(Expression, bool),
+}
+
+/// A message with expressions in braces, such as `i = {R01}, top = {[SP]:x}`.
+/// `:x` shows a value in hex; `{{` and `}}` stand for braces.
+#[derive(Clone)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Template, String> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut chars = source.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut inside = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        inside.push(c);
+                    }
+                    if !closed {
+                        return Err(format!("Unmatched '{{' in {:?}", source));
+                    }
+                    let (expression, hex) = match inside.rsplit_once(':') {
+                        Some((expression, "x")) => (expression, true),
+                        Some((_, format)) => {
+                            return Err(format!("Unknown format :{} in {:?}", format, source))
+                        }
+                        None => (inside.as_str(), false),
+                    };
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(Piece::Value(Expression::parse(expression)?, hex));
+                }
+                '}' => return Err(format!("Unmatched '}}' in {:?}", source)),
+                _ => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Ok(Template { pieces })
+    }
+
+    /// Fills in the expressions; one that can't be evaluated shows up as
+    /// `<error: ...>`.
+    pub fn render(&self, environment: &mut dyn Environment) -> String {
+        self.pieces
+            .iter()
+            .map(|piece| match piece {
+                Piece::Text(text) => text.clone(),
+                Piece::Value(expression, hex) => match expression.evaluate(environment) {
+                    Ok(value) if *hex => format!("{:#06x}", value),
+                    Ok(value) => value.to_string(),
+                    Err(message) => format!("<error: {}>", message),
+                },
+            })
+            .collect()
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct LogEntry {
+    address: u16,
+    steps: u64,
+    message: String,
+}
+
+#[wasm_bindgen]
+impl LogEntry {
+    /// The address of the logpoint.
+    #[wasm_bindgen]
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    /// How many instructions had run when the message was written, as
+    /// `Runner::steps` counts them.
+    #[wasm_bindgen]
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    #[wasm_bindgen]
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+}
+
+/// The messages written so far and not yet drained by the host.
+#[derive(Default)]
+pub struct Log {
+    entries: VecDeque<LogEntry>,
+    dropped: u64,
+}
+
+impl Log {
+    pub fn push(&mut self, address: u16, steps: u64, message: String) {
+        if self.entries.len() == LOG_CAPACITY {
+            self.entries.pop_front();
+            self.dropped += 1;
+        }
+        self.entries.push_back(LogEntry {
+            address,
+            steps,
+            message,
+        });
+    }
+
+    pub fn drain(&mut self) -> Vec<LogEntry> {
+        self.entries.drain(..).collect()
+    }
+
+    /// How many messages were dropped because the log was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.dropped = 0;
+    }
+}
@@ -1,7 +1,7 @@
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum MemoryType {
     Text,
     Data,
@@ -34,3 +34,49 @@ impl MemoryBlock {
         }
     }
 }
+
+/// How a memory block is laid out: how many cells it has, how wide each cell
+/// is, and how many cells make up one 16-bit machine word.
+///
+/// Every monistode architecture stores words big-endian, so the first cell of
+/// a word holds its most significant bits.
+#[derive(Clone, Copy)]
+pub struct MemoryLayout {
+    pub size: usize,
+    pub cell_bits: u32,
+    pub word_cells: usize,
+}
+
+impl MemoryLayout {
+    pub fn max_cell(&self) -> u8 {
+        ((1u16 << self.cell_bits) - 1) as u8
+    }
+
+    /// Combines big-endian cells into a word, dropping any bits that don't fit
+    /// into 16 bits - the same way the processors do when fetching immediates.
+    pub fn combine(&self, cells: &[u8]) -> u16 {
+        cells
+            .iter()
+            .fold(0u32, |word, cell| word << self.cell_bits | u32::from(*cell)) as u16
+    }
+
+    pub fn split(&self, word: u16) -> Vec<u8> {
+        (0..self.word_cells)
+            .rev()
+            .map(|i| {
+                let shift = i as u32 * self.cell_bits;
+                ((u32::from(word) >> shift) & u32::from(self.max_cell())) as u8
+            })
+            .collect()
+    }
+
+    pub fn check_range(&self, address: usize, cells: usize) -> Result<(), String> {
+        match address.checked_add(cells) {
+            Some(end) if end <= self.size => Ok(()),
+            _ => Err(format!(
+                "Range {:#x}+{} is outside of memory of size {:#x}",
+                address, cells, self.size
+            )),
+        }
+    }
+}
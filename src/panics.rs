@@ -0,0 +1,61 @@
+//! Keeps panics inside the emulator from taking the runner down: instructions
+//! known to make it panic are turned into faults before they run, and on
+//! native targets any other panic is caught and turned into an error.
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::decoder::{Instruction, Operand};
+use crate::processor::WasmProcessor;
+
+/// How many bits the emulator's words hold; shifting by as many or more
+/// overflows.
+const WORD_BITS: u16 = 16;
+
+fn describe(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown cause".to_string()
+    }
+}
+
+/// Runs `f`, returning the panic message if it panics.
+///
+/// WebAssembly builds abort on panic instead of unwinding, so this catches
+/// nothing there, and the runner can't recover from the panic. On the web
+/// the emulator is only protected by the checks made before it runs, such as
+/// `check_instruction` and the segment checks in `load_program`.
+pub fn catch<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(describe)
+}
+
+/// Rejects an instruction the emulator would panic on, before it runs: a
+/// shift by a whole word or more overflows its arithmetic.
+pub fn check_instruction(
+    instruction: &Instruction,
+    processor: &mut dyn WasmProcessor,
+) -> Result<(), String> {
+    if !matches!(instruction.mnemonic, "lsh" | "rsh") {
+        return Ok(());
+    }
+    // Every processor takes the shift amount from the last operand.
+    let amount = match instruction.operands.last() {
+        Some(Operand::Immediate(amount)) => *amount,
+        Some(Operand::Register(name)) => processor
+            .get_registers()
+            .iter()
+            .find(|register| register.name() == *name)
+            .map_or(0, |register| register.value()),
+        _ => return Ok(()),
+    };
+    if amount >= WORD_BITS {
+        return Err(format!(
+            "{} shifts by {}, but words only hold {} bits",
+            instruction, amount, WORD_BITS
+        ));
+    }
+    Ok(())
+}
@@ -1,28 +1,81 @@
+use std::cell::RefCell;
+
 use crate::{
-    memory::{MemoryBlock, MemoryType},
+    decoder::Instruction,
+    io::PortIo,
+    memory::{MemoryBlock, MemoryLayout, MemoryType},
     registers::RegisterState,
+    stack::StackLayout,
 };
+use monistode_binutils::Executable;
+use monistode_emulator::common::{Processor, ProcessorContinue};
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum WasmProcessorContinue {
     Continue,
     Error,
     Halt,
+    /// Execution reached a breakpoint; the instruction there hasn't run yet.
+    Breakpoint,
+    /// The runner's step or time budget ran out before the instruction at
+    /// the program counter.
+    Timeout,
+    /// The machine came back to a state it was in before without reading
+    /// any input in between, so it will loop forever.
+    Loop,
+    /// A port callback threw, or the input callback returned a value that
+    /// doesn't fit a port; `Runner::io_failure` has the details. The
+    /// instruction has run.
+    IoError,
+    /// A catchpoint saw the instruction that just ran do what it watches
+    /// for; `Runner::last_catch` tells which one and what happened.
+    Catchpoint,
 }
 
 pub trait WasmProcessor {
-    fn run(&mut self, output: &js_sys::Function, input: &js_sys::Function)
-        -> WasmProcessorContinue;
-    fn run_n(
-        &mut self,
-        output: &js_sys::Function,
-        input: &js_sys::Function,
-        n: usize,
-    ) -> WasmProcessorContinue;
+    fn run(&mut self, io: &mut dyn PortIo) -> WasmProcessorContinue;
     fn get_memory(&mut self) -> Vec<MemoryBlock>;
     fn set_memory(&mut self, mem_type: MemoryType, index: usize, value: u8) -> bool;
+    fn read_memory(&self, mem_type: MemoryType, index: usize) -> Option<u8>;
+    fn memory_layout(&self, mem_type: MemoryType) -> Option<MemoryLayout>;
     fn get_registers(&mut self) -> Vec<RegisterState>;
-    fn load_executable(&mut self, binary: &[u8]) -> Result<(), String>;
+    /// Sets a register by the name `get_registers` gives it. Fails if there
+    /// is no such register or the value doesn't fit into it.
+    fn set_register(&mut self, name: &str, value: u16) -> bool;
+    fn load_executable(&mut self, executable: &Executable) -> Result<(), String>;
     fn peek_stack(&mut self, n: u8) -> u16;
+    /// The processor's stacks, the one `peek_stack` looks at first.
+    fn stack_layouts(&self) -> Vec<StackLayout>;
+    fn pc(&self) -> u16;
+    /// The pointer of the stack CALL pushes its return address onto.
+    fn stack_pointer(&self) -> u16;
+    /// Decodes the instruction at `address` in text memory, without running it.
+    fn decode(&self, address: u16) -> Option<Instruction>;
+    /// Why the last `run` returned `Error`, if the wrapper knows better than
+    /// the emulator does.
+    fn fault(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Runs a single instruction of an emulator processor, routing its port
+/// accesses to `io`.
+pub fn run_command<B, P>(processor: &mut P, io: &mut dyn PortIo) -> WasmProcessorContinue
+where
+    P: Processor<B, u16, u16, u16>,
+{
+    // The emulator takes its callbacks as `Fn`, but only ever calls one at
+    // a time, so they can share `io` through a `RefCell`.
+    let io = RefCell::new(io);
+    let result = processor.run_command(
+        |port, value| io.borrow_mut().output(port, value),
+        |port| io.borrow_mut().input(port),
+    );
+    match result {
+        ProcessorContinue::KeepRunning => WasmProcessorContinue::Continue,
+        ProcessorContinue::Error => WasmProcessorContinue::Error,
+        ProcessorContinue::Halt => WasmProcessorContinue::Halt,
+    }
 }
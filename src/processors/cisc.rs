@@ -1,11 +1,17 @@
+use std::convert::TryFrom;
+
 use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsValue;
 
+use crate::decoder::{self, Instruction};
+use crate::io::PortIo;
+use crate::memory::MemoryLayout;
+use crate::processor::run_command;
 use crate::registers::RegisterState;
+use crate::stack::StackLayout;
 use crate::{MemoryBlock, MemoryType, WasmProcessor, WasmProcessorContinue};
 use monistode_binutils::Executable;
 use monistode_emulator::cisc_processor;
-use monistode_emulator::common::{Processor, ProcessorContinue};
+use monistode_emulator::common::Processor;
 
 #[wasm_bindgen]
 extern "C" {
@@ -13,6 +19,10 @@ extern "C" {
     fn log(s: &str);
 }
 
+// The emulator starts the stack here; it grows down towards address 0.
+const STACK_BASE: u16 = 1024;
+const STACK_LIMIT: u16 = 0;
+
 pub struct CiscProcessorWrapper {
     processor: cisc_processor::CiscProcessor,
 }
@@ -26,66 +36,8 @@ impl CiscProcessorWrapper {
 }
 
 impl WasmProcessor for CiscProcessorWrapper {
-    fn run(
-        &mut self,
-        output: &js_sys::Function,
-        input: &js_sys::Function,
-    ) -> WasmProcessorContinue {
-        let result = self.processor.run_command(
-            |port, value| {
-                let _ = output.call2(
-                    &JsValue::NULL,
-                    &JsValue::from_f64(port as f64),
-                    &JsValue::from_f64(value as f64),
-                );
-            },
-            |port| {
-                let value = input.call1(&JsValue::NULL, &JsValue::from_f64(port as f64));
-                if let Ok(value) = value {
-                    value.as_f64().unwrap() as u16
-                } else {
-                    0
-                }
-            },
-        );
-        match result {
-            ProcessorContinue::KeepRunning => WasmProcessorContinue::Continue,
-            ProcessorContinue::Error => WasmProcessorContinue::Error,
-            ProcessorContinue::Halt => WasmProcessorContinue::Halt,
-        }
-    }
-
-    fn run_n(
-        &mut self,
-        output: &js_sys::Function,
-        input: &js_sys::Function,
-        n: usize,
-    ) -> WasmProcessorContinue {
-        for _ in 0..n {
-            let result = self.processor.run_command(
-                |port, value| {
-                    let _ = output.call2(
-                        &JsValue::NULL,
-                        &JsValue::from_f64(port as f64),
-                        &JsValue::from_f64(value as f64),
-                    );
-                },
-                |port| {
-                    let value = input.call1(&JsValue::NULL, &JsValue::from_f64(port as f64));
-                    if let Ok(value) = value {
-                        value.as_f64().unwrap() as u16
-                    } else {
-                        0
-                    }
-                },
-            );
-            match result {
-                ProcessorContinue::KeepRunning => {}
-                ProcessorContinue::Error => return WasmProcessorContinue::Error,
-                ProcessorContinue::Halt => return WasmProcessorContinue::Halt,
-            }
-        }
-        WasmProcessorContinue::Continue
+    fn run(&mut self, io: &mut dyn PortIo) -> WasmProcessorContinue {
+        run_command(&mut self.processor, io)
     }
 
     fn get_memory(&mut self) -> Vec<MemoryBlock> {
@@ -93,7 +45,7 @@ impl WasmProcessor for CiscProcessorWrapper {
 
         let mut memory = Vec::new();
         for value in self.processor.memory.memory.iter() {
-            memory.push((*value).into());
+            memory.push(*value);
         }
         result.push(MemoryBlock {
             memory_type: MemoryType::Text,
@@ -116,50 +68,88 @@ impl WasmProcessor for CiscProcessorWrapper {
         }
     }
 
-    fn get_registers(&mut self) -> Vec<RegisterState> {
-        let mut result = Vec::new();
+    fn read_memory(&self, mem_type: MemoryType, index: usize) -> Option<u8> {
+        match mem_type {
+            MemoryType::Text => self.processor.memory.memory.get(index).copied(),
+            MemoryType::Data => None,
+        }
+    }
 
-        result.push(RegisterState::new(
-            "PC".to_string(),
-            self.processor.registers.pc.into(),
-        ));
-        result.push(RegisterState::new(
-            "FR".to_string(),
-            self.processor.registers.fr.0.into(),
-        ));
-        result.push(RegisterState::new(
-            "SP".to_string(),
-            self.processor.registers.sp.into(),
-        ));
-        result.push(RegisterState::new(
-            "BP".to_string(),
-            self.processor.registers.bp.into(),
-        ));
-        result.push(RegisterState::new(
-            "R00".to_string(),
-            self.processor.registers.r[0].into(),
-        ));
-        result.push(RegisterState::new(
-            "R01".to_string(),
-            self.processor.registers.r[1].into(),
-        ));
-        result.push(RegisterState::new(
-            "R10".to_string(),
-            self.processor.registers.r[2].into(),
-        ));
-        result.push(RegisterState::new(
-            "R11".to_string(),
-            self.processor.registers.r[3].into(),
-        ));
+    fn memory_layout(&self, mem_type: MemoryType) -> Option<MemoryLayout> {
+        match mem_type {
+            MemoryType::Text => Some(MemoryLayout {
+                size: self.processor.memory.memory.len(),
+                cell_bits: 8,
+                word_cells: 2,
+            }),
+            MemoryType::Data => None,
+        }
+    }
 
-        result
+    fn get_registers(&mut self) -> Vec<RegisterState> {
+        vec![
+            RegisterState::new("PC".to_string(), self.processor.registers.pc),
+            RegisterState::new("FR".to_string(), self.processor.registers.fr.0.into()),
+            RegisterState::new("SP".to_string(), self.processor.registers.sp),
+            RegisterState::new("BP".to_string(), self.processor.registers.bp),
+            RegisterState::new("R00".to_string(), self.processor.registers.r[0]),
+            RegisterState::new("R01".to_string(), self.processor.registers.r[1]),
+            RegisterState::new("R10".to_string(), self.processor.registers.r[2]),
+            RegisterState::new("R11".to_string(), self.processor.registers.r[3]),
+        ]
+    }
+
+    fn set_register(&mut self, name: &str, value: u16) -> bool {
+        let registers = &mut self.processor.registers;
+        match name {
+            "PC" => registers.pc = value,
+            "FR" => match u8::try_from(value) {
+                Ok(value) => registers.fr.0 = value,
+                Err(_) => return false,
+            },
+            "SP" => registers.sp = value,
+            "BP" => registers.bp = value,
+            "R00" => registers.r[0] = value,
+            "R01" => registers.r[1] = value,
+            "R10" => registers.r[2] = value,
+            "R11" => registers.r[3] = value,
+            _ => return false,
+        }
+        true
     }
 
-    fn load_executable(&mut self, binary: &[u8]) -> Result<(), String> {
-        unimplemented!()
+    fn load_executable(&mut self, _executable: &Executable) -> Result<(), String> {
+        // binutils has no CISC architecture to build executables for, and
+        // the emulator's own loader is unimplemented; programs have to be
+        // written into memory instead.
+        Err("Executables can't target the CISC processor yet".to_string())
     }
 
     fn peek_stack(&mut self, n: u8) -> u16 {
         self.processor.peek_stack(n)
     }
+
+    fn pc(&self) -> u16 {
+        self.processor.registers.pc
+    }
+
+    fn stack_pointer(&self) -> u16 {
+        self.processor.registers.sp
+    }
+
+    fn decode(&self, address: u16) -> Option<Instruction> {
+        decoder::cisc::decode(address, |address| self.processor.memory[address as usize])
+    }
+
+    fn stack_layouts(&self) -> Vec<StackLayout> {
+        vec![StackLayout {
+            name: "SP",
+            memory_type: MemoryType::Text,
+            pointer: self.processor.registers.sp,
+            base_pointer: Some(self.processor.registers.bp),
+            base: STACK_BASE,
+            limit: STACK_LIMIT,
+            grows_down: true,
+        }]
+    }
 }
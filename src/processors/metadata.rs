@@ -0,0 +1,418 @@
+//! Descriptions of the instruction sets, so that front ends can lay out
+//! their registers, memories and opcode reference without knowing the
+//! processors in advance.
+
+use monistode_binutils::Architecture;
+use wasm_bindgen::prelude::*;
+
+use super::{create_processor, ProcessorType};
+use crate::decoder::{self, Instruction, OpcodeInfo, Operand};
+use crate::memory::MemoryType;
+
+/// The bits of the flag register, the same on every processor.
+pub const FLAGS: [(&str, &str, u16); 4] = [
+    ("CF", "Carry", 1),
+    ("ZF", "Zero", 2),
+    ("OF", "Overflow", 4),
+    ("SF", "Sign", 8),
+];
+
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct RegisterInfo {
+    name: String,
+    bits: u32,
+}
+
+#[wasm_bindgen]
+impl RegisterInfo {
+    #[wasm_bindgen]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[wasm_bindgen]
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct MemoryInfo {
+    memory_type: MemoryType,
+    size: usize,
+    cell_bits: u32,
+    word_cells: usize,
+}
+
+#[wasm_bindgen]
+impl MemoryInfo {
+    #[wasm_bindgen]
+    pub fn memory_type(&self) -> MemoryType {
+        self.memory_type
+    }
+
+    /// How many cells the memory has.
+    #[wasm_bindgen]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    #[wasm_bindgen]
+    pub fn cell_bits(&self) -> u32 {
+        self.cell_bits
+    }
+
+    /// How many cells a 16-bit word takes up.
+    #[wasm_bindgen]
+    pub fn word_cells(&self) -> usize {
+        self.word_cells
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct FlagInfo {
+    name: String,
+    description: String,
+    mask: u16,
+}
+
+#[wasm_bindgen]
+impl FlagInfo {
+    #[wasm_bindgen]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[wasm_bindgen]
+    pub fn description(&self) -> String {
+        self.description.clone()
+    }
+
+    /// The bit of the `FR` register holding the flag.
+    #[wasm_bindgen]
+    pub fn mask(&self) -> u16 {
+        self.mask
+    }
+}
+
+/// One entry of an opcode table. An instruction starts with a text cell
+/// whose `opcode_mask` bits equal those of `first_cell`; its other bits
+/// and the cells after it hold the operands.
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct OpcodeEntry {
+    opcode: u8,
+    mnemonic: String,
+    operands: Vec<String>,
+    kind: String,
+    length: u16,
+    first_cell: u8,
+    opcode_mask: u8,
+    cell_bits: u32,
+}
+
+#[wasm_bindgen]
+impl OpcodeEntry {
+    #[wasm_bindgen]
+    pub fn opcode(&self) -> u8 {
+        self.opcode
+    }
+
+    #[wasm_bindgen]
+    pub fn mnemonic(&self) -> String {
+        self.mnemonic.clone()
+    }
+
+    /// The operands in assembly order: `reg`, `imm`, `addr`, `port`, with
+    /// brackets around memory operands, or the register an accumulator
+    /// instruction always uses, such as `ACC`.
+    #[wasm_bindgen]
+    pub fn operands(&self) -> Vec<String> {
+        self.operands.clone()
+    }
+
+    /// What the instruction does to the flow of control: `halt`, `call`,
+    /// `return`, `jump`, `branch`, `input`, `output` or `other`.
+    #[wasm_bindgen]
+    pub fn kind(&self) -> String {
+        self.kind.clone()
+    }
+
+    /// The length of the instruction in text cells.
+    #[wasm_bindgen]
+    pub fn length(&self) -> u16 {
+        self.length
+    }
+
+    #[wasm_bindgen]
+    pub fn first_cell(&self) -> u8 {
+        self.first_cell
+    }
+
+    #[wasm_bindgen]
+    pub fn opcode_mask(&self) -> u8 {
+        self.opcode_mask
+    }
+
+    /// The bits of the first cell, most significant first, with `x` for
+    /// the ones holding operands, such as `000001xx`.
+    #[wasm_bindgen]
+    pub fn encoding(&self) -> String {
+        (0..self.cell_bits)
+            .rev()
+            .map(
+                |bit| match (self.opcode_mask >> bit & 1, self.first_cell >> bit & 1) {
+                    (0, _) => 'x',
+                    (_, 0) => '0',
+                    _ => '1',
+                },
+            )
+            .collect()
+    }
+}
+
+#[wasm_bindgen]
+pub struct ProcessorMetadata {
+    name: String,
+    description: String,
+    type_: ProcessorType,
+    registers: Vec<RegisterInfo>,
+    memories: Vec<MemoryInfo>,
+    opcodes: Vec<OpcodeEntry>,
+}
+
+#[wasm_bindgen]
+impl ProcessorMetadata {
+    #[wasm_bindgen]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[wasm_bindgen]
+    pub fn description(&self) -> String {
+        self.description.clone()
+    }
+
+    #[wasm_bindgen]
+    pub fn type_(&self) -> ProcessorType {
+        self.type_.clone()
+    }
+
+    /// The registers in the order `Runner::get_registers` returns them.
+    #[wasm_bindgen]
+    pub fn registers(&self) -> Vec<RegisterInfo> {
+        self.registers.clone()
+    }
+
+    #[wasm_bindgen]
+    pub fn memories(&self) -> Vec<MemoryInfo> {
+        self.memories.clone()
+    }
+
+    #[wasm_bindgen]
+    pub fn flags(&self) -> Vec<FlagInfo> {
+        FLAGS
+            .iter()
+            .map(|(name, description, mask)| FlagInfo {
+                name: name.to_string(),
+                description: description.to_string(),
+                mask: *mask,
+            })
+            .collect()
+    }
+
+    /// The lowest port IN and OUT can address.
+    #[wasm_bindgen]
+    pub fn min_port(&self) -> u16 {
+        0
+    }
+
+    /// The highest port IN and OUT can address.
+    #[wasm_bindgen]
+    pub fn max_port(&self) -> u16 {
+        u16::MAX
+    }
+
+    /// The binutils architecture whose executables the processor runs,
+    /// `stack`, `accumulator` or `risc`, if there is one.
+    #[wasm_bindgen]
+    pub fn architecture(&self) -> Option<String> {
+        let name = match self.type_.architecture()? {
+            Architecture::Stack => "stack",
+            Architecture::Accumulator => "accumulator",
+            Architecture::Risc => "risc",
+        };
+        Some(name.to_string())
+    }
+
+    #[wasm_bindgen]
+    pub fn opcodes(&self) -> Vec<OpcodeEntry> {
+        self.opcodes.clone()
+    }
+}
+
+impl ProcessorMetadata {
+    fn new(name: &str, description: &str, type_: ProcessorType) -> Self {
+        let mut processor = create_processor(type_.clone());
+        let registers = processor
+            .get_registers()
+            .iter()
+            .map(|register| RegisterInfo {
+                name: register.name(),
+                bits: register_bits(&type_, &register.name()),
+            })
+            .collect();
+        let memories = [MemoryType::Text, MemoryType::Data]
+            .iter()
+            .copied()
+            .filter_map(|memory_type| {
+                let layout = processor.memory_layout(memory_type)?;
+                Some(MemoryInfo {
+                    memory_type,
+                    size: layout.size,
+                    cell_bits: layout.cell_bits,
+                    word_cells: layout.word_cells,
+                })
+            })
+            .collect();
+        ProcessorMetadata {
+            name: name.to_string(),
+            description: description.to_string(),
+            opcodes: opcode_table(&type_),
+            type_,
+            registers,
+            memories,
+        }
+    }
+}
+
+/// Every register is a 16-bit word, except that the flag register is a
+/// single byte on all but the stack processor.
+fn register_bits(processor_type: &ProcessorType, name: &str) -> u32 {
+    match (processor_type, name) {
+        (ProcessorType::Stack, _) => 16,
+        (_, "FR") => 8,
+        _ => 16,
+    }
+}
+
+fn placeholder(operand: &Operand) -> String {
+    match operand {
+        Operand::Register(_) => "reg",
+        Operand::Immediate(_) => "imm",
+        Operand::Memory(_) => "[addr]",
+        Operand::RegisterIndirect(_) => "[reg]",
+        Operand::RegisterOffset(..) => "reg+imm",
+        Operand::RegisterOffsetIndirect(..) => "[reg+imm]",
+        Operand::Target(_) => "addr",
+        Operand::Port(_) => "port",
+    }
+    .to_string()
+}
+
+/// How an instruction set puts its opcodes into the first text cell.
+struct Encoding {
+    shift: u32,
+    cell_bits: u32,
+    opcode_mask: u8,
+}
+
+/// Lists `table`, decoding every opcode followed by zeros to find out the
+/// length and operands of its instructions. The first `fixed` operands
+/// are spelled out instead of replaced with placeholders.
+fn entries<F>(
+    table: &'static [OpcodeInfo<F>],
+    encoding: Encoding,
+    fixed: impl Fn(&F) -> usize,
+    decode: impl Fn(u8) -> Option<Instruction>,
+) -> Vec<OpcodeEntry> {
+    table
+        .iter()
+        .map(|info| {
+            let first_cell = info.opcode << encoding.shift;
+            let instruction = decode(first_cell).expect("every opcode in the table decodes");
+            let fixed = fixed(&info.format);
+            let operands = instruction
+                .operands
+                .iter()
+                .enumerate()
+                .map(|(i, operand)| {
+                    if i < fixed {
+                        operand.to_string()
+                    } else {
+                        placeholder(operand)
+                    }
+                })
+                .collect();
+            OpcodeEntry {
+                opcode: info.opcode,
+                mnemonic: info.mnemonic.to_string(),
+                operands,
+                kind: info.kind.name().to_string(),
+                length: instruction.length,
+                first_cell,
+                opcode_mask: encoding.opcode_mask,
+                cell_bits: encoding.cell_bits,
+            }
+        })
+        .collect()
+}
+
+fn alone(first_cell: u8) -> impl Fn(u16) -> u8 {
+    move |address| if address == 0 { first_cell } else { 0 }
+}
+
+fn opcode_table(processor_type: &ProcessorType) -> Vec<OpcodeEntry> {
+    let bytes = |shift| Encoding {
+        shift,
+        cell_bits: 8,
+        opcode_mask: 0xff << shift,
+    };
+    match processor_type {
+        ProcessorType::Stack => entries(
+            decoder::stack::OPCODES,
+            Encoding {
+                shift: 0,
+                cell_bits: 6,
+                opcode_mask: 0b111111,
+            },
+            |_| 0,
+            |cell| decoder::stack::decode(0, alone(cell)),
+        ),
+        ProcessorType::Acc => entries(
+            decoder::acc::OPCODES,
+            bytes(0),
+            |format| format.fixed.len(),
+            |cell| decoder::acc::decode(0, alone(cell)),
+        ),
+        ProcessorType::Risc => entries(
+            decoder::risc::OPCODES,
+            bytes(2),
+            |_| 0,
+            |cell| decoder::risc::decode(0, alone(cell)),
+        ),
+        ProcessorType::Cisc => entries(
+            decoder::cisc::OPCODES,
+            bytes(0),
+            |_| 0,
+            |cell| decoder::cisc::decode(0, alone(cell)),
+        ),
+    }
+}
+
+#[wasm_bindgen]
+pub fn available_processors() -> Vec<ProcessorMetadata> {
+    vec![
+        ProcessorMetadata::new("Stack", "Stack-based processor", ProcessorType::Stack),
+        ProcessorMetadata::new(
+            "Accumulator",
+            "Accumulator-based processor",
+            ProcessorType::Acc,
+        ),
+        ProcessorMetadata::new("RISC", "RISC processor", ProcessorType::Risc),
+        ProcessorMetadata::new("CISC", "CISC processor", ProcessorType::Cisc),
+    ]
+}
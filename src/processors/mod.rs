@@ -1,12 +1,20 @@
+use monistode_binutils::Architecture;
 use wasm_bindgen::prelude::*;
 
+use crate::inspect::parse_executable;
 use crate::processor::WasmProcessor;
 
 pub mod acc;
 pub mod cisc;
+mod metadata;
 pub mod risc;
 pub mod stack;
 
+pub(crate) use metadata::FLAGS;
+pub use metadata::{
+    available_processors, FlagInfo, MemoryInfo, OpcodeEntry, ProcessorMetadata, RegisterInfo,
+};
+
 #[wasm_bindgen]
 #[derive(Clone)]
 pub enum ProcessorType {
@@ -16,6 +24,28 @@ pub enum ProcessorType {
     Cisc,
 }
 
+impl From<Architecture> for ProcessorType {
+    fn from(architecture: Architecture) -> Self {
+        match architecture {
+            Architecture::Stack => ProcessorType::Stack,
+            Architecture::Accumulator => ProcessorType::Acc,
+            Architecture::Risc => ProcessorType::Risc,
+        }
+    }
+}
+
+impl ProcessorType {
+    /// The binutils architecture of the executables the processor runs.
+    pub fn architecture(&self) -> Option<Architecture> {
+        match self {
+            ProcessorType::Stack => Some(Architecture::Stack),
+            ProcessorType::Acc => Some(Architecture::Accumulator),
+            ProcessorType::Risc => Some(Architecture::Risc),
+            ProcessorType::Cisc => None,
+        }
+    }
+}
+
 pub fn create_processor(processor_type: ProcessorType) -> Box<dyn WasmProcessor> {
     match processor_type {
         ProcessorType::Stack => {
@@ -37,53 +67,8 @@ pub fn create_processor(processor_type: ProcessorType) -> Box<dyn WasmProcessor>
     }
 }
 
+/// The processor an executable was built for, read from its header.
 #[wasm_bindgen]
-pub struct ProcessorMetadata {
-    name: String,
-    description: String,
-    type_: ProcessorType,
-}
-
-#[wasm_bindgen]
-impl ProcessorMetadata {
-    #[wasm_bindgen]
-    pub fn name(&self) -> String {
-        self.name.clone()
-    }
-
-    #[wasm_bindgen]
-    pub fn description(&self) -> String {
-        self.description.clone()
-    }
-
-    #[wasm_bindgen]
-    pub fn type_(&self) -> ProcessorType {
-        self.type_.clone()
-    }
-}
-
-#[wasm_bindgen]
-pub fn available_processors() -> Vec<ProcessorMetadata> {
-    vec![
-        ProcessorMetadata {
-            name: "Stack".to_string(),
-            description: "Stack-based processor".to_string(),
-            type_: ProcessorType::Stack,
-        },
-        ProcessorMetadata {
-            name: "Accumulator".to_string(),
-            description: "Accumulator-based processor".to_string(),
-            type_: ProcessorType::Acc,
-        },
-        ProcessorMetadata {
-            name: "RISC".to_string(),
-            description: "RISC processor".to_string(),
-            type_: ProcessorType::Risc,
-        },
-        ProcessorMetadata {
-            name: "CISC".to_string(),
-            description: "CISC processor".to_string(),
-            type_: ProcessorType::Cisc,
-        },
-    ]
+pub fn detect_processor_type(program: &[u8]) -> Result<ProcessorType, String> {
+    Ok(parse_executable(program)?.architecture().into())
 }
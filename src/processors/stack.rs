@@ -1,13 +1,16 @@
 use monistode_binutils::Architecture;
-use monistode_binutils::Serializable;
 use ux::u6;
 use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsValue;
 
+use crate::decoder::{self, Instruction};
+use crate::io::PortIo;
+use crate::memory::MemoryLayout;
+use crate::processor::run_command;
 use crate::registers::RegisterState;
+use crate::stack::StackLayout;
 use crate::{MemoryBlock, MemoryType, WasmProcessor, WasmProcessorContinue};
 use monistode_binutils::Executable;
-use monistode_emulator::common::{Processor, ProcessorContinue};
+use monistode_emulator::common::Processor;
 use monistode_emulator::stack_processor;
 
 #[wasm_bindgen]
@@ -16,6 +19,13 @@ extern "C" {
     fn log(s: &str);
 }
 
+// Where the emulator starts the two stacks. The register stack grows down
+// towards address 0, the memory stack grows up towards the end of data memory.
+const REGISTER_STACK_BASE: u16 = 256;
+const REGISTER_STACK_LIMIT: u16 = 0;
+const MEMORY_STACK_BASE: u16 = 1024;
+const MEMORY_STACK_LIMIT: u16 = 0xfffe;
+
 pub struct StackProcessorWrapper {
     processor: stack_processor::StackProcessor,
 }
@@ -29,66 +39,8 @@ impl StackProcessorWrapper {
 }
 
 impl WasmProcessor for StackProcessorWrapper {
-    fn run(
-        &mut self,
-        output: &js_sys::Function,
-        input: &js_sys::Function,
-    ) -> WasmProcessorContinue {
-        let result = self.processor.run_command(
-            |port, value| {
-                let _ = output.call2(
-                    &JsValue::NULL,
-                    &JsValue::from_f64(port as f64),
-                    &JsValue::from_f64(value as f64),
-                );
-            },
-            |port| {
-                let value = input.call1(&JsValue::NULL, &JsValue::from_f64(port as f64));
-                if let Ok(value) = value {
-                    value.as_f64().unwrap() as u16
-                } else {
-                    0
-                }
-            },
-        );
-        match result {
-            ProcessorContinue::KeepRunning => WasmProcessorContinue::Continue,
-            ProcessorContinue::Error => WasmProcessorContinue::Error,
-            ProcessorContinue::Halt => WasmProcessorContinue::Halt,
-        }
-    }
-
-    fn run_n(
-        &mut self,
-        output: &js_sys::Function,
-        input: &js_sys::Function,
-        n: usize,
-    ) -> WasmProcessorContinue {
-        for _ in 0..n {
-            let result = self.processor.run_command(
-                |port, value| {
-                    let _ = output.call2(
-                        &JsValue::NULL,
-                        &JsValue::from_f64(port as f64),
-                        &JsValue::from_f64(value as f64),
-                    );
-                },
-                |port| {
-                    let value = input.call1(&JsValue::NULL, &JsValue::from_f64(port as f64));
-                    if let Ok(value) = value {
-                        value.as_f64().unwrap() as u16
-                    } else {
-                        0
-                    }
-                },
-            );
-            match result {
-                ProcessorContinue::KeepRunning => {}
-                ProcessorContinue::Error => return WasmProcessorContinue::Error,
-                ProcessorContinue::Halt => return WasmProcessorContinue::Halt,
-            }
-        }
-        WasmProcessorContinue::Continue
+    fn run(&mut self, io: &mut dyn PortIo) -> WasmProcessorContinue {
+        run_command(&mut self.processor, io)
     }
 
     fn get_memory(&mut self) -> Vec<MemoryBlock> {
@@ -118,54 +70,121 @@ impl WasmProcessor for StackProcessorWrapper {
     fn set_memory(&mut self, mem_type: MemoryType, index: usize, value: u8) -> bool {
         match mem_type {
             MemoryType::Text => {
-                if value > u8::MAX - 1 {
+                // `u6::new` panics on anything wider than six bits
+                if value > 0b111111 {
                     return false;
                 }
-                self.processor.text_memory.memory[index] = u6::new(value);
-                true
-            }
-            MemoryType::Data => {
-                self.processor.data_memory.memory[index] = value;
+                match self.processor.text_memory.memory.get_mut(index) {
+                    Some(cell) => *cell = u6::new(value),
+                    None => return false,
+                }
                 true
             }
+            MemoryType::Data => match self.processor.data_memory.memory.get_mut(index) {
+                Some(cell) => {
+                    *cell = value;
+                    true
+                }
+                None => false,
+            },
         }
     }
 
-    fn get_registers(&mut self) -> Vec<RegisterState> {
-        let mut result = Vec::new();
+    fn read_memory(&self, mem_type: MemoryType, index: usize) -> Option<u8> {
+        match mem_type {
+            MemoryType::Text => self
+                .processor
+                .text_memory
+                .memory
+                .get(index)
+                .map(|value| (*value).into()),
+            MemoryType::Data => self.processor.data_memory.memory.get(index).copied(),
+        }
+    }
 
-        result.push(RegisterState::new(
-            "PC".to_string(),
-            self.processor.registers.pc.into(),
-        ));
-        result.push(RegisterState::new(
-            "FR".to_string(),
-            self.processor.registers.fr.0.into(),
-        ));
-        result.push(RegisterState::new(
-            "TOS".to_string(),
-            self.processor.registers.tos.into(),
-        ));
-        result.push(RegisterState::new(
-            "SP".to_string(),
-            self.processor.registers.sp.into(),
-        ));
+    fn memory_layout(&self, mem_type: MemoryType) -> Option<MemoryLayout> {
+        match mem_type {
+            // Immediates in the text memory are three 6-bit cells wide
+            MemoryType::Text => Some(MemoryLayout {
+                size: self.processor.text_memory.memory.len(),
+                cell_bits: 6,
+                word_cells: 3,
+            }),
+            MemoryType::Data => Some(MemoryLayout {
+                size: self.processor.data_memory.memory.len(),
+                cell_bits: 8,
+                word_cells: 2,
+            }),
+        }
+    }
 
-        result
+    fn get_registers(&mut self) -> Vec<RegisterState> {
+        vec![
+            RegisterState::new("PC".to_string(), self.processor.registers.pc),
+            RegisterState::new("FR".to_string(), self.processor.registers.fr.0),
+            RegisterState::new("TOS".to_string(), self.processor.registers.tos),
+            RegisterState::new("SP".to_string(), self.processor.registers.sp),
+        ]
     }
 
-    fn load_executable(&mut self, binary: &[u8]) -> Result<(), String> {
-        let executable = Executable::deserialize(binary)
-            .map_err(|_| "Failed to load executable")?
-            .1;
+    fn set_register(&mut self, name: &str, value: u16) -> bool {
+        let registers = &mut self.processor.registers;
+        match name {
+            "PC" => registers.pc = value,
+            "FR" => registers.fr.0 = value,
+            "TOS" => registers.tos = value,
+            "SP" => registers.sp = value,
+            _ => return false,
+        }
+        true
+    }
+
+    fn load_executable(&mut self, executable: &Executable) -> Result<(), String> {
         if !matches!(executable.architecture(), Architecture::Stack) {
             return Err("Invalid architecture".to_string());
         }
         // log(&format!("Executable: {:?}", executable));
-        self.processor.load_executable(&executable)
+        self.processor.load_executable(executable)
     }
 
     fn peek_stack(&mut self, n: u8) -> u16 {
         self.processor.peek_stack(n)
     }
+
+    fn pc(&self) -> u16 {
+        self.processor.registers.pc
+    }
+
+    fn stack_pointer(&self) -> u16 {
+        self.processor.registers.sp
+    }
+
+    fn decode(&self, address: u16) -> Option<Instruction> {
+        decoder::stack::decode(address, |address| {
+            self.processor.text_memory[address as usize].into()
+        })
+    }
+
+    fn stack_layouts(&self) -> Vec<StackLayout> {
+        vec![
+            StackLayout {
+                name: "TOS",
+                memory_type: MemoryType::Data,
+                pointer: self.processor.registers.tos,
+                base_pointer: None,
+                base: REGISTER_STACK_BASE,
+                limit: REGISTER_STACK_LIMIT,
+                grows_down: true,
+            },
+            StackLayout {
+                name: "SP",
+                memory_type: MemoryType::Data,
+                pointer: self.processor.registers.sp,
+                base_pointer: None,
+                base: MEMORY_STACK_BASE,
+                limit: MEMORY_STACK_LIMIT,
+                grows_down: false,
+            },
+        ]
+    }
 }
@@ -0,0 +1,46 @@
+use crate::memory::MemoryBlock;
+use crate::processor::WasmProcessor;
+use crate::registers::RegisterState;
+
+/// A copy of a processor's memory and registers, to put it back into that
+/// state later.
+pub struct MachineState {
+    memory: Vec<MemoryBlock>,
+    registers: Vec<RegisterState>,
+}
+
+impl MachineState {
+    pub fn capture(processor: &mut dyn WasmProcessor) -> MachineState {
+        MachineState {
+            memory: processor.get_memory(),
+            registers: processor.get_registers(),
+        }
+    }
+
+    /// The program counter when the state was captured.
+    pub fn pc(&self) -> Option<u16> {
+        self.registers
+            .iter()
+            .find(|register| register.name() == "PC")
+            .map(|register| register.value())
+    }
+
+    /// Writes the saved state back, touching only the cells that changed.
+    pub fn restore(&self, processor: &mut dyn WasmProcessor) -> Result<(), String> {
+        for block in &self.memory {
+            for (index, value) in block.values.iter().enumerate() {
+                if processor.read_memory(block.memory_type, index) != Some(*value)
+                    && !processor.set_memory(block.memory_type, index, *value)
+                {
+                    return Err(format!("Failed to restore memory at {:#x}", index));
+                }
+            }
+        }
+        for register in &self.registers {
+            if !processor.set_register(&register.name(), register.value()) {
+                return Err(format!("Failed to restore register {}", register.name()));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+use wasm_bindgen::prelude::*;
+
+use crate::decoder::{Instruction, InstructionKind, Operand};
+use crate::memory::MemoryType;
+use crate::processor::WasmProcessor;
+
+/// Where a processor keeps one of its stacks and which way it grows.
+///
+/// Every stack holds 16-bit words. A downward stack's items live at
+/// `pointer..base`, an upward stack's at `base + 2..=pointer`; in both cases
+/// the top of the stack is the word at `pointer`.
+pub struct StackLayout {
+    pub name: &'static str,
+    pub memory_type: MemoryType,
+    pub pointer: u16,
+    pub base_pointer: Option<u16>,
+    pub base: u16,
+    pub limit: u16,
+    pub grows_down: bool,
+}
+
+impl StackLayout {
+    /// Reads the whole stack, top first, so that `values[n]` is what
+    /// `peek_stack(n)` returns.
+    pub fn read<F>(&self, read_word: F) -> Result<StackFrame, String>
+    where
+        F: Fn(MemoryType, usize) -> Result<u16, String>,
+    {
+        let (used, room) = if self.grows_down {
+            (
+                self.base.checked_sub(self.pointer),
+                self.pointer.checked_sub(self.limit),
+            )
+        } else {
+            (
+                self.pointer.checked_sub(self.base),
+                self.limit.checked_sub(self.pointer),
+            )
+        };
+        let Some(used) = used else {
+            return Err(format!(
+                "Stack underflow: {} is {:#06x}, past the stack base {:#06x}",
+                self.name, self.pointer, self.base
+            ));
+        };
+        if room.is_none() {
+            return Err(format!(
+                "Stack overflow: {} is {:#06x}, past the stack limit {:#06x}",
+                self.name, self.pointer, self.limit
+            ));
+        }
+
+        let values = (0..used / 2)
+            .map(|i| {
+                let address = if self.grows_down {
+                    self.pointer + i * 2
+                } else {
+                    self.pointer - i * 2
+                };
+                read_word(self.memory_type, address as usize)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(StackFrame {
+            name: self.name.to_string(),
+            memory_type: self.memory_type,
+            values,
+            pointer: self.pointer,
+            base_pointer: self.base_pointer,
+            base: self.base,
+            limit: self.limit,
+            grows_down: self.grows_down,
+        })
+    }
+}
+
+/// How many words an instruction pops off a stack and then pushes onto it.
+pub struct StackEffect {
+    pub stack: &'static str,
+    pub pops: u16,
+    pub pushes: u16,
+}
+
+impl StackEffect {
+    fn new(stack: &'static str, pops: u16, pushes: u16) -> Self {
+        StackEffect {
+            stack,
+            pops,
+            pushes,
+        }
+    }
+
+    /// Where the pointer of the stack `layout` describes is once the pops
+    /// are done.
+    pub fn popped_pointer(&self, layout: &StackLayout) -> u16 {
+        if layout.grows_down {
+            layout.pointer.wrapping_add(self.pops * 2)
+        } else {
+            layout.pointer.wrapping_sub(self.pops * 2)
+        }
+    }
+
+    /// Where the pointer is once the pushes are done as well.
+    pub fn pushed_pointer(&self, layout: &StackLayout) -> u16 {
+        if layout.grows_down {
+            self.popped_pointer(layout).wrapping_sub(self.pushes * 2)
+        } else {
+            self.popped_pointer(layout).wrapping_add(self.pushes * 2)
+        }
+    }
+
+    /// The addresses of the words the pops read, in the order they are
+    /// read: each pop reads at the pointer before moving it.
+    pub fn popped_words(&self, layout: &StackLayout) -> Vec<u16> {
+        (0..self.pops)
+            .map(|i| match layout.grows_down {
+                true => layout.pointer.wrapping_add(i * 2),
+                false => layout.pointer.wrapping_sub(i * 2),
+            })
+            .collect()
+    }
+
+    /// The addresses of the words the pushes write, in the order they are
+    /// written: each push moves the pointer before writing at it.
+    pub fn pushed_words(&self, layout: &StackLayout) -> Vec<u16> {
+        let popped = self.popped_pointer(layout);
+        (1..=self.pushes)
+            .map(|i| match layout.grows_down {
+                true => popped.wrapping_sub(i * 2),
+                false => popped.wrapping_add(i * 2),
+            })
+            .collect()
+    }
+}
+
+/// What the instruction about to run does to each stack. The stack
+/// processor keeps its operands on the `TOS` stack and return addresses on
+/// `SP`; the others only push and pop `SP`.
+pub fn stack_effects(
+    processor: &mut dyn WasmProcessor,
+    stack_machine: bool,
+    instruction: &Instruction,
+) -> Vec<StackEffect> {
+    let immediate = !instruction.operands.is_empty();
+    if !stack_machine {
+        let (pops, pushes) = match (instruction.mnemonic, instruction.kind) {
+            ("push", _) | ("pushf", _) | ("enter", _) | (_, InstructionKind::Call) => (0, 1),
+            ("pop", _) | ("popf", _) | (_, InstructionKind::Return) => (1, 0),
+            _ => return vec![],
+        };
+        return vec![StackEffect::new("SP", pops, pushes)];
+    }
+    let register = |pops, pushes| StackEffect::new("TOS", pops, pushes);
+    let memory = |pops, pushes| StackEffect::new("SP", pops, pushes);
+    match instruction.mnemonic {
+        "load" | "not" => vec![register(u16::from(!immediate), 1)],
+        "lsh" | "rsh" => vec![register(1, 1)],
+        "loadf" | "mov" | "in" => vec![register(0, 1)],
+        "store" => vec![register(if immediate { 1 } else { 2 }, 0)],
+        "storef" | "out" => vec![register(1, 0)],
+        "swap" => vec![register(2, 2)],
+        "dup" => vec![register(1, 2)],
+        "dup2" => vec![register(2, 3)],
+        "push" => vec![register(1, 0), memory(0, 1)],
+        "pushf" => vec![memory(0, 1)],
+        "pop" => vec![memory(1, 0), register(0, 1)],
+        "popf" => vec![memory(1, 0)],
+        "add" | "sub" | "mul" | "div" | "and" | "or" | "xor" => vec![register(2, 1)],
+        "cmpe" | "cmpb" => vec![register(if immediate { 1 } else { 2 }, 1)],
+        "call" => vec![register(u16::from(!immediate), 0), memory(0, 1)],
+        "ret" => vec![memory(1, 0)],
+        "jmp" => vec![register(u16::from(!immediate), 0)],
+        // A taken `jc` also pops its target.
+        "jc" if !immediate && processor.peek_stack(0) == u16::MAX => vec![register(2, 0)],
+        "jc" => vec![register(1, 0)],
+        _ => vec![],
+    }
+}
+
+/// The data memory address the stack processor's `load`, `store` and
+/// `storef` about to run take from the register stack.
+pub fn stack_address(
+    processor: &mut dyn WasmProcessor,
+    stack_machine: bool,
+    instruction: &Instruction,
+) -> Option<u16> {
+    if !stack_machine {
+        return None;
+    }
+    let depth = match (instruction.mnemonic, instruction.operands.first()) {
+        ("load", None) | ("store", Some(Operand::Immediate(_))) | ("storef", None) => 0,
+        ("store", None) => 1,
+        _ => return None,
+    };
+    Some(processor.peek_stack(depth))
+}
+
+#[wasm_bindgen]
+pub struct StackFrame {
+    name: String,
+    memory_type: MemoryType,
+    values: Vec<u16>,
+    pointer: u16,
+    base_pointer: Option<u16>,
+    base: u16,
+    limit: u16,
+    grows_down: bool,
+}
+
+#[wasm_bindgen]
+impl StackFrame {
+    /// The name of the register pointing at the top of this stack.
+    #[wasm_bindgen]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[wasm_bindgen]
+    pub fn memory_type(&self) -> MemoryType {
+        self.memory_type
+    }
+
+    /// The stack contents, top of the stack first.
+    #[wasm_bindgen]
+    pub fn values(&self) -> Vec<u16> {
+        self.values.clone()
+    }
+
+    #[wasm_bindgen]
+    pub fn pointer(&self) -> u16 {
+        self.pointer
+    }
+
+    /// The frame base pointer, on processors that have one.
+    #[wasm_bindgen]
+    pub fn base_pointer(&self) -> Option<u16> {
+        self.base_pointer
+    }
+
+    #[wasm_bindgen]
+    pub fn base(&self) -> u16 {
+        self.base
+    }
+
+    #[wasm_bindgen]
+    pub fn limit(&self) -> u16 {
+        self.limit
+    }
+
+    #[wasm_bindgen]
+    pub fn grows_down(&self) -> bool {
+        self.grows_down
+    }
+}
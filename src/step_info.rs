@@ -0,0 +1,153 @@
+//! What a single step did to the machine, so that front ends can animate
+//! and explain it without diffing the whole state themselves.
+
+use wasm_bindgen::prelude::*;
+
+use crate::io::PortAccess;
+use crate::memory::MemoryType;
+use crate::processor::WasmProcessorContinue;
+
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegisterChange {
+    pub(crate) name: String,
+    pub(crate) old: u16,
+    pub(crate) new: u16,
+}
+
+#[wasm_bindgen]
+impl RegisterChange {
+    #[wasm_bindgen]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[wasm_bindgen]
+    pub fn old_value(&self) -> u16 {
+        self.old
+    }
+
+    #[wasm_bindgen]
+    pub fn new_value(&self) -> u16 {
+        self.new
+    }
+}
+
+/// A memory access: a word an instruction read or wrote.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub(crate) memory_type: MemoryType,
+    pub(crate) address: u16,
+    pub(crate) value: u16,
+    pub(crate) previous: Option<u16>,
+}
+
+#[wasm_bindgen]
+impl MemoryAccess {
+    #[wasm_bindgen]
+    pub fn memory_type(&self) -> MemoryType {
+        self.memory_type
+    }
+
+    #[wasm_bindgen]
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    /// The value read, or the one written.
+    #[wasm_bindgen]
+    pub fn value(&self) -> u16 {
+        self.value
+    }
+
+    /// What a written word held before.
+    #[wasm_bindgen]
+    pub fn previous(&self) -> Option<u16> {
+        self.previous
+    }
+}
+
+#[wasm_bindgen]
+pub struct StepInfo {
+    pub(crate) status: WasmProcessorContinue,
+    pub(crate) pc_before: u16,
+    pub(crate) pc_after: u16,
+    pub(crate) instruction: Option<String>,
+    pub(crate) kind: Option<String>,
+    pub(crate) registers: Vec<RegisterChange>,
+    pub(crate) reads: Vec<MemoryAccess>,
+    pub(crate) writes: Vec<MemoryAccess>,
+    pub(crate) ports: Vec<PortAccess>,
+    pub(crate) branch_taken: Option<bool>,
+}
+
+#[wasm_bindgen]
+impl StepInfo {
+    #[wasm_bindgen]
+    pub fn status(&self) -> WasmProcessorContinue {
+        self.status
+    }
+
+    /// The address of the instruction that ran, or the program counter if
+    /// none did.
+    #[wasm_bindgen]
+    pub fn pc_before(&self) -> u16 {
+        self.pc_before
+    }
+
+    #[wasm_bindgen]
+    pub fn pc_after(&self) -> u16 {
+        self.pc_after
+    }
+
+    /// The instruction that ran, disassembled, if one did: a step can stop
+    /// before running anything, at a breakpoint on an interrupt handler or
+    /// when the budget runs out.
+    #[wasm_bindgen]
+    pub fn instruction(&self) -> Option<String> {
+        self.instruction.clone()
+    }
+
+    /// What the instruction does to the flow of control, named like
+    /// `OpcodeEntry::kind`.
+    #[wasm_bindgen]
+    pub fn kind(&self) -> Option<String> {
+        self.kind.clone()
+    }
+
+    /// The registers whose values changed.
+    #[wasm_bindgen]
+    pub fn registers(&self) -> Vec<RegisterChange> {
+        self.registers.clone()
+    }
+
+    /// The words the instruction read, with the values there before it ran,
+    /// unless it wrote them: those its source operands pointed at, those it
+    /// popped off stacks, such as RET's return address, and the one the
+    /// stack processor's `load` takes the address of from the stack.
+    #[wasm_bindgen]
+    pub fn memory_reads(&self) -> Vec<MemoryAccess> {
+        self.reads.clone()
+    }
+
+    /// The words the instruction wrote, in either memory, even with the
+    /// value they already held: those its destination operands pointed at,
+    /// those it pushed, and a return address pushed for an interrupt.
+    #[wasm_bindgen]
+    pub fn memory_writes(&self) -> Vec<MemoryAccess> {
+        self.writes.clone()
+    }
+
+    #[wasm_bindgen]
+    pub fn ports(&self) -> Vec<PortAccess> {
+        self.ports.clone()
+    }
+
+    /// For jumps, branches, calls and returns, whether execution went
+    /// somewhere other than the next instruction.
+    #[wasm_bindgen]
+    pub fn branch_taken(&self) -> Option<bool> {
+        self.branch_taken
+    }
+}
@@ -0,0 +1,70 @@
+use monistode_binutils::Executable;
+
+use crate::inspect::segment_memory;
+use crate::memory::{MemoryLayout, MemoryType};
+
+pub struct SymbolEntry {
+    pub name: String,
+    pub memory_type: MemoryType,
+    pub address: u16,
+}
+
+/// The symbols of the loaded executable, with their addresses converted from
+/// bit offsets within a segment to cell addresses in the processor's memory.
+#[derive(Default)]
+pub struct SymbolTable {
+    entries: Vec<SymbolEntry>,
+}
+
+impl SymbolTable {
+    /// `layout` tells the width of the cells of each memory; executable
+    /// segments go into text memory, the rest into data memory if the
+    /// processor has a separate one.
+    pub fn from_executable<F>(executable: &Executable, layout: F) -> SymbolTable
+    where
+        F: Fn(MemoryType) -> Option<MemoryLayout>,
+    {
+        let mut entries = Vec::new();
+        for segment in executable.segments() {
+            let memory_type = segment_memory(segment, layout(MemoryType::Data).is_some());
+            let Some(cell_bits) = layout(memory_type).map(|layout| layout.cell_bits) else {
+                continue;
+            };
+            for symbol in segment.symbols() {
+                let address =
+                    segment.address_space_start + (symbol.address.0 as u64) / u64::from(cell_bits);
+                entries.push(SymbolEntry {
+                    name: symbol.name,
+                    memory_type,
+                    address: address as u16,
+                });
+            }
+        }
+        entries.sort_by_key(|entry| entry.address);
+        SymbolTable { entries }
+    }
+
+    /// Names a text address after the closest symbol at or before it, as
+    /// `name` or `name+0x4`.
+    pub fn describe(&self, address: u16) -> Option<String> {
+        let entry = self
+            .entries
+            .iter()
+            .filter(|entry| matches!(entry.memory_type, MemoryType::Text))
+            .take_while(|entry| entry.address <= address)
+            .last()?;
+        Some(match address - entry.address {
+            0 => entry.name.clone(),
+            offset => format!("{}+{:#x}", entry.name, offset),
+        })
+    }
+
+    pub fn entries(&self) -> &[SymbolEntry] {
+        &self.entries
+    }
+
+    /// Looks a symbol up by name.
+    pub fn find(&self, name: &str) -> Option<&SymbolEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+}
@@ -0,0 +1,534 @@
+//! Several processors running in lockstep, sharing part of their memory
+//! and passing values to each other through ports.
+
+use std::collections::VecDeque;
+
+use wasm_bindgen::prelude::*;
+
+use crate::decoder::{InstructionKind, Operand};
+use crate::io::{IoFailure, JsPortIo, PortIo};
+use crate::memory::{MemoryBlock, MemoryType};
+use crate::processor::WasmProcessorContinue;
+use crate::processors::ProcessorType;
+use crate::registers::RegisterState;
+use crate::Runner;
+
+/// How `System` picks the core to run the next instruction.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchedulePolicy {
+    /// Takes turns in core order, each core running a quantum of
+    /// instructions.
+    RoundRobin,
+    /// Picks a core at random for every instruction, from a seeded
+    /// generator so runs can be repeated.
+    Random,
+}
+
+struct Core {
+    runner: Runner,
+    halted: bool,
+    /// The version of the shared memory this core has seen.
+    synced: u64,
+}
+
+struct SharedRegion {
+    memory_type: MemoryType,
+    start: usize,
+    /// The width of the cells, which every core must have there.
+    cell_bits: u32,
+    cells: Vec<u8>,
+}
+
+/// A FIFO from one core's OUT port to another core's IN port.
+struct Channel {
+    from: (usize, u16),
+    to: (usize, u16),
+    queue: VecDeque<u16>,
+}
+
+/// The ports of one core: channel ports go to other cores, everything
+/// else to the host.
+struct CoreIo<'a> {
+    core: usize,
+    host: &'a mut dyn PortIo,
+    channels: &'a mut [Channel],
+}
+
+impl PortIo for CoreIo<'_> {
+    fn output(&mut self, port: u16, value: u16) {
+        let core = self.core;
+        match self
+            .channels
+            .iter_mut()
+            .find(|channel| channel.from == (core, port))
+        {
+            Some(channel) => channel.queue.push_back(value),
+            None => self.host.output(port, value),
+        }
+    }
+
+    fn input(&mut self, port: u16) -> u16 {
+        let core = self.core;
+        match self
+            .channels
+            .iter_mut()
+            .find(|channel| channel.to == (core, port))
+        {
+            Some(channel) => channel.queue.pop_front().unwrap_or(0),
+            None => self.host.input(port),
+        }
+    }
+
+    fn take_failure(&mut self) -> Option<IoFailure> {
+        self.host.take_failure()
+    }
+}
+
+/// Processors, possibly of different types, that take turns running one
+/// instruction at a time under a deterministic scheduler.
+///
+/// Shared memory is kept in one place and copied into a core before each
+/// of its instructions, and back out after it, so every core sees the
+/// writes of the others as soon as they happen. A core whose next
+/// instruction reads a channel with nothing in it waits for another core
+/// to write to it.
+#[wasm_bindgen]
+pub struct System {
+    cores: Vec<Core>,
+    shared: Vec<SharedRegion>,
+    /// Bumped whenever the shared memory changes.
+    version: u64,
+    channels: Vec<Channel>,
+    policy: SchedulePolicy,
+    quantum: u32,
+    /// The core that ran last and how many instructions of its quantum it
+    /// has used.
+    current: Option<(usize, u32)>,
+    rng: u64,
+}
+
+impl Default for System {
+    fn default() -> Self {
+        System::new()
+    }
+}
+
+/// The seed used when none is given, or when the seed is zero, which
+/// xorshift can't use.
+const DEFAULT_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+#[wasm_bindgen]
+impl System {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> System {
+        System {
+            cores: Vec::new(),
+            shared: Vec::new(),
+            version: 0,
+            channels: Vec::new(),
+            policy: SchedulePolicy::RoundRobin,
+            quantum: 1,
+            current: None,
+            rng: DEFAULT_SEED,
+        }
+    }
+
+    /// Adds a core and returns its index. Fails if it lacks a memory that
+    /// is shared.
+    #[wasm_bindgen]
+    pub fn add_core(&mut self, processor_type: ProcessorType) -> Result<usize, String> {
+        let runner = Runner::new(processor_type);
+        for region in &self.shared {
+            let bits = check_region(
+                &runner,
+                region.memory_type,
+                region.start,
+                region.cells.len(),
+            )?;
+            check_cell_bits(bits, region.cell_bits)?;
+        }
+        self.cores.push(Core {
+            runner,
+            halted: false,
+            synced: 0,
+        });
+        Ok(self.cores.len() - 1)
+    }
+
+    #[wasm_bindgen]
+    pub fn core_count(&self) -> usize {
+        self.cores.len()
+    }
+
+    #[wasm_bindgen]
+    pub fn load_program(&mut self, core: usize, program: &[u8]) -> Result<(), String> {
+        let core = self.core_mut(core)?;
+        core.runner.load_program(program)?;
+        core.halted = false;
+        core.synced = 0;
+        Ok(())
+    }
+
+    /// Shares `cells` cells of `memory_type` starting at `start` between
+    /// all cores, which must have cells of the same width there, as must
+    /// any core added later. Needs at least one core. The region starts
+    /// out zeroed, overriding whatever the programs loaded there;
+    /// `write_u16` fills it in for every core.
+    #[wasm_bindgen]
+    pub fn share_memory(
+        &mut self,
+        memory_type: MemoryType,
+        start: usize,
+        cells: usize,
+    ) -> Result<(), String> {
+        let mut cell_bits = None;
+        for core in &self.cores {
+            let bits = check_region(&core.runner, memory_type, start, cells)?;
+            check_cell_bits(bits, *cell_bits.get_or_insert(bits))?;
+        }
+        let Some(cell_bits) = cell_bits else {
+            return Err("Add a core before sharing memory".to_string());
+        };
+        let overlaps = self.shared.iter().any(|region| {
+            matches!(
+                (region.memory_type, memory_type),
+                (MemoryType::Text, MemoryType::Text) | (MemoryType::Data, MemoryType::Data)
+            ) && start < region.start + region.cells.len()
+                && region.start < start + cells
+        });
+        if overlaps {
+            return Err("The region overlaps memory that is already shared".to_string());
+        }
+        self.shared.push(SharedRegion {
+            memory_type,
+            start,
+            cell_bits,
+            cells: vec![0; cells],
+        });
+        self.version += 1;
+        Ok(())
+    }
+
+    /// Makes what core `from` writes to `from_port` come out of
+    /// `to_port` on core `to`, in order.
+    #[wasm_bindgen]
+    pub fn connect(
+        &mut self,
+        from: usize,
+        from_port: u16,
+        to: usize,
+        to_port: u16,
+    ) -> Result<(), String> {
+        self.core(from)?;
+        self.core(to)?;
+        if self
+            .channels
+            .iter()
+            .any(|channel| channel.from == (from, from_port) || channel.to == (to, to_port))
+        {
+            return Err("One of the ports is already connected".to_string());
+        }
+        self.channels.push(Channel {
+            from: (from, from_port),
+            to: (to, to_port),
+            queue: VecDeque::new(),
+        });
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn set_policy(&mut self, policy: SchedulePolicy) {
+        self.policy = policy;
+    }
+
+    /// How many instructions a core runs in a row under round robin.
+    #[wasm_bindgen]
+    pub fn set_quantum(&mut self, instructions: u32) -> Result<(), String> {
+        if instructions == 0 {
+            return Err("The quantum must be at least one instruction".to_string());
+        }
+        self.quantum = instructions;
+        Ok(())
+    }
+
+    /// Restarts the random scheduler from `seed`; the same seed gives the
+    /// same interleaving.
+    #[wasm_bindgen]
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = if seed == 0 { DEFAULT_SEED } else { seed };
+    }
+
+    /// Runs up to `n` instructions in total. The callbacks are called as
+    /// `output(port, value, core)` and `input(port, core)` for the ports
+    /// that aren't connected to another core.
+    ///
+    /// Returns `Halt` once every core has halted, and `Loop` if every core
+    /// still running waits on an empty channel. Any other status stops the
+    /// whole system, and `last_core` tells which core it came from.
+    #[wasm_bindgen]
+    pub fn run_n(
+        &mut self,
+        output: &js_sys::Function,
+        input: &js_sys::Function,
+        n: usize,
+    ) -> WasmProcessorContinue {
+        let mut ios: Vec<JsPortIo> = (0..self.cores.len())
+            .map(|core| JsPortIo::for_core(output, input, core))
+            .collect();
+        let mut ios: Vec<&mut dyn PortIo> =
+            ios.iter_mut().map(|io| io as &mut dyn PortIo).collect();
+        self.run_n_with(&mut ios, n)
+    }
+
+    /// The core that ran the last instruction.
+    #[wasm_bindgen]
+    pub fn last_core(&self) -> Option<usize> {
+        self.current.map(|(core, _)| core)
+    }
+
+    #[wasm_bindgen]
+    pub fn is_halted(&self, core: usize) -> Result<bool, String> {
+        Ok(self.core(core)?.halted)
+    }
+
+    /// Whether the core's next instruction reads a channel that is empty.
+    #[wasm_bindgen]
+    pub fn is_waiting(&self, core: usize) -> Result<bool, String> {
+        self.core(core)?;
+        Ok(self.waiting(core))
+    }
+
+    /// The port access that stopped the core with `IoError`.
+    #[wasm_bindgen]
+    pub fn io_failure(&self, core: usize) -> Result<Option<IoFailure>, String> {
+        Ok(self.core(core)?.runner.io_failure())
+    }
+
+    #[wasm_bindgen]
+    pub fn pc(&self, core: usize) -> Result<u16, String> {
+        Ok(self.core(core)?.runner.pc())
+    }
+
+    #[wasm_bindgen]
+    pub fn get_registers(&mut self, core: usize) -> Result<Vec<RegisterState>, String> {
+        Ok(self.core_mut(core)?.runner.get_registers())
+    }
+
+    #[wasm_bindgen]
+    pub fn get_memory(&mut self, core: usize) -> Result<Vec<MemoryBlock>, String> {
+        self.sync_in(core)?;
+        Ok(self.cores[core].runner.get_memory())
+    }
+
+    #[wasm_bindgen]
+    pub fn read_u16(
+        &mut self,
+        core: usize,
+        mem_type: MemoryType,
+        address: usize,
+    ) -> Result<u16, String> {
+        self.sync_in(core)?;
+        self.cores[core].runner.read_u16(mem_type, address)
+    }
+
+    /// Writes a word into a core's memory, and into every core's if the
+    /// address is shared.
+    #[wasm_bindgen]
+    pub fn write_u16(
+        &mut self,
+        core: usize,
+        mem_type: MemoryType,
+        address: usize,
+        value: u16,
+    ) -> Result<(), String> {
+        self.sync_in(core)?;
+        self.cores[core]
+            .runner
+            .write_u16(mem_type, address, value)?;
+        self.sync_out(core);
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn add_breakpoint(&mut self, core: usize, address: u16) -> Result<(), String> {
+        self.core_mut(core)?.runner.add_breakpoint(address);
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_breakpoint(&mut self, core: usize, address: u16) -> Result<bool, String> {
+        Ok(self.core_mut(core)?.runner.remove_breakpoint(address))
+    }
+}
+
+/// Checks that a runner has room for a shared region, returning the width
+/// of its cells there.
+fn check_region(
+    runner: &Runner,
+    memory_type: MemoryType,
+    start: usize,
+    cells: usize,
+) -> Result<u32, String> {
+    let layout = runner.memory_layout(memory_type)?;
+    layout.check_range(start, cells)?;
+    Ok(layout.cell_bits)
+}
+
+fn check_cell_bits(bits: u32, expected: u32) -> Result<(), String> {
+    if bits == expected {
+        Ok(())
+    } else {
+        Err(format!(
+            "Shared memory can't mix {}-bit and {}-bit cells",
+            expected, bits
+        ))
+    }
+}
+
+impl System {
+    /// The runner of a core, to use everything `Runner` offers.
+    pub fn runner(&self, core: usize) -> Option<&Runner> {
+        self.cores.get(core).map(|core| &core.runner)
+    }
+
+    /// `run_n` for Rust callers, with one `PortIo` per core. Stops with
+    /// `Error` before running anything if the number of `ios` is wrong.
+    pub fn run_n_with(&mut self, ios: &mut [&mut dyn PortIo], n: usize) -> WasmProcessorContinue {
+        if ios.len() != self.cores.len() {
+            return WasmProcessorContinue::Error;
+        }
+        for _ in 0..n {
+            let runnable: Vec<usize> = (0..self.cores.len())
+                .filter(|core| !self.cores[*core].halted && !self.waiting(*core))
+                .collect();
+            if runnable.is_empty() {
+                return if self.cores.iter().all(|core| core.halted) {
+                    WasmProcessorContinue::Halt
+                } else {
+                    WasmProcessorContinue::Loop
+                };
+            }
+            let core = self.pick(&runnable);
+            if let Err(fault) = self.sync_in(core) {
+                self.cores[core].runner.fault = Some(fault);
+                return WasmProcessorContinue::Error;
+            }
+            let mut io = CoreIo {
+                core,
+                host: &mut *ios[core],
+                channels: &mut self.channels,
+            };
+            let result = self.cores[core].runner.advance(&mut io);
+            self.sync_out(core);
+            match result {
+                WasmProcessorContinue::Continue => {}
+                WasmProcessorContinue::Halt => {
+                    self.cores[core].halted = true;
+                    if self.cores.iter().all(|core| core.halted) {
+                        return WasmProcessorContinue::Halt;
+                    }
+                }
+                other => return other,
+            }
+        }
+        WasmProcessorContinue::Continue
+    }
+
+    fn core(&self, core: usize) -> Result<&Core, String> {
+        self.cores
+            .get(core)
+            .ok_or_else(|| format!("There is no core {}", core))
+    }
+
+    fn core_mut(&mut self, core: usize) -> Result<&mut Core, String> {
+        self.cores
+            .get_mut(core)
+            .ok_or_else(|| format!("There is no core {}", core))
+    }
+
+    fn waiting(&self, core: usize) -> bool {
+        let processor = &self.cores[core].runner.processor;
+        let Some(instruction) = processor.decode(processor.pc()) else {
+            return false;
+        };
+        if instruction.kind != InstructionKind::Input {
+            return false;
+        }
+        instruction.operands.iter().any(|operand| match operand {
+            Operand::Port(port) => self
+                .channels
+                .iter()
+                .any(|channel| channel.to == (core, *port) && channel.queue.is_empty()),
+            _ => false,
+        })
+    }
+
+    fn pick(&mut self, runnable: &[usize]) -> usize {
+        let core = match (self.policy, self.current) {
+            (SchedulePolicy::RoundRobin, Some((core, used)))
+                if used < self.quantum && runnable.contains(&core) =>
+            {
+                self.current = Some((core, used + 1));
+                return core;
+            }
+            (SchedulePolicy::RoundRobin, current) => {
+                let after = current.map_or(0, |(core, _)| core + 1);
+                runnable
+                    .iter()
+                    .copied()
+                    .find(|core| *core >= after)
+                    .unwrap_or(runnable[0])
+            }
+            (SchedulePolicy::Random, _) => {
+                runnable[(self.next_random() % runnable.len() as u64) as usize]
+            }
+        };
+        self.current = Some((core, 1));
+        core
+    }
+
+    /// xorshift64*, small and good enough to shuffle a schedule.
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Copies the shared memory into a core that hasn't seen its latest
+    /// version.
+    fn sync_in(&mut self, core: usize) -> Result<(), String> {
+        if self.core(core)?.synced == self.version {
+            return Ok(());
+        }
+        for region in &self.shared {
+            self.cores[core]
+                .runner
+                .write_cells(region.memory_type, region.start, &region.cells)?;
+        }
+        self.cores[core].synced = self.version;
+        Ok(())
+    }
+
+    /// Copies the cells a core changed in shared memory back out.
+    fn sync_out(&mut self, core: usize) {
+        let runner = &self.cores[core].runner;
+        let mut changed = false;
+        for region in &mut self.shared {
+            for (offset, cell) in region.cells.iter_mut().enumerate() {
+                let value = runner
+                    .processor
+                    .read_memory(region.memory_type, region.start + offset);
+                if let Some(value) = value.filter(|value| value != cell) {
+                    *cell = value;
+                    changed = true;
+                }
+            }
+        }
+        if changed {
+            self.version += 1;
+            self.cores[core].synced = self.version;
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Stops programs that run for too long or are stuck in a loop.
+
+use crate::processor::{WasmProcessor, WasmProcessorContinue};
+
+/// The clock is only read every so many steps, since reading it costs far
+/// more than a step.
+const STEPS_PER_CLOCK_CHECK: u64 = 256;
+
+/// How many steps the loop detector waits before its first snapshot, so
+/// that programs reading input in a loop don't copy memory all the time.
+const FIRST_SNAPSHOT_AFTER: u64 = 64;
+
+/// The registers and memory at some point of the run.
+#[derive(PartialEq, Eq)]
+struct Snapshot {
+    registers: Vec<u16>,
+    memory: Vec<Vec<u8>>,
+}
+
+fn registers(processor: &mut dyn WasmProcessor) -> Vec<u16> {
+    processor
+        .get_registers()
+        .iter()
+        .map(|register| register.value())
+        .collect()
+}
+
+fn memory(processor: &mut dyn WasmProcessor) -> Vec<Vec<u8>> {
+    processor
+        .get_memory()
+        .iter()
+        .map(|block| block.values())
+        .collect()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now_ms() -> f64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |elapsed| elapsed.as_secs_f64() * 1000.0)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn now_ms() -> f64 {
+    js_sys::Date::now()
+}
+
+/// Counts the steps since the last reset against the budgets, and watches
+/// for the machine coming back to a state it was in before.
+///
+/// Loops are found by comparing every state with a snapshot that is
+/// retaken at exponentially growing intervals, so a loop is caught within
+/// a few of its iterations no matter how long it is. Memory is only
+/// compared when the registers match. Reading input makes the program
+/// depend on more than its state, so it starts the search over.
+#[derive(Default)]
+pub struct Watchdog {
+    step_budget: Option<u64>,
+    time_budget_ms: Option<f64>,
+    detect_loops: bool,
+    steps: u64,
+    started_ms: Option<f64>,
+    snapshot: Option<Snapshot>,
+    since_snapshot: u64,
+    snapshot_interval: u64,
+}
+
+impl Watchdog {
+    pub fn set_step_budget(&mut self, steps: Option<u64>) {
+        self.step_budget = steps;
+    }
+
+    pub fn set_time_budget(&mut self, milliseconds: Option<f64>) {
+        self.time_budget_ms = milliseconds;
+    }
+
+    pub fn set_loop_detection(&mut self, enabled: bool) {
+        self.detect_loops = enabled;
+        self.forget_states();
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Starts counting steps and time from zero again.
+    pub fn reset(&mut self) {
+        self.steps = 0;
+        self.started_ms = None;
+        self.forget_states();
+    }
+
+    /// Returns `Timeout` if either budget has run out, before a step.
+    pub fn check_budget(&mut self) -> WasmProcessorContinue {
+        if matches!(self.step_budget, Some(budget) if self.steps >= budget) {
+            return WasmProcessorContinue::Timeout;
+        }
+        if let Some(budget) = self.time_budget_ms {
+            if self.steps % STEPS_PER_CLOCK_CHECK == 0 {
+                let now = now_ms();
+                let started = *self.started_ms.get_or_insert(now);
+                if now - started >= budget {
+                    return WasmProcessorContinue::Timeout;
+                }
+            }
+        }
+        WasmProcessorContinue::Continue
+    }
+
+    /// Counts a step that has just run, returning `Loop` if the state it
+    /// left behind has been seen before.
+    pub fn record_step(
+        &mut self,
+        processor: &mut dyn WasmProcessor,
+        read_input: bool,
+    ) -> WasmProcessorContinue {
+        self.steps += 1;
+        if !self.detect_loops {
+            return WasmProcessorContinue::Continue;
+        }
+        if read_input {
+            self.forget_states();
+            return WasmProcessorContinue::Continue;
+        }
+        let registers = registers(processor);
+        if let Some(snapshot) = &self.snapshot {
+            if snapshot.registers == registers && snapshot.memory == memory(processor) {
+                return WasmProcessorContinue::Loop;
+            }
+        }
+        self.since_snapshot += 1;
+        if self.since_snapshot >= self.snapshot_interval {
+            self.snapshot = Some(Snapshot {
+                registers,
+                memory: memory(processor),
+            });
+            self.since_snapshot = 0;
+            self.snapshot_interval *= 2;
+        }
+        WasmProcessorContinue::Continue
+    }
+
+    fn forget_states(&mut self) {
+        self.snapshot = None;
+        self.since_snapshot = 0;
+        self.snapshot_interval = FIRST_SNAPSHOT_AFTER;
+    }
+}
@@ -0,0 +1,195 @@
+//! Runs the `monistode-run` binary on the golden programs and checks its
+//! argument parsing, output and exit codes.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+use common::{executable, RISC};
+
+fn golden(name: &str) -> String {
+    format!("{}/tests/golden/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+fn run(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_monistode-run"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+/// Writes a RISC program to a file of its own, for the test named `name`.
+fn risc_program(name: &str, cells: &[u16]) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("monistode-run-{}-{}.bin", name, std::process::id()));
+    std::fs::write(&path, executable(RISC, 8, cells)).unwrap();
+    path
+}
+
+#[test]
+fn rejects_bad_arguments() {
+    for (args, message) in [
+        (&[][..], "No executable given"),
+        (
+            &["--frobnicate", "a.bin"][..],
+            "Unknown option: --frobnicate",
+        ),
+        (&["a.bin", "b.bin"][..], "Unexpected argument: b.bin"),
+        (
+            &["--max-steps", "many", "a.bin"][..],
+            "Invalid value for --max-steps: many",
+        ),
+        (&["a.bin", "--input-port"][..], "--input-port needs a value"),
+        (
+            &["--processor", "vax", "a.bin"][..],
+            "Unknown processor: vax",
+        ),
+    ]
+    .iter()
+    {
+        let output = run(args, b"");
+        assert_eq!(output.status.code(), Some(5), "{:?}", args);
+        assert!(stderr(&output).contains(message), "{}", stderr(&output));
+        assert!(stderr(&output).contains("Usage: monistode-run"));
+    }
+
+    let output = run(&["--help"], b"");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("Usage: monistode-run"));
+}
+
+#[test]
+fn maps_ports_to_stdin_and_stdout() {
+    let output = run(&[&golden("risc.bin")], b"");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, b"Hi");
+
+    // The accumulator program echoes port 3 to port 4.
+    let args = [
+        "--input-port",
+        "3",
+        "--output-port",
+        "4",
+        "--dump-registers",
+        &golden("accumulator.bin"),
+    ];
+    let output = run(&args, b"*");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, b"*");
+    assert!(
+        stderr(&output).contains(" ACC = 0x002a"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn exit_codes_tell_how_the_program_ended() {
+    let risc = golden("risc.bin");
+    let output = run(&["--max-steps", "2", &risc], b"");
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(output.stdout, b"H");
+
+    let output = run(&["--trace", &risc], b"");
+    assert!(stderr(&output).contains("0x0000: mov R00, 72"));
+
+    let output = run(&["--processor", "stack", &risc], b"");
+    assert_eq!(output.status.code(), Some(2));
+    let output = run(&[&golden("missing.bin")], b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("Failed to read"));
+
+    // jmp 0x0000
+    let path = risc_program("loop", &[0x64, 0, 0]);
+    let output = run(&["--detect-loops", path.to_str().unwrap()], b"");
+    assert_eq!(output.status.code(), Some(4));
+    assert!(stderr(&output).contains("Stuck in a loop at 0x0000 (_start)"));
+    std::fs::remove_file(path).unwrap();
+
+    // An opcode no RISC instruction uses.
+    let path = risc_program("error", &[0xfc, 0, 0]);
+    let output = run(&[path.to_str().unwrap()], b"");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stderr(&output).contains("Processor error at"),
+        "{}",
+        stderr(&output)
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+/// Runs `program` under `--gdb`, connects to it and sends `packets`, waiting
+/// for a stop reply after each `c`, and returns how the process ended.
+#[cfg(feature = "gdb")]
+fn gdb_session(program: &std::path::Path, packets: &[&str]) -> Output {
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let address = format!("127.0.0.1:{}", port);
+    let child = Command::new(env!("CARGO_BIN_EXE_monistode-run"))
+        .args(["--gdb", &address, program.to_str().unwrap()])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stream = loop {
+        match TcpStream::connect(&address) {
+            Ok(stream) => break stream,
+            Err(_) => std::thread::sleep(std::time::Duration::from_millis(10)),
+        }
+    };
+    for body in packets {
+        let sum = body.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(stream, "${}#{:02x}", body, sum).unwrap();
+        if *body == "c" {
+            let mut received: Vec<u8> = Vec::new();
+            let mut buffer = [0; 64];
+            while !received
+                .windows(2)
+                .any(|pair| pair == b"$S" || pair == b"$W")
+            {
+                let count = stream.read(&mut buffer).unwrap();
+                received.extend(&buffer[..count]);
+            }
+        }
+    }
+    child.wait_with_output().unwrap()
+}
+
+#[cfg(feature = "gdb")]
+#[test]
+fn exits_after_gdb_with_how_the_program_stopped() {
+    let output = gdb_session(golden("risc.bin").as_ref(), &["c", "D"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, b"Hi");
+
+    // An opcode no RISC instruction uses.
+    let path = risc_program("gdb-error", &[0xfc, 0, 0]);
+    let output = gdb_session(&path, &["c", "D"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("Processor error at"));
+
+    let output = gdb_session(&path, &["k"]);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(stderr(&output).contains("before the program finished"));
+    std::fs::remove_file(path).unwrap();
+}
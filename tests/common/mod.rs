@@ -0,0 +1,45 @@
+//! Helpers shared by the integration tests.
+
+#![allow(dead_code)]
+
+pub const STACK: u8 = 0;
+pub const ACCUMULATOR: u8 = 1;
+pub const RISC: u8 = 2;
+
+/// Builds a binutils executable with a single text segment at address 0,
+/// holding `cells` of `cell_bits` bits each, and a `_start` symbol.
+pub fn executable(architecture: u8, cell_bits: usize, cells: &[u16]) -> Vec<u8> {
+    let mut bits = Vec::new();
+    for cell in cells {
+        for bit in (0..cell_bits).rev() {
+            bits.push(cell >> bit & 1 == 1);
+        }
+    }
+    let mut text = vec![0u8; bits.len().div_ceil(8)];
+    for (index, bit) in bits.iter().enumerate() {
+        if *bit {
+            text[index / 8] |= 1 << (index % 8);
+        }
+    }
+
+    let mut symbols = Vec::new();
+    symbols.extend(0u32.to_le_bytes()); // segment
+    symbols.extend(0u32.to_le_bytes()); // offset in bits
+    symbols.extend(0u32.to_le_bytes()); // name offset
+    symbols.extend(b"_start\0");
+
+    let mut data = vec![architecture];
+    data.extend(2u64.to_le_bytes()); // the text segment and the symbol table
+    data.extend(0u64.to_le_bytes()); // entry point
+    data.extend(0u64.to_le_bytes());
+    data.extend((cells.len() as u64).to_le_bytes());
+    data.extend((bits.len() as u64).to_le_bytes());
+    data.push(0b101); // executable, readable
+    data.extend(0u64.to_le_bytes());
+    data.extend(1u64.to_le_bytes()); // symbol count
+    data.extend((symbols.len() as u64).to_le_bytes());
+    data.push(0b1000); // special
+    data.extend(text);
+    data.extend(symbols);
+    data
+}
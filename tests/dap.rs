@@ -0,0 +1,315 @@
+//! Drives the DAP session with the requests an editor would send.
+
+#![cfg(feature = "dap")]
+
+mod common;
+
+use common::{executable, RISC};
+use monistode_emulator_bindings::dap::DapSession;
+use serde_json::{json, Value};
+
+/// call 0x0008; out 0, R00; halt; mov R00, 'H'; ret
+const RISC_CALL: &[u16] = &[0x48, 0, 0x20, 0x88, 0, 0, 0, 0, 0x18, 0, 0, 0x48, 0x50];
+
+struct Client {
+    session: DapSession,
+    seq: u64,
+}
+
+impl Client {
+    /// Initializes and launches `RISC_CALL`, stopped on entry.
+    fn launch() -> Self {
+        let mut client = Client {
+            session: DapSession::new(),
+            seq: 0,
+        };
+        let messages = client.send("initialize", json!({ "adapterID": "monistode" }));
+        assert_eq!(messages[0]["body"]["supportsInstructionBreakpoints"], true);
+        assert_eq!(messages[1]["event"], "initialized");
+        let program = executable(RISC, 8, RISC_CALL);
+        let messages = client.send(
+            "launch",
+            json!({ "programBytes": program, "stopOnEntry": true }),
+        );
+        assert_eq!(messages[0]["success"], true);
+        let messages = client.send("configurationDone", json!({}));
+        assert_eq!(messages[1]["body"]["reason"], "entry");
+        client
+    }
+
+    /// Sends a request and returns the response followed by the events.
+    fn send(&mut self, command: &str, arguments: Value) -> Vec<Value> {
+        self.seq += 1;
+        let request = json!({
+            "seq": self.seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        });
+        let messages = self.session.handle(&request.to_string());
+        let messages: Vec<Value> = messages
+            .iter()
+            .map(|message| serde_json::from_str(message).unwrap())
+            .collect();
+        assert_eq!(messages[0]["type"], "response");
+        assert_eq!(messages[0]["request_seq"], self.seq);
+        messages
+    }
+
+    fn variables(&mut self, reference: u64) -> Vec<(String, String)> {
+        let messages = self.send("variables", json!({ "variablesReference": reference }));
+        messages[0]["body"]["variables"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|variable| {
+                (
+                    variable["name"].as_str().unwrap().to_string(),
+                    variable["value"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect()
+    }
+}
+
+fn events(messages: &[Value]) -> Vec<String> {
+    messages[1..]
+        .iter()
+        .map(|message| message["event"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn steps_and_inspects_the_program() {
+    let mut client = Client::launch();
+    let messages = client.send("stepIn", json!({ "threadId": 1 }));
+    assert_eq!(messages[1]["body"]["reason"], "step");
+
+    let messages = client.send("stackTrace", json!({ "threadId": 1 }));
+    let frames = messages[0]["body"]["stackFrames"].as_array().unwrap();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0]["instructionPointerReference"], "0x0008");
+    assert_eq!(frames[1]["name"], "_start");
+
+    let messages = client.send("scopes", json!({ "frameId": 0 }));
+    let scopes = messages[0]["body"]["scopes"].as_array().unwrap();
+    let names: Vec<&str> = scopes.iter().map(|s| s["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["Registers", "Flags", "Stack", "Memory"]);
+
+    let registers = client.variables(1);
+    assert!(registers.contains(&("PC".to_string(), "0x0008".to_string())));
+    let flags = client.variables(2);
+    assert_eq!(flags.len(), 4);
+    assert_eq!(flags[0].0, "CF");
+
+    let messages = client.send(
+        "readMemory",
+        json!({ "memoryReference": "text", "offset": 0, "count": 3 }),
+    );
+    assert_eq!(messages[0]["body"]["data"], "SAAg");
+
+    let messages = client.send(
+        "disassemble",
+        json!({ "memoryReference": "0x8", "instructionCount": 2 }),
+    );
+    let instructions = messages[0]["body"]["instructions"].as_array().unwrap();
+    assert_eq!(instructions[1]["address"], "0x000c");
+
+    let messages = client.send(
+        "disassemble",
+        json!({
+            "memoryReference": "0x0",
+            "instructionOffset": i64::MAX,
+            "instructionCount": u64::MAX,
+        }),
+    );
+    let instructions = messages[0]["body"]["instructions"].as_array().unwrap();
+    assert_eq!(instructions.len(), 0x10000);
+}
+
+#[test]
+fn continues_to_breakpoints_and_reports_output() {
+    let mut client = Client::launch();
+    let messages = client.send(
+        "setInstructionBreakpoints",
+        json!({ "breakpoints": [{ "instructionReference": "0x3" }] }),
+    );
+    assert_eq!(messages[0]["body"]["breakpoints"][0]["verified"], true);
+    let messages = client.send(
+        "setFunctionBreakpoints",
+        json!({ "breakpoints": [{ "name": "missing" }] }),
+    );
+    assert_eq!(messages[0]["body"]["breakpoints"][0]["verified"], false);
+
+    client.send("continue", json!({ "threadId": 1 }));
+    assert!(client.session.is_running());
+    let stopped: Vec<Value> = client
+        .session
+        .poll(100)
+        .iter()
+        .map(|message| serde_json::from_str(message).unwrap())
+        .collect();
+    assert_eq!(stopped[0]["body"]["reason"], "breakpoint");
+
+    let messages = client.send("next", json!({ "threadId": 1 }));
+    assert_eq!(events(&messages), ["output", "stopped"]);
+    assert_eq!(messages[1]["body"]["output"], "H");
+
+    let messages = client.send("next", json!({ "threadId": 1 }));
+    assert_eq!(events(&messages), ["exited", "terminated"]);
+    assert!(client.session.is_terminated());
+    // The adapter keeps serving until the client disconnects.
+    assert!(!client.session.is_disconnected());
+    let messages = client.send("disconnect", json!({}));
+    assert_eq!(messages[0]["success"], true);
+    assert!(client.session.is_disconnected());
+}
+
+#[test]
+fn checks_breakpoint_conditions_and_evaluates_expressions() {
+    let mut client = Client::launch();
+    let messages = client.send(
+        "setInstructionBreakpoints",
+        json!({ "breakpoints": [
+            { "instructionReference": "0x3", "condition": "R00 ==" },
+            { "instructionReference": "0x3", "hitCondition": "twice" },
+            { "instructionReference": "0x3", "condition": "R00 == 0x48", "hitCondition": "1" },
+            { "instructionReference": "0x7", "condition": "R00 != 0x48" },
+        ] }),
+    );
+    let verified: Vec<&Value> = messages[0]["body"]["breakpoints"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|breakpoint| &breakpoint["verified"])
+        .collect();
+    assert_eq!(verified, [false, false, true, true]);
+
+    client.send("continue", json!({ "threadId": 1 }));
+    let stopped: Vec<Value> = client
+        .session
+        .poll(100)
+        .iter()
+        .map(|message| serde_json::from_str(message).unwrap())
+        .collect();
+    assert_eq!(stopped[0]["body"]["reason"], "breakpoint");
+
+    let messages = client.send("evaluate", json!({ "expression": "R00 + 1" }));
+    assert_eq!(messages[0]["body"]["result"], "73 (0x0049)");
+    let messages = client.send("evaluate", json!({ "expression": "R00 +" }));
+    assert_eq!(messages[0]["success"], false);
+
+    // The breakpoint on the halt doesn't hold, and logpoints don't stop.
+    let messages = client.send(
+        "setFunctionBreakpoints",
+        json!({ "breakpoints": [{ "name": "_start", "logMessage": "R00 is {R00" }] }),
+    );
+    assert_eq!(messages[0]["body"]["breakpoints"][0]["verified"], false);
+    client.send(
+        "setInstructionBreakpoints",
+        json!({ "breakpoints": [
+            { "instructionReference": "0x7", "condition": "R00 != 0x48" },
+            { "instructionReference": "0x7", "logMessage": "R00 is {R00:x}" },
+        ] }),
+    );
+    client.send("continue", json!({ "threadId": 1 }));
+    let mut finished: Vec<Value> = client
+        .session
+        .poll(100)
+        .iter()
+        .map(|message| serde_json::from_str(message).unwrap())
+        .collect();
+    finished.insert(0, Value::Null);
+    assert_eq!(
+        events(&finished),
+        ["output", "output", "exited", "terminated"]
+    );
+    assert_eq!(finished[2]["body"]["output"], "R00 is 0x0048\n");
+}
+
+#[test]
+fn checks_breakpoints_at_the_entry_point() {
+    let mut client = Client {
+        session: DapSession::new(),
+        seq: 0,
+    };
+    client.send("initialize", json!({ "adapterID": "monistode" }));
+    let program = executable(RISC, 8, RISC_CALL);
+    client.send("launch", json!({ "programBytes": program }));
+    client.send(
+        "setInstructionBreakpoints",
+        json!({ "breakpoints": [{ "instructionReference": "0x0", "condition": "R00 == 0" }] }),
+    );
+    client.send("configurationDone", json!({}));
+    let stopped: Vec<Value> = client
+        .session
+        .poll(100)
+        .iter()
+        .map(|message| serde_json::from_str(message).unwrap())
+        .collect();
+    assert_eq!(stopped[0]["body"]["reason"], "breakpoint");
+    let registers = client.variables(1);
+    assert!(registers.contains(&("PC".to_string(), "0x0000".to_string())));
+
+    // Stopping on entry counts as having been there, so continuing doesn't
+    // stop at the entry point again.
+    let mut client = Client::launch();
+    client.send(
+        "setInstructionBreakpoints",
+        json!({ "breakpoints": [
+            { "instructionReference": "0x0" },
+            { "instructionReference": "0x3" },
+        ] }),
+    );
+    let messages = client.send("restart", json!({}));
+    assert_eq!(messages[1]["body"]["reason"], "entry");
+    client.send("continue", json!({ "threadId": 1 }));
+    let stopped: Vec<Value> = client
+        .session
+        .poll(100)
+        .iter()
+        .map(|message| serde_json::from_str(message).unwrap())
+        .collect();
+    assert_eq!(stopped[0]["body"]["reason"], "breakpoint");
+    let registers = client.variables(1);
+    assert!(registers.contains(&("PC".to_string(), "0x0003".to_string())));
+}
+
+#[test]
+fn restarts_from_the_launched_program() {
+    let mut client = Client::launch();
+    client.send("next", json!({ "threadId": 1 }));
+    client.send("next", json!({ "threadId": 1 }));
+    let messages = client.send("restart", json!({}));
+    assert_eq!(messages[0]["success"], true);
+    assert_eq!(messages[1]["body"]["reason"], "entry");
+    let registers = client.variables(1);
+    assert!(registers.contains(&("PC".to_string(), "0x0000".to_string())));
+    client.send("next", json!({ "threadId": 1 }));
+    let messages = client.send("next", json!({ "threadId": 1 }));
+    assert_eq!(messages[1]["body"]["output"], "H");
+}
+
+#[test]
+fn steps_over_and_out_of_calls() {
+    let mut client = Client::launch();
+    let messages = client.send("next", json!({ "threadId": 1 }));
+    assert_eq!(events(&messages), ["stopped"]);
+    let registers = client.variables(1);
+    assert!(registers.contains(&("PC".to_string(), "0x0003".to_string())));
+
+    let mut client = Client::launch();
+    client.send("stepIn", json!({ "threadId": 1 }));
+    client.send("stepOut", json!({ "threadId": 1 }));
+    let registers = client.variables(1);
+    assert!(registers.contains(&("PC".to_string(), "0x0003".to_string())));
+}
+
+#[test]
+fn rejects_requests_before_launch() {
+    let mut session = DapSession::new();
+    let messages = session.handle(r#"{"seq":1,"type":"request","command":"threads"}"#);
+    assert!(messages[0].contains("\"success\":true"));
+    let messages = session.handle(r#"{"seq":2,"type":"request","command":"stackTrace"}"#);
+    assert!(messages[0].contains("No program has been launched"));
+}
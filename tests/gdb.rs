@@ -0,0 +1,137 @@
+//! Drives the GDB stub with the packets GDB would send.
+
+#![cfg(all(feature = "gdb", not(target_arch = "wasm32")))]
+
+mod common;
+
+use common::{executable, RISC};
+use monistode_emulator_bindings::gdb::GdbStub;
+use monistode_emulator_bindings::{BufferedIo, ProcessorType, Runner, WasmProcessorContinue};
+
+/// call 0x0008; out 0, R00; halt; mov R00, 5; ret
+const RISC_CALL: &[u16] = &[0x48, 0, 0x20, 0x88, 0, 0, 0, 0, 0x18, 0, 0, 5, 0x50];
+
+fn packet(body: &str) -> Vec<u8> {
+    let sum = body.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    format!("${}#{:02x}", body, sum).into_bytes()
+}
+
+struct Session {
+    stub: GdbStub,
+    io: BufferedIo,
+}
+
+impl Session {
+    fn new() -> Self {
+        let mut runner = Runner::new(ProcessorType::Risc);
+        runner
+            .load_program(&executable(RISC, 8, RISC_CALL))
+            .unwrap();
+        Session {
+            stub: GdbStub::new(runner),
+            io: BufferedIo::new(),
+        }
+    }
+
+    /// Sends a packet and returns the body of the reply, checking the
+    /// acknowledgement and the checksum on the way.
+    fn send(&mut self, body: &str) -> String {
+        let reply = self.stub.receive(&packet(body), &mut self.io);
+        let reply = String::from_utf8(reply).unwrap();
+        let reply = reply.strip_prefix('+').unwrap();
+        unframe(reply)
+    }
+}
+
+fn unframe(reply: &str) -> String {
+    if reply.is_empty() {
+        return String::new();
+    }
+    let (body, sum) = reply[1..].split_once('#').unwrap();
+    assert_eq!(packet(body), reply.as_bytes());
+    assert_eq!(sum.len(), 2);
+    body.to_string()
+}
+
+#[test]
+fn describes_the_target() {
+    let mut session = Session::new();
+    assert!(session
+        .send("qSupported:multiprocess+;swbreak+")
+        .contains("qXfer:features:read+"));
+    let xml = session.send("qXfer:features:read:target.xml:0,1000");
+    assert!(xml.starts_with("l<?xml"));
+    assert!(xml.contains("<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\" regnum=\"0\"/>"));
+    assert!(xml.contains("<reg name=\"r11\""));
+    let start = session.send("qXfer:features:read:target.xml:0,a");
+    assert_eq!(start, "m<?xml vers");
+}
+
+#[test]
+fn reads_and_writes_registers_and_memory() {
+    let mut session = Session::new();
+    assert_eq!(session.send("?"), "S05");
+    // PC, FR, SP, R00..R11, little-endian
+    assert_eq!(session.send("g"), "0000000000040000000000000000");
+    assert_eq!(session.send("P3=3412"), "OK");
+    assert_eq!(session.send("p3"), "3412");
+    assert_eq!(session.send("P1=0001"), "E01");
+    assert_eq!(session.send("m0,4"), "48002088");
+    assert_eq!(session.send("M100,2:abcd"), "OK");
+    assert_eq!(session.send("m100,2"), "abcd");
+    // The RISC processor has no data memory.
+    assert_eq!(session.send("m10000,2"), "E01");
+}
+
+#[test]
+fn steps_and_continues_to_breakpoints() {
+    let mut session = Session::new();
+    assert_eq!(session.send("s"), "S05");
+    assert_eq!(session.send("p0"), "0800");
+
+    assert_eq!(session.send("Z0,3,1"), "OK");
+    assert_eq!(session.send("c"), "");
+    assert!(session.stub.is_running());
+    let reply = session.stub.poll(&mut session.io, 100);
+    assert_eq!(unframe(&String::from_utf8(reply).unwrap()), "S05");
+    assert_eq!(session.send("p0"), "0300");
+    assert_eq!(session.stub.last_stop(), WasmProcessorContinue::Breakpoint);
+
+    assert_eq!(session.send("z0,3,1"), "OK");
+    assert_eq!(session.send("c"), "");
+    let reply = session.stub.poll(&mut session.io, 100);
+    assert_eq!(unframe(&String::from_utf8(reply).unwrap()), "W00");
+    assert_eq!(session.io.outputs(), &[(0, 5)]);
+    assert_eq!(session.stub.last_stop(), WasmProcessorContinue::Halt);
+}
+
+#[test]
+fn names_software_breakpoints_once_gdb_supports_them() {
+    let mut session = Session::new();
+    session.send("qSupported:multiprocess+;swbreak+");
+    assert_eq!(session.send("Z0,3,1"), "OK");
+    assert_eq!(session.send("s"), "S05");
+    session.send("c");
+    let reply = session.stub.poll(&mut session.io, 100);
+    assert_eq!(unframe(&String::from_utf8(reply).unwrap()), "T05swbreak:;");
+}
+
+#[test]
+fn stops_running_on_interrupt() {
+    let mut session = Session::new();
+    session.send("c");
+    let reply = session.stub.receive(&[0x03], &mut session.io);
+    assert_eq!(unframe(&String::from_utf8(reply).unwrap()), "S02");
+    assert!(!session.stub.is_running());
+    assert_eq!(session.stub.last_stop(), WasmProcessorContinue::Continue);
+}
+
+#[test]
+fn stops_acknowledging_in_no_ack_mode() {
+    let mut session = Session::new();
+    assert_eq!(session.send("QStartNoAckMode"), "OK");
+    let reply = session.stub.receive(&packet("?"), &mut session.io);
+    assert_eq!(String::from_utf8(reply).unwrap(), "$S05#b8");
+    session.stub.receive(&packet("D"), &mut session.io);
+    assert!(session.stub.is_detached());
+}
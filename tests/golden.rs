@@ -0,0 +1,135 @@
+//! Runs the executables in `tests/golden` on every processor and compares
+//! the final state with the transcripts next to them. Runs natively and
+//! under `wasm-pack test --headless`.
+//!
+//! After an intended change in behaviour, rerun natively with
+//! `UPDATE_GOLDEN=1` to rewrite the transcripts, and review the diff.
+
+use std::fmt::Write;
+
+use monistode_emulator_bindings::{BufferedIo, MemoryType, ProcessorType, Runner};
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen_test::*;
+
+#[cfg(target_arch = "wasm32")]
+wasm_bindgen_test_configure!(run_in_browser);
+
+/// How many text cells each transcript shows, enough for every program.
+const TEXT_CELLS: usize = 32;
+
+/// Runs the program to the end and describes the outcome: the result, the
+/// port writes, the registers, the stacks and the start of text memory.
+fn transcript(mut runner: Runner, inputs: &[(u16, &[u16])]) -> String {
+    let mut io = BufferedIo::new();
+    for (port, values) in inputs {
+        io.queue_input(*port, values.iter().copied());
+    }
+    let status = runner.run_n_with(&mut io, 1000);
+
+    let mut transcript = String::new();
+    writeln!(transcript, "status {:?}", status).unwrap();
+    for (port, value) in io.outputs() {
+        writeln!(transcript, "output {} {:#06x}", port, value).unwrap();
+    }
+    for register in runner.get_registers() {
+        writeln!(
+            transcript,
+            "register {} {:#06x}",
+            register.name(),
+            register.value()
+        )
+        .unwrap();
+    }
+    for stack in runner.stack_frames().unwrap() {
+        write!(transcript, "stack {}", stack.name()).unwrap();
+        for value in stack.values() {
+            write!(transcript, " {:#06x}", value).unwrap();
+        }
+        transcript.push('\n');
+    }
+    let memory = runner.get_memory();
+    let text = memory
+        .iter()
+        .find(|block| matches!(block.cell_type(), MemoryType::Text))
+        .unwrap();
+    transcript.push_str("text");
+    for cell in &text.values()[..TEXT_CELLS] {
+        write!(transcript, " {:02x}", cell).unwrap();
+    }
+    transcript.push('\n');
+    transcript
+}
+
+fn load(processor_type: ProcessorType, program: &[u8]) -> Runner {
+    let mut runner = Runner::new(processor_type);
+    runner.load_program(program).unwrap();
+    runner
+}
+
+fn check(name: &str, actual: String, expected: &str) {
+    #[cfg(not(target_arch = "wasm32"))]
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        let path = format!("{}/tests/golden/{}.txt", env!("CARGO_MANIFEST_DIR"), name);
+        std::fs::write(path, &actual).unwrap();
+        return;
+    }
+    assert_eq!(actual, expected, "{} differs from its transcript", name);
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+fn stack() {
+    let runner = load(ProcessorType::Stack, include_bytes!("golden/stack.bin"));
+    check(
+        "stack",
+        transcript(runner, &[]),
+        include_str!("golden/stack.txt"),
+    );
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+fn accumulator() {
+    let runner = load(ProcessorType::Acc, include_bytes!("golden/accumulator.bin"));
+    check(
+        "accumulator",
+        transcript(runner, &[(3, &[42, 43])]),
+        include_str!("golden/accumulator.txt"),
+    );
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+fn risc() {
+    let runner = load(ProcessorType::Risc, include_bytes!("golden/risc.bin"));
+    check(
+        "risc",
+        transcript(runner, &[]),
+        include_str!("golden/risc.txt"),
+    );
+}
+
+/// There are no CISC executables, so the image goes straight into memory.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+fn cisc() {
+    let mut runner = Runner::new(ProcessorType::Cisc);
+    for (address, cell) in include_bytes!("golden/cisc.img").iter().enumerate() {
+        assert!(runner.set_memory(MemoryType::Text, address, *cell));
+    }
+    check(
+        "cisc",
+        transcript(runner, &[(3, &[21])]),
+        include_str!("golden/cisc.txt"),
+    );
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+fn cisc_rejects_executables() {
+    let mut runner = Runner::new(ProcessorType::Cisc);
+    assert!(runner
+        .load_program(include_bytes!("golden/risc.bin"))
+        .is_err());
+}
@@ -0,0 +1,81 @@
+//! Grades small programs with `run_tests`.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use common::{executable, ACCUMULATOR};
+use monistode_emulator_bindings::{run_tests, MemoryType, TestCase, WasmProcessorContinue};
+
+/// in 3; out 4; halt
+fn echo() -> Vec<u8> {
+    executable(ACCUMULATOR, 8, &[0x8e, 0, 3, 0x98, 0, 4, 0])
+}
+
+#[test]
+fn passes_matching_cases() {
+    let mut case = TestCase::new("echo");
+    case.queue_input(3, vec![42]);
+    case.expect_output(4, vec![42]);
+    case.expect_register("ACC", 42);
+    case.expect_memory(MemoryType::Text, 0, vec![0x8e, 0, 3]);
+    let results = run_tests(&echo(), vec![case]).unwrap();
+    assert_eq!(results.len(), 1);
+    assert!(results[0].passed(), "{:?}", results[0].mismatch());
+    assert_eq!(results[0].status(), WasmProcessorContinue::Halt);
+    assert_eq!(results[0].outputs_on(4), vec![42]);
+}
+
+#[test]
+fn reports_the_first_mismatch() {
+    let mut wrong_output = TestCase::new("wrong output");
+    wrong_output.queue_input(3, vec![1]);
+    wrong_output.expect_output(4, vec![1, 2]);
+    let mut wrong_register = TestCase::new("wrong register");
+    wrong_register.expect_register("ACC", 7);
+    let mut wrong_memory = TestCase::new("wrong memory");
+    wrong_memory.expect_memory(MemoryType::Text, 1, vec![0, 4]);
+
+    let results = run_tests(&echo(), vec![wrong_output, wrong_register, wrong_memory]).unwrap();
+    let descriptions: Vec<String> = results
+        .iter()
+        .map(|result| result.mismatch().unwrap().describe())
+        .collect();
+    assert_eq!(
+        descriptions,
+        [
+            "port 4 output 1: expected 0x0002, got nothing",
+            "register ACC: expected 0x0007, got 0x0000",
+            "text memory at 0x0002: expected 0x04, got 0x03",
+        ]
+    );
+}
+
+#[test]
+fn applies_presets_and_the_step_limit() {
+    // out 4; halt, with the input overwritten
+    let mut preset = TestCase::new("preset");
+    preset.preset_memory(MemoryType::Text, 0, vec![0x98, 0, 4, 0]);
+    preset.preset_register("ACC", 9);
+    preset.expect_output(4, vec![9]);
+
+    let mut limited = TestCase::new("limited");
+    limited.set_max_steps(1);
+
+    let mut broken = TestCase::new("broken");
+    broken.preset_register("R99", 1);
+
+    let results = run_tests(&echo(), vec![preset, limited, broken]).unwrap();
+    assert!(results[0].passed(), "{:?}", results[0].mismatch());
+    assert_eq!(results[1].status(), WasmProcessorContinue::Timeout);
+    assert_eq!(
+        results[1].mismatch().unwrap().actual(),
+        "still running after 1 steps"
+    );
+    assert_eq!(results[2].mismatch().unwrap().location(), "setup");
+}
+
+#[test]
+fn rejects_invalid_executables() {
+    assert!(run_tests(&[1, 2, 3], vec![TestCase::new("any")]).is_err());
+}
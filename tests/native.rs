@@ -0,0 +1,1462 @@
+//! Tests that drive the runner from plain Rust, with no JS involved.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use common::{executable, ACCUMULATOR, RISC, STACK};
+use monistode_emulator_bindings::{
+    available_processors, detect_processor_type, inspect_executable, BufferedIo, ImageFormat,
+    ImageOptions, IoFailure, MemoryAccess, MemoryType, PortIo, ProcessorConfig, ProcessorType,
+    Runner, WasmProcessorContinue,
+};
+
+fn load(processor_type: ProcessorType, program: &[u8]) -> Runner {
+    let mut runner = Runner::new(processor_type);
+    runner.load_program(program).unwrap();
+    runner
+}
+
+fn run_to_end(runner: &mut Runner, io: &mut BufferedIo) -> WasmProcessorContinue {
+    runner.run_n_with(io, 1000)
+}
+
+fn register(runner: &mut Runner, name: &str) -> u16 {
+    runner
+        .get_registers()
+        .iter()
+        .find(|register| register.name() == name)
+        .map(|register| register.value())
+        .unwrap()
+}
+
+#[test]
+fn stack_outputs_an_immediate() {
+    // mov 7; out 1; halt
+    let program = executable(STACK, 6, &[0x22, 0, 0, 7, 0x2b, 0, 0, 1, 0]);
+    let mut runner = load(ProcessorType::Stack, &program);
+    let mut io = BufferedIo::new();
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Halt
+    );
+    assert_eq!(io.outputs(), &[(1, 7)]);
+}
+
+#[test]
+fn accumulator_echoes_input() {
+    // in 3; out 4; halt
+    let program = executable(ACCUMULATOR, 8, &[0x8e, 0, 3, 0x98, 0, 4, 0]);
+    let mut runner = load(ProcessorType::Acc, &program);
+    let mut io = BufferedIo::new();
+    io.queue_input(3, vec![42, 43]);
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Halt
+    );
+    assert_eq!(io.outputs_on(4), vec![42]);
+    assert_eq!(register(&mut runner, "ACC"), 42);
+}
+
+#[test]
+fn risc_writes_a_string() {
+    // mov R00, 'H'; out 0, R00; mov R00, 'i'; out 0, R00; halt
+    let program = executable(
+        RISC,
+        8,
+        &[
+            0x18, 0, 0, 0x48, 0x88, 0, 0, 0, 0x18, 0, 0, 0x69, 0x88, 0, 0, 0, 0,
+        ],
+    );
+    let mut runner = load(ProcessorType::Risc, &program);
+    let mut io = BufferedIo::new();
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Halt
+    );
+    assert_eq!(io.outputs_on(0), vec![0x48, 0x69]);
+    assert_eq!(runner.pc(), 0x11);
+}
+
+#[test]
+fn detects_the_processor_type() {
+    let program = executable(ACCUMULATOR, 8, &[0]);
+    assert!(matches!(
+        detect_processor_type(&program),
+        Ok(ProcessorType::Acc)
+    ));
+    assert!(detect_processor_type(&[1, 2, 3]).is_err());
+}
+
+#[test]
+fn describes_every_processor() {
+    let processors = available_processors();
+    assert_eq!(processors.len(), 4);
+    for metadata in &processors {
+        let mut runner = Runner::new(metadata.type_());
+        let names: Vec<String> = runner.get_registers().iter().map(|r| r.name()).collect();
+        let described: Vec<String> = metadata.registers().iter().map(|r| r.name()).collect();
+        assert_eq!(names, described);
+        // Every opcode disassembles to its mnemonic.
+        for opcode in metadata.opcodes() {
+            assert!(runner.set_memory(MemoryType::Text, 0, opcode.first_cell()));
+            let text = runner.disassemble(0).unwrap();
+            assert!(text.starts_with(&opcode.mnemonic()), "{}", text);
+        }
+    }
+
+    let stack = &processors[0];
+    assert_eq!(stack.architecture().unwrap(), "stack");
+    let cells: Vec<u32> = stack.memories().iter().map(|m| m.cell_bits()).collect();
+    assert_eq!(cells, [6, 8]);
+    assert_eq!(stack.flags()[1].name(), "ZF");
+
+    let accumulator = &processors[1];
+    let load = accumulator
+        .opcodes()
+        .into_iter()
+        .find(|opcode| opcode.mnemonic() == "load" && opcode.first_cell() == 0b1000001)
+        .unwrap();
+    assert_eq!(load.operands(), ["[IR1]"]);
+
+    let risc = &processors[2];
+    let fr = risc
+        .registers()
+        .into_iter()
+        .find(|r| r.name() == "FR")
+        .unwrap();
+    assert_eq!(fr.bits(), 8);
+    let load = risc
+        .opcodes()
+        .into_iter()
+        .find(|o| o.mnemonic() == "load")
+        .unwrap();
+    assert_eq!(load.operands(), ["reg", "[reg]"]);
+    assert_eq!(load.encoding(), "000001xx");
+    assert_eq!(load.length(), 2);
+    assert_eq!(load.kind(), "other");
+
+    assert!(processors[3].architecture().is_none());
+}
+
+#[test]
+fn rejects_executables_for_other_processors() {
+    let program = executable(STACK, 6, &[0]);
+    assert!(Runner::new(ProcessorType::Risc)
+        .load_program(&program)
+        .is_err());
+}
+
+#[test]
+fn disassembles_the_text_memory() {
+    let program = executable(RISC, 8, &[0x18, 0, 0, 0x48, 0x88, 0, 0, 0, 0]);
+    let runner = load(ProcessorType::Risc, &program);
+    assert_eq!(runner.disassemble(0).unwrap(), "mov R00, 72");
+    assert_eq!(runner.disassemble(4).unwrap(), "out port 0, R00");
+    assert_eq!(runner.disassemble(8).unwrap(), "halt");
+}
+
+/// call 0x0008; out 0, R00; halt; mov R00, 5; ret
+const RISC_CALL: &[u16] = &[0x48, 0, 0x20, 0x88, 0, 0, 0, 0, 0x18, 0, 0, 5, 0x50];
+
+#[test]
+fn tracks_calls_for_the_backtrace() {
+    let program = executable(RISC, 8, RISC_CALL);
+    let mut runner = load(ProcessorType::Risc, &program);
+    let mut io = BufferedIo::new();
+    assert!(runner.backtrace().is_empty());
+
+    runner.run_with(&mut io);
+    let backtrace = runner.backtrace();
+    assert_eq!(backtrace.len(), 1);
+    assert_eq!(backtrace[0].call_site(), 0);
+    assert_eq!(backtrace[0].target(), 8);
+    assert_eq!(backtrace[0].return_address(), 3);
+    assert_eq!(backtrace[0].function().as_deref(), Some("_start+0x8"));
+
+    assert_eq!(
+        runner.step_out_with(&mut io, 100),
+        WasmProcessorContinue::Continue
+    );
+    assert_eq!(runner.pc(), 3);
+    assert!(runner.backtrace().is_empty());
+}
+
+#[test]
+fn steps_over_calls() {
+    let program = executable(RISC, 8, RISC_CALL);
+    let mut runner = load(ProcessorType::Risc, &program);
+    let mut io = BufferedIo::new();
+    runner.step_over_with(&mut io, 100);
+    assert_eq!(runner.pc(), 3);
+    assert_eq!(register(&mut runner, "R00"), 5);
+    runner.step_over_with(&mut io, 100);
+    assert_eq!(runner.pc(), 7);
+    assert_eq!(io.outputs(), &[(0, 5)]);
+}
+
+#[test]
+fn stops_stepping_over_a_call_at_a_breakpoint_inside() {
+    let program = executable(RISC, 8, RISC_CALL);
+    let mut runner = load(ProcessorType::Risc, &program);
+    let mut io = BufferedIo::new();
+    runner.add_breakpoint(12);
+    assert_eq!(
+        runner.step_over_with(&mut io, 100),
+        WasmProcessorContinue::Breakpoint
+    );
+    assert_eq!(runner.pc(), 12);
+    assert_eq!(runner.backtrace().len(), 1);
+
+    // A step over an instruction that isn't a call is a single step.
+    runner.remove_breakpoint(12);
+    assert_eq!(
+        runner.step_over_with(&mut io, 100),
+        WasmProcessorContinue::Continue
+    );
+    assert_eq!(runner.pc(), 3);
+    assert!(runner.backtrace().is_empty());
+}
+
+#[test]
+fn inspects_the_stack() {
+    // mov R00, 0x1234; push R00; halt
+    let program = executable(RISC, 8, &[0x18, 0, 0x12, 0x34, 0xa0, 0, 0]);
+    let mut runner = load(ProcessorType::Risc, &program);
+    run_to_end(&mut runner, &mut BufferedIo::new());
+    let frame = runner.stack_frame().unwrap();
+    assert_eq!(frame.values(), vec![0x1234]);
+    assert_eq!(frame.pointer(), 1022);
+    assert_eq!(runner.peek_stack(0), 0x1234);
+}
+
+#[test]
+fn reports_stacks_run_past_either_end() {
+    // pop R00; halt
+    let program = executable(RISC, 8, &[0xa4, 0, 0]);
+    let mut runner = load(ProcessorType::Risc, &program);
+    run_to_end(&mut runner, &mut BufferedIo::new());
+    assert_eq!(
+        runner.stack_frame().err().unwrap(),
+        "Stack underflow: SP is 0x0402, past the stack base 0x0400"
+    );
+
+    let mut runner = Runner::new(ProcessorType::Stack);
+    runner.set_register("SP", 0xffff).unwrap();
+    assert_eq!(
+        runner.stack_frames().err().unwrap(),
+        "Stack overflow: SP is 0xffff, past the stack limit 0xfffe"
+    );
+    runner.set_register("SP", 0x0400).unwrap();
+    assert_eq!(runner.stack_frames().map(|frames| frames.len()), Ok(2));
+}
+
+#[test]
+fn reads_and_writes_typed_memory() {
+    let mut runner = Runner::new(ProcessorType::Stack);
+    runner.write_u16(MemoryType::Data, 100, 0xbeef).unwrap();
+    assert_eq!(runner.read_u16(MemoryType::Data, 100).unwrap(), 0xbeef);
+    runner.write_i16(MemoryType::Text, 10, -2).unwrap();
+    assert_eq!(runner.read_i16(MemoryType::Text, 10).unwrap(), -2);
+    runner.write_string(MemoryType::Data, 200, "hi").unwrap();
+    assert_eq!(runner.read_string(MemoryType::Data, 200, 16).unwrap(), "hi");
+    // Text cells of the stack processor only hold six bits.
+    assert!(runner.write_string(MemoryType::Text, 0, "A").is_err());
+    assert!(runner.read_u16(MemoryType::Data, 0xffff).is_err());
+}
+
+fn cells(runner: &Runner, mem_type: MemoryType, start: usize, end: usize) -> Vec<u8> {
+    runner
+        .export_image(ImageFormat::Flat, mem_type, start, end)
+        .unwrap()
+}
+
+#[test]
+fn typed_memory_follows_each_word_layout() {
+    // Words are big-endian everywhere: two 8-bit cells on the accumulator
+    // processor, three 6-bit cells in the stack processor's text memory.
+    let mut runner = Runner::new(ProcessorType::Acc);
+    runner.write_u16(MemoryType::Text, 4, 0x1234).unwrap();
+    assert_eq!(cells(&runner, MemoryType::Text, 4, 6), vec![0x12, 0x34]);
+    runner.set_memory(MemoryType::Text, 6, 0xff);
+    runner.set_memory(MemoryType::Text, 7, 0xfe);
+    assert_eq!(runner.read_u16(MemoryType::Text, 6).unwrap(), 0xfffe);
+    assert_eq!(runner.read_i16(MemoryType::Text, 6).unwrap(), -2);
+    assert!(runner.read_u16(MemoryType::Data, 0).is_err());
+
+    let mut runner = Runner::new(ProcessorType::Stack);
+    runner.write_u16(MemoryType::Text, 0, 0xabcd).unwrap();
+    assert_eq!(
+        cells(&runner, MemoryType::Text, 0, 3),
+        vec![0x0a, 0x2f, 0x0d]
+    );
+    runner.write_i16(MemoryType::Data, 0, -300).unwrap();
+    assert_eq!(cells(&runner, MemoryType::Data, 0, 2), vec![0xfe, 0xd4]);
+    assert_eq!(runner.read_i16(MemoryType::Data, 0).unwrap(), -300);
+}
+
+#[test]
+fn reads_and_writes_arrays_and_strings() {
+    let mut runner = Runner::new(ProcessorType::Risc);
+    runner
+        .write_array(MemoryType::Text, 10, &[1, 0x200, 0xffff])
+        .unwrap();
+    assert_eq!(
+        cells(&runner, MemoryType::Text, 10, 16),
+        vec![0, 1, 2, 0, 0xff, 0xff]
+    );
+    assert_eq!(
+        runner.read_array(MemoryType::Text, 10, 3).unwrap(),
+        vec![1, 0x200, 0xffff]
+    );
+    assert_eq!(
+        runner.read_array(MemoryType::Text, 10, 0).unwrap(),
+        Vec::<u16>::new()
+    );
+    assert!(runner.read_array(MemoryType::Text, 0xfffe, 2).is_err());
+
+    runner.write_string(MemoryType::Text, 40, "hello").unwrap();
+    assert_eq!(
+        cells(&runner, MemoryType::Text, 40, 46),
+        b"hello\0".to_vec()
+    );
+    assert_eq!(runner.read_string(MemoryType::Text, 40, 3).unwrap(), "hel");
+    assert!(runner
+        .write_string(MemoryType::Text, 40, "héllo\u{263a}")
+        .is_err());
+    // A failed write leaves memory alone.
+    assert_eq!(
+        runner.read_string(MemoryType::Text, 40, 16).unwrap(),
+        "hello"
+    );
+    assert!(runner.write_array(MemoryType::Text, 0xffff, &[1]).is_err());
+}
+
+#[test]
+fn stops_when_the_budget_runs_out() {
+    // in R00, port 0; jmp 0x0000
+    let program = executable(RISC, 8, &[0x80, 0, 0, 0, 0x64, 0, 0]);
+    let mut runner = load(ProcessorType::Risc, &program);
+    let mut io = BufferedIo::new();
+    runner.set_step_budget(Some(10));
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Timeout
+    );
+    assert_eq!(runner.steps(), 10);
+    assert_eq!(runner.run_with(&mut io), WasmProcessorContinue::Timeout);
+
+    runner.reset_watchdog();
+    runner.set_step_budget(None);
+    runner.set_time_budget(Some(0.0));
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Timeout
+    );
+    assert_eq!(runner.steps(), 0);
+}
+
+#[test]
+fn detects_loops_that_read_no_input() {
+    // mov R00, 1; jmp 0x0004
+    let program = executable(RISC, 8, &[0x18, 0, 0, 1, 0x64, 0, 0x10]);
+    let mut runner = load(ProcessorType::Risc, &program);
+    runner.set_loop_detection(true);
+    assert_eq!(
+        run_to_end(&mut runner, &mut BufferedIo::new()),
+        WasmProcessorContinue::Loop
+    );
+    assert_eq!(runner.pc(), 4);
+    assert_eq!(runner.describe_address(4).as_deref(), Some("_start+0x4"));
+
+    // in R00, port 0; jmp 0x0000
+    let program = executable(RISC, 8, &[0x80, 0, 0, 0, 0x64, 0, 0]);
+    let mut runner = load(ProcessorType::Risc, &program);
+    runner.set_loop_detection(true);
+    assert_eq!(
+        run_to_end(&mut runner, &mut BufferedIo::new()),
+        WasmProcessorContinue::Continue
+    );
+}
+
+#[test]
+fn resets_to_the_loaded_program() {
+    // mov R00, 'H'; out 0, R00; halt
+    let program = executable(RISC, 8, &[0x18, 0, 0, 0x48, 0x88, 0, 0, 0, 0]);
+    let mut runner = load(ProcessorType::Risc, &program);
+    runner.add_breakpoint(4);
+    let mut io = BufferedIo::new();
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Breakpoint
+    );
+    runner.write_u16(MemoryType::Text, 0x100, 0xbeef).unwrap();
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Halt
+    );
+
+    runner.reset().unwrap();
+    assert_eq!(runner.pc(), 0);
+    assert_eq!(register(&mut runner, "R00"), 0);
+    assert_eq!(runner.read_u16(MemoryType::Text, 0x100).unwrap(), 0);
+    assert_eq!(runner.steps(), 0);
+    assert_eq!(runner.breakpoints(), vec![4]);
+    let mut io = BufferedIo::new();
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Breakpoint
+    );
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Halt
+    );
+    assert_eq!(io.outputs(), &[(0, 0x48)]);
+
+    runner.hard_reset();
+    assert!(runner.breakpoints().is_empty());
+    assert_eq!(runner.read_u16(MemoryType::Text, 0).unwrap(), 0);
+    assert!(runner.reset().is_err());
+}
+
+fn small_risc() -> ProcessorConfig {
+    let mut config = ProcessorConfig::new();
+    config.set_text_memory_size(0x100);
+    config.set_stack("SP", 0xf0, 0xc0);
+    config
+}
+
+#[test]
+fn limits_memory_to_the_configuration() {
+    let mut config = ProcessorConfig::new();
+    config.set_text_memory_size(0x100);
+    assert!(Runner::with_config(ProcessorType::Risc, &config)
+        .err()
+        .unwrap()
+        .contains("The SP stack"));
+    assert!(Runner::with_config(ProcessorType::Risc, &{
+        let mut config = small_risc();
+        config.set_data_memory_size(16);
+        config
+    })
+    .is_err());
+
+    let mut runner = Runner::with_config(ProcessorType::Risc, &small_risc()).unwrap();
+    assert_eq!(runner.get_memory()[0].values().len(), 0x100);
+    assert!(!runner.set_memory(MemoryType::Text, 0x100, 1));
+    assert!(runner.read_u16(MemoryType::Text, 0xff).is_err());
+    assert_eq!(register(&mut runner, "SP"), 0xf0);
+
+    // mov R00, 0x0120; load R00, [R00]; halt
+    let program = executable(RISC, 8, &[0x18, 0, 0x01, 0x20, 0x04, 0, 0]);
+    runner.load_program(&program).unwrap();
+    let mut io = BufferedIo::new();
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Error
+    );
+    assert_eq!(runner.pc(), 4);
+    assert!(runner
+        .fault()
+        .unwrap()
+        .contains("outside the 256-cell text memory"));
+
+    let mut config = small_risc();
+    config.set_text_memory_size(4);
+    config.set_stack("SP", 2, 0);
+    let mut runner = Runner::with_config(ProcessorType::Risc, &config).unwrap();
+    assert!(runner.load_program(&program).is_err());
+}
+
+#[test]
+fn stops_when_the_configured_stack_overflows() {
+    // push R00; jmp 0x0000
+    let program = executable(RISC, 8, &[0xa0, 0, 0x64, 0, 0]);
+    let mut config = small_risc();
+    config.set_initial_pc(2);
+    let mut runner = Runner::with_config(ProcessorType::Risc, &config).unwrap();
+    runner.load_program(&program).unwrap();
+    assert_eq!(runner.pc(), 2);
+    runner.reset().unwrap();
+    assert_eq!(runner.pc(), 2);
+    assert_eq!(
+        run_to_end(&mut runner, &mut BufferedIo::new()),
+        WasmProcessorContinue::Error
+    );
+    assert_eq!(
+        runner.fault().unwrap(),
+        "Stack overflow: SP is 0x00be, past the stack limit 0x00c0"
+    );
+    runner.hard_reset();
+    assert_eq!(register(&mut runner, "SP"), 0xf0);
+}
+
+fn small_stack() -> ProcessorConfig {
+    let mut config = ProcessorConfig::new();
+    config.set_data_memory_size(0x200);
+    config.set_stack("TOS", 0x100, 0xfc);
+    config.set_stack("SP", 0x180, 0x1a0);
+    config
+}
+
+#[test]
+fn checks_stack_processor_accesses_before_running() {
+    // The address comes off the register stack: mov 0x0300 and then
+    // load; store 7; mov 7, store.
+    for (tail, mnemonic) in [
+        (&[0x01][..], "load"),
+        (&[0x21, 0, 0, 7][..], "store"),
+        (&[0x22, 0, 0, 7, 0x04][..], "store"),
+    ]
+    .iter()
+    {
+        let mut program = vec![0x22, 0, 12, 0];
+        program.extend(*tail);
+        let mut runner = Runner::with_config(ProcessorType::Stack, &small_stack()).unwrap();
+        runner
+            .load_program(&executable(STACK, 6, &program))
+            .unwrap();
+        assert_eq!(
+            run_to_end(&mut runner, &mut BufferedIo::new()),
+            WasmProcessorContinue::Error
+        );
+        assert_eq!(
+            runner.fault().unwrap(),
+            format!(
+                "{} accesses 0x0300 from the stack, outside the 512-cell data memory",
+                mnemonic
+            )
+        );
+    }
+
+    // mov 1; mov 2; mov 3: the third push doesn't fit, so it never runs.
+    let program = [0x22, 0, 0, 1, 0x22, 0, 0, 2, 0x22, 0, 0, 3];
+    let mut runner = Runner::with_config(ProcessorType::Stack, &small_stack()).unwrap();
+    runner
+        .load_program(&executable(STACK, 6, &program))
+        .unwrap();
+    assert_eq!(
+        run_to_end(&mut runner, &mut BufferedIo::new()),
+        WasmProcessorContinue::Error
+    );
+    assert_eq!(
+        runner.fault().unwrap(),
+        "Stack overflow: TOS is 0x00fa, past the stack limit 0x00fc"
+    );
+    assert_eq!(runner.pc(), 8);
+    assert_eq!(register(&mut runner, "TOS"), 0xfc);
+    assert_eq!(runner.read_u16(MemoryType::Data, 0xfa).unwrap(), 0);
+
+    // add on an empty stack underflows before it writes its result.
+    let mut runner = Runner::with_config(ProcessorType::Stack, &small_stack()).unwrap();
+    runner.load_program(&executable(STACK, 6, &[0x0e])).unwrap();
+    assert_eq!(
+        run_to_end(&mut runner, &mut BufferedIo::new()),
+        WasmProcessorContinue::Error
+    );
+    assert_eq!(
+        runner.fault().unwrap(),
+        "Stack underflow: TOS is 0x0104, past the stack base 0x0100"
+    );
+    assert_eq!(runner.read_u16(MemoryType::Data, 0x102).unwrap(), 0);
+}
+
+#[test]
+fn checks_shifts_and_frames_against_the_configured_stack() {
+    // mov 1; lsh 3; rsh 1; halt: the shifts pop their operand before they
+    // push the result, so they run with a single word of room.
+    let program = [0x22, 0, 0, 1, 0x23, 0, 0, 3, 0x24, 0, 0, 1, 0];
+    let mut config = ProcessorConfig::new();
+    config.set_stack("TOS", 0x100, 0xfe);
+    let mut runner = Runner::with_config(ProcessorType::Stack, &config).unwrap();
+    runner
+        .load_program(&executable(STACK, 6, &program))
+        .unwrap();
+    assert_eq!(
+        run_to_end(&mut runner, &mut BufferedIo::new()),
+        WasmProcessorContinue::Halt
+    );
+    assert_eq!(runner.fault(), None);
+    assert_eq!(runner.read_u16(MemoryType::Data, 0xfe).unwrap(), 4);
+
+    // enter 0, three times, on CISC, which has no executables: the third
+    // push of the frame pointer doesn't fit.
+    let program = [0x41, 0, 0, 0x41, 0, 0, 0x41, 0, 0, 0];
+    let mut config = ProcessorConfig::new();
+    config.set_stack("SP", 0x400, 0x3fc);
+    let mut runner = Runner::with_config(ProcessorType::Cisc, &config).unwrap();
+    runner
+        .load_image(ImageFormat::Flat, &program, &ImageOptions::new())
+        .unwrap();
+    assert_eq!(
+        run_to_end(&mut runner, &mut BufferedIo::new()),
+        WasmProcessorContinue::Error
+    );
+    assert_eq!(
+        runner.fault().unwrap(),
+        "Stack overflow: SP is 0x03fa, past the stack limit 0x03fc"
+    );
+    assert_eq!(runner.pc(), 6);
+}
+
+/// jmp 0x0000; handler: out 0, R00; ret
+const RISC_IDLE: &[u16] = &[0x64, 0, 0, 0x88, 0, 0, 0, 0x50];
+
+#[test]
+fn delivers_timer_interrupts() {
+    let mut runner = load(ProcessorType::Risc, &executable(RISC, 8, RISC_IDLE));
+    assert!(runner.set_timer(5, 0).is_err());
+    runner.set_interrupt_vector(0, 3).unwrap();
+    runner.set_timer(5, 0).unwrap();
+    runner.set_loop_detection(true);
+    let mut io = BufferedIo::new();
+    assert_eq!(
+        runner.run_n_with(&mut io, 200),
+        WasmProcessorContinue::Continue
+    );
+    // The timer counts the handler's instructions too; the last interrupt
+    // is still waiting.
+    assert_eq!(io.outputs().len(), 200 / 5 - 1);
+    assert_eq!(runner.pending_interrupts(), [0]);
+    assert_eq!(register(&mut runner, "SP"), 1024);
+}
+
+#[test]
+fn holds_interrupts_until_they_can_be_delivered() {
+    let mut runner = load(ProcessorType::Risc, &executable(RISC, 8, RISC_IDLE));
+    assert!(runner.raise_interrupt(1).is_err());
+    assert!(runner.set_interrupt_vector(8, 3).is_err());
+    runner.set_interrupt_vector(1, 3).unwrap();
+    runner.set_interrupts_enabled(false);
+    runner.raise_interrupt(1).unwrap();
+    let mut io = BufferedIo::new();
+    runner.run_n_with(&mut io, 10);
+    assert_eq!(runner.pending_interrupts(), [1]);
+
+    runner.set_interrupts_enabled(true);
+    runner.add_breakpoint(3);
+    assert_eq!(
+        runner.run_n_with(&mut io, 10),
+        WasmProcessorContinue::Breakpoint
+    );
+    assert_eq!(runner.interrupt_in_service(), Some(1));
+    assert_eq!(runner.backtrace()[0].return_address(), 0);
+    assert_eq!(runner.peek_stack(0), 0);
+
+    runner.raise_interrupt(1).unwrap();
+    runner.run_n_with(&mut io, 2);
+    assert_eq!(runner.pc(), 0);
+    assert_eq!(runner.interrupt_in_service(), None);
+    assert!(runner.backtrace().is_empty());
+    // The second interrupt waited for the first handler to return.
+    assert_eq!(runner.pending_interrupts(), [1]);
+    runner.clear_interrupt(1).unwrap();
+    runner.run_n_with(&mut io, 10);
+    assert_eq!(io.outputs().len(), 1);
+}
+
+/// Overflowing shifts panic in debug builds of the emulator, so they are
+/// stopped before they run in every build.
+#[test]
+fn rejects_shifts_the_emulator_would_panic_on() {
+    // mov R00, 20; mov R01, 20; lsh R00, R00, R01; halt
+    let program = &[0x18, 0, 0, 20, 0x18, 0x80, 0, 20, 0x40, 0x02, 0];
+    let mut runner = load(ProcessorType::Risc, &executable(RISC, 8, program));
+    let mut io = BufferedIo::new();
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Error
+    );
+    assert_eq!(
+        runner.fault().unwrap(),
+        "lsh R00, R00, R01 shifts by 20, but words only hold 16 bits"
+    );
+    assert_eq!(register(&mut runner, "R00"), 20);
+    assert_eq!(runner.pc(), 8);
+    let steps = runner.steps();
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Error
+    );
+    assert_eq!(runner.steps(), steps);
+
+    runner.reset().unwrap();
+    assert_eq!(runner.fault(), None);
+    assert_eq!(runner.pc(), 0);
+    assert_eq!(
+        runner.run_n_with(&mut io, 2),
+        WasmProcessorContinue::Continue
+    );
+}
+
+#[test]
+fn rejects_writes_and_segments_outside_memory() {
+    let mut runner = Runner::new(ProcessorType::Stack);
+    assert!(!runner.set_memory(MemoryType::Text, 0, 64));
+    assert!(!runner.set_memory(MemoryType::Text, 1 << 16, 1));
+    assert!(!runner.set_memory(MemoryType::Data, 1 << 16, 1));
+    assert!(runner.set_memory(MemoryType::Text, 0, 63));
+
+    // The segment header starts with its address and size, after the
+    // architecture, the header count and the entry point.
+    let mut program = executable(RISC, 8, &[0; 16]);
+    program[17..25].copy_from_slice(&0xfff8u64.to_le_bytes());
+    let mut runner = Runner::new(ProcessorType::Risc);
+    assert!(runner
+        .load_program(&program)
+        .unwrap_err()
+        .contains("doesn't fit"));
+    let mut program = executable(RISC, 8, &[0; 16]);
+    program[25..33].copy_from_slice(&32u64.to_le_bytes());
+    assert!(runner
+        .load_program(&program)
+        .unwrap_err()
+        .contains("less data"));
+}
+
+/// Ports that fail the `fail_at`th write, counting from 1.
+struct FailingIo {
+    writes: usize,
+    fail_at: usize,
+    failure: Option<IoFailure>,
+}
+
+impl PortIo for FailingIo {
+    fn output(&mut self, port: u16, _value: u16) {
+        self.writes += 1;
+        if self.writes == self.fail_at {
+            self.failure = Some(IoFailure::new("The port is gone", port, false));
+        }
+    }
+
+    fn input(&mut self, _port: u16) -> u16 {
+        0
+    }
+
+    fn take_failure(&mut self) -> Option<IoFailure> {
+        self.failure.take()
+    }
+}
+
+#[test]
+fn stops_at_a_failed_port_access() {
+    // out 0, R00; out 0, R00; jmp 0x0000
+    let program = executable(RISC, 8, &[0x88, 0, 0, 0, 0x88, 0, 0, 0, 0x64, 0, 0]);
+    let mut runner = load(ProcessorType::Risc, &program);
+    let mut io = FailingIo {
+        writes: 0,
+        fail_at: 3,
+        failure: None,
+    };
+    assert_eq!(
+        runner.run_n_with(&mut io, 100),
+        WasmProcessorContinue::IoError
+    );
+    assert_eq!(runner.steps(), 4);
+    let failure = runner.io_failure().unwrap();
+    assert_eq!(failure.pc(), 0);
+    assert_eq!(failure.port(), 0);
+    assert!(!failure.is_input());
+    assert_eq!(failure.message(), "The port is gone");
+
+    // The write that fails is the one the loop detector stops at.
+    runner.reset().unwrap();
+    runner.set_loop_detection(true);
+    let mut io = FailingIo {
+        writes: 0,
+        fail_at: 0,
+        failure: None,
+    };
+    assert_eq!(runner.run_n_with(&mut io, 100), WasmProcessorContinue::Loop);
+    assert_eq!(runner.pc(), 4);
+    let writes = io.writes;
+    runner.reset().unwrap();
+    let mut io = FailingIo {
+        writes: 0,
+        fail_at: writes,
+        failure: None,
+    };
+    assert_eq!(
+        runner.run_n_with(&mut io, 100),
+        WasmProcessorContinue::IoError
+    );
+    assert_eq!(runner.io_failure().unwrap().pc(), 0);
+}
+
+#[test]
+fn evaluates_expressions_over_the_machine() {
+    let mut runner = load(ProcessorType::Risc, &executable(RISC, 8, RISC_IDLE));
+    runner.set_register("R00", 5).unwrap();
+    runner.write_u16(MemoryType::Text, 0x10, 0xbeef).unwrap();
+    assert_eq!(runner.evaluate("r00 * 2 + 1"), Ok(11));
+    assert_eq!(runner.evaluate("(R00 << 2) - 1 == 19 && !ZF"), Ok(1));
+    assert_eq!(runner.evaluate("0 - 1 > 0x10 | 0b10"), Ok(3));
+    assert_eq!(runner.evaluate("[0x8 + 8]"), Ok(0xbeef));
+    // Without a separate data memory, `[addr]` reads text memory.
+    assert_eq!(runner.evaluate("text[0x10] == [0x10]"), Ok(1));
+    assert!(runner.evaluate("data[0x10]").is_err());
+    assert_eq!(
+        runner.evaluate("text[_start + 3]"),
+        runner.read_u16(MemoryType::Text, 3)
+    );
+    assert!(runner.evaluate("R09").is_err());
+    assert!(runner.evaluate("1 / 0").is_err());
+    assert!(runner.evaluate("(1").is_err());
+    assert!(runner.evaluate("[0xffff]").is_err());
+}
+
+#[test]
+fn stops_at_breakpoints_whose_condition_holds() {
+    let mut runner = load(ProcessorType::Risc, &executable(RISC, 8, RISC_IDLE));
+    let mut io = BufferedIo::new();
+    assert!(runner
+        .add_conditional_breakpoint(0, Some("R00 ==".to_string()), None)
+        .is_err());
+    runner
+        .add_conditional_breakpoint(0, Some("R00 == 7".to_string()), Some(2))
+        .unwrap();
+    assert_eq!(
+        runner.run_n_with(&mut io, 50),
+        WasmProcessorContinue::Continue
+    );
+    assert_eq!(runner.breakpoint_hits(0), Some(0));
+
+    runner.set_register("R00", 7).unwrap();
+    assert_eq!(
+        runner.run_n_with(&mut io, 50),
+        WasmProcessorContinue::Breakpoint
+    );
+    assert_eq!(runner.breakpoint_hits(0), Some(2));
+    assert_eq!(runner.run_with(&mut io), WasmProcessorContinue::Breakpoint);
+
+    runner.reset().unwrap();
+    assert_eq!(runner.breakpoint_hits(0), Some(0));
+}
+
+#[test]
+fn logs_at_logpoints_without_stopping() {
+    let mut runner = load(ProcessorType::Risc, &executable(RISC, 8, RISC_CALL));
+    assert!(runner.add_logpoint(3, "{R00").is_err());
+    assert!(runner.add_logpoint(3, "R00}").is_err());
+    assert!(runner.add_logpoint(3, "{R00:q}").is_err());
+    runner
+        .add_logpoint(8, "entered with {{R00}} = {R00}")
+        .unwrap();
+    runner.add_logpoint(3, "R00 = {R00:x}, {R09}").unwrap();
+    assert_eq!(
+        run_to_end(&mut runner, &mut BufferedIo::new()),
+        WasmProcessorContinue::Halt
+    );
+    let log = runner.drain_log();
+    let messages: Vec<(u16, String)> = log
+        .iter()
+        .map(|entry| (entry.address(), entry.message()))
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            (8, "entered with {R00} = 0".to_string()),
+            (
+                3,
+                "R00 = 0x0005, <error: There is no register, flag or symbol named R09>".to_string()
+            ),
+        ]
+    );
+    assert_eq!(log[0].steps(), 1);
+    assert!(runner.drain_log().is_empty());
+}
+
+#[test]
+fn checks_the_entry_point_before_the_first_step() {
+    let mut runner = load(ProcessorType::Risc, &executable(RISC, 8, RISC_CALL));
+    runner
+        .add_conditional_breakpoint(0, Some("R00 == 0".to_string()), None)
+        .unwrap();
+    runner.add_logpoint(0, "starting").unwrap();
+    let mut io = BufferedIo::new();
+    for _ in 0..2 {
+        assert_eq!(
+            run_to_end(&mut runner, &mut io),
+            WasmProcessorContinue::Breakpoint
+        );
+        assert_eq!(runner.steps(), 0);
+        assert_eq!(runner.breakpoint_hits(0), Some(1));
+        let log = runner.drain_log();
+        assert_eq!(log.len(), 1);
+        assert_eq!((log[0].address(), log[0].steps()), (0, 0));
+
+        // Only the first step checks the entry point.
+        assert_eq!(
+            run_to_end(&mut runner, &mut io),
+            WasmProcessorContinue::Halt
+        );
+        assert!(runner.drain_log().is_empty());
+        runner.reset().unwrap();
+    }
+}
+
+fn catch_description(runner: &Runner) -> String {
+    runner.last_catch().unwrap().description()
+}
+
+#[test]
+fn catches_port_accesses() {
+    // in 3; out 4; halt
+    let program = executable(ACCUMULATOR, 8, &[0x8e, 0, 3, 0x98, 0, 4, 0]);
+    let mut runner = load(ProcessorType::Acc, &program);
+    let mut io = BufferedIo::new();
+    io.queue_input(3, vec![42]);
+    runner.catch_port(Some(4), true, false);
+    let any_output = runner.catch_port(None, false, true);
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Catchpoint
+    );
+    assert_eq!(runner.last_catch().unwrap().catchpoint(), any_output);
+    assert_eq!(catch_description(&runner), "OUT to port 4");
+    assert_eq!(runner.pc(), 6);
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Halt
+    );
+    assert!(runner.last_catch().is_none());
+
+    // mov 7; out 1; halt
+    let program = executable(STACK, 6, &[0x22, 0, 0, 7, 0x2b, 0, 0, 1, 0]);
+    let mut runner = load(ProcessorType::Stack, &program);
+    runner.catch_port(Some(1), false, true);
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Catchpoint
+    );
+    assert_eq!(catch_description(&runner), "OUT to port 1");
+}
+
+#[test]
+fn catches_calls_and_register_changes() {
+    let mut runner = load(ProcessorType::Risc, &executable(RISC, 8, RISC_CALL));
+    let mut io = BufferedIo::new();
+    assert!(runner.catch_register("R99", None).is_err());
+    let calls = runner.catch_calls(false, true);
+    runner.catch_register("r00", Some(5)).unwrap();
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Catchpoint
+    );
+    assert_eq!(
+        catch_description(&runner),
+        "R00 crossed 0x0005, going from 0x0000 to 0x0005"
+    );
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Catchpoint
+    );
+    assert_eq!(catch_description(&runner), "RET to 0x0003");
+    assert!(runner.remove_catchpoint(calls));
+    assert_eq!(runner.catchpoints().len(), 1);
+
+    runner.reset().unwrap();
+    runner.clear_catchpoints();
+    runner.catch_register("SP", None).unwrap();
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Catchpoint
+    );
+    assert_eq!(
+        catch_description(&runner),
+        "SP changed from 0x0400 to 0x03fe"
+    );
+}
+
+#[test]
+fn catches_writes_to_text_memory() {
+    // mov R00, 0x12; mov R01, 0x20; store [R01], R00; halt
+    let program = executable(
+        RISC,
+        8,
+        &[0x18, 0, 0, 0x12, 0x18, 0x80, 0, 0x20, 0x08, 0x80, 0],
+    );
+    let mut runner = load(ProcessorType::Risc, &program);
+    assert!(runner.catch_text_writes(0x20, 0x20).is_err());
+    assert!(runner.catch_text_writes(0xfff0, 0x10010).is_err());
+    runner.catch_text_writes(0, 0x10).unwrap();
+    runner.catch_text_writes(0x20, 0x22).unwrap();
+    let mut io = BufferedIo::new();
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Catchpoint
+    );
+    assert_eq!(
+        catch_description(&runner),
+        "Text memory at 0x0021 changed from 0x00 to 0x12"
+    );
+    assert_eq!(runner.last_catch().unwrap().catchpoint(), 1);
+
+    // The stack shares memory with the code, so a CALL writes text memory
+    // too, at 0x03fe and 0x03ff.
+    let mut runner = load(ProcessorType::Risc, &executable(RISC, 8, RISC_CALL));
+    runner.catch_text_writes(0x3f0, 0x400).unwrap();
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Catchpoint
+    );
+    assert_eq!(runner.pc(), 8);
+    assert_eq!(
+        catch_description(&runner),
+        "Text memory at 0x03ff changed from 0x00 to 0x03"
+    );
+}
+
+#[test]
+fn describes_what_a_step_did() {
+    let mut runner = load(ProcessorType::Risc, &executable(RISC, 8, RISC_CALL));
+    let mut io = BufferedIo::new();
+    let call = runner.step_with(&mut io);
+    assert_eq!(call.status(), WasmProcessorContinue::Continue);
+    assert_eq!((call.pc_before(), call.pc_after()), (0, 8));
+    assert_eq!(call.kind().as_deref(), Some("call"));
+    assert_eq!(call.branch_taken(), Some(true));
+    let registers: Vec<(String, u16, u16)> = call
+        .registers()
+        .iter()
+        .map(|change| (change.name(), change.old_value(), change.new_value()))
+        .collect();
+    assert!(registers.contains(&("SP".to_string(), 1024, 1022)));
+    let writes = call.memory_writes();
+    assert_eq!(writes.len(), 1);
+    assert_eq!(
+        (writes[0].address(), writes[0].value(), writes[0].previous()),
+        (1022, 3, Some(0))
+    );
+
+    let mov = runner.step_with(&mut io);
+    assert_eq!(mov.instruction().as_deref(), Some("mov R00, 5"));
+    assert_eq!(mov.branch_taken(), None);
+    let ret = runner.step_with(&mut io);
+    let reads = ret.memory_reads();
+    assert_eq!(reads.len(), 1);
+    assert_eq!((reads[0].address(), reads[0].value()), (1022, 3));
+    let out = runner.step_with(&mut io);
+    assert_eq!(out.ports().len(), 1);
+    assert_eq!(
+        (
+            out.ports()[0].port(),
+            out.ports()[0].is_input(),
+            out.ports()[0].value()
+        ),
+        (0, false, 5)
+    );
+
+    // mov R01, 0x20; load R00, [R01]; halt
+    let program = executable(RISC, 8, &[0x18, 0x80, 0, 0x20, 0x04, 0x10, 0]);
+    let mut runner = load(ProcessorType::Risc, &program);
+    runner.write_u16(MemoryType::Text, 0x20, 0xbeef).unwrap();
+    runner.step_with(&mut io);
+    let load = runner.step_with(&mut io);
+    assert_eq!(load.instruction().as_deref(), Some("load R00, [R01]"));
+    let reads = load.memory_reads();
+    assert_eq!(reads.len(), 1);
+    assert_eq!((reads[0].address(), reads[0].value()), (0x20, 0xbeef));
+    assert!(load.memory_writes().is_empty());
+    let halt = runner.step_with(&mut io);
+    assert_eq!(halt.status(), WasmProcessorContinue::Halt);
+}
+
+#[test]
+fn describes_stack_accesses_by_word() {
+    let accesses = |list: Vec<MemoryAccess>| -> Vec<(u16, u16, Option<u16>)> {
+        list.iter()
+            .map(|access| (access.address(), access.value(), access.previous()))
+            .collect()
+    };
+    // mov 0x20; load; push; pop; halt
+    let program = executable(STACK, 6, &[0x22, 0, 0, 0x20, 0x01, 0x09, 0x0c, 0]);
+    let mut runner = load(ProcessorType::Stack, &program);
+    runner.write_u16(MemoryType::Data, 0x20, 0xbeef).unwrap();
+    let mut io = BufferedIo::new();
+
+    let mov = runner.step_with(&mut io);
+    assert_eq!(accesses(mov.memory_writes()), [(254, 0x20, Some(0))]);
+    // The address comes off the stack, and the value read takes its place.
+    let load = runner.step_with(&mut io);
+    assert_eq!(accesses(load.memory_reads()), [(0x20, 0xbeef, None)]);
+    assert_eq!(accesses(load.memory_writes()), [(254, 0xbeef, Some(0x20))]);
+    let push = runner.step_with(&mut io);
+    assert_eq!(accesses(push.memory_reads()), [(254, 0xbeef, None)]);
+    assert_eq!(accesses(push.memory_writes()), [(1026, 0xbeef, Some(0))]);
+    // Writing the value the cell already holds still counts as a write.
+    let pop = runner.step_with(&mut io);
+    assert_eq!(accesses(pop.memory_reads()), [(1026, 0xbeef, None)]);
+    assert_eq!(accesses(pop.memory_writes()), [(254, 0xbeef, Some(0xbeef))]);
+}
+
+#[test]
+fn describes_writes_by_what_the_operand_is_for() {
+    let accesses = |list: Vec<MemoryAccess>| -> Vec<(u16, u16, Option<u16>)> {
+        list.iter()
+            .map(|access| (access.address(), access.value(), access.previous()))
+            .collect()
+    };
+    let mut io = BufferedIo::new();
+
+    // mov R00, 0x12; mov R01, 0x20; store [R01], R00; halt, over a word
+    // that already holds 0x12.
+    let program = executable(
+        RISC,
+        8,
+        &[0x18, 0, 0, 0x12, 0x18, 0x80, 0, 0x20, 0x08, 0x80, 0],
+    );
+    let mut runner = load(ProcessorType::Risc, &program);
+    runner.write_u16(MemoryType::Text, 0x20, 0x12).unwrap();
+    runner.step_with(&mut io);
+    runner.step_with(&mut io);
+    let store = runner.step_with(&mut io);
+    assert_eq!(store.instruction().as_deref(), Some("store [R01], R00"));
+    assert!(store.memory_reads().is_empty());
+    assert_eq!(accesses(store.memory_writes()), [(0x20, 0x12, Some(0x12))]);
+
+    // mov 1; lsh 3; rsh 1; halt: each shift takes the top of the stack and
+    // leaves its result in the same word.
+    let program = [0x22, 0, 0, 1, 0x23, 0, 0, 3, 0x24, 0, 0, 1, 0];
+    let mut runner = load(ProcessorType::Stack, &executable(STACK, 6, &program));
+    runner.step_with(&mut io);
+    let lsh = runner.step_with(&mut io);
+    assert_eq!(lsh.instruction().as_deref(), Some("lsh 3"));
+    assert!(lsh.memory_reads().is_empty());
+    assert_eq!(accesses(lsh.memory_writes()), [(254, 8, Some(1))]);
+    let rsh = runner.step_with(&mut io);
+    assert!(rsh.memory_reads().is_empty());
+    assert_eq!(accesses(rsh.memory_writes()), [(254, 4, Some(8))]);
+}
+
+#[test]
+fn explains_each_step_in_words() {
+    let mut io = BufferedIo::new();
+    let explain = |runner: &mut Runner, io: &mut BufferedIo| {
+        runner.step_with(io);
+        runner.explain_last_step().unwrap()
+    };
+
+    // mov R00, 3; mov R01, 4; add R00, R00, R01; cmp R00, 7; halt
+    let program = executable(
+        RISC,
+        8,
+        &[
+            0x18, 0, 0, 3, 0x18, 0x80, 0, 4, 0x0c, 0x02, 0x58, 0, 0, 7, 0,
+        ],
+    );
+    let mut runner = load(ProcessorType::Risc, &program);
+    assert_eq!(runner.explain_last_step(), None);
+    assert_eq!(explain(&mut runner, &mut io), "MOV R00, 3 → R00 = 3");
+    explain(&mut runner, &mut io);
+    assert_eq!(
+        explain(&mut runner, &mut io),
+        "ADD R00, R00, R01 → R00 = 3 + 4 = 7; flags unchanged"
+    );
+    assert_eq!(
+        explain(&mut runner, &mut io),
+        "CMP R00, 7 → compares 7 with 7; CF set, ZF set"
+    );
+    assert_eq!(explain(&mut runner, &mut io), "HALT → halts the processor");
+    // Only steps are explained.
+    runner.reset().unwrap();
+    run_to_end(&mut runner, &mut io);
+    assert_eq!(runner.explain_last_step(), None);
+
+    let mut runner = load(ProcessorType::Risc, &executable(RISC, 8, RISC_CALL));
+    assert_eq!(
+        explain(&mut runner, &mut io),
+        "CALL 0x0008 → calls 0x0008, which will return to 0x0003"
+    );
+
+    // load 0x10; mov IR1, ACC; load 3; add [IR1]; halt
+    let program = executable(ACCUMULATOR, 8, &[0x90, 0, 0x10, 0x21, 0x90, 0, 3, 0x26, 0]);
+    let mut runner = load(ProcessorType::Acc, &program);
+    for _ in 0..3 {
+        runner.step_with(&mut io);
+    }
+    assert_eq!(
+        explain(&mut runner, &mut io),
+        "ADD [IR1] → ACC = 3 + 0 = 3, where [IR1] is [0x0010]; flags unchanged"
+    );
+
+    // mov 3; mov 4; sub; cmpe 1; halt
+    let program = executable(
+        STACK,
+        6,
+        &[0x22, 0, 0, 3, 0x22, 0, 0, 4, 0x0f, 0x26, 0, 0, 1, 0],
+    );
+    let mut runner = load(ProcessorType::Stack, &program);
+    assert_eq!(explain(&mut runner, &mut io), "MOV 3 → pushes 3");
+    explain(&mut runner, &mut io);
+    assert_eq!(
+        explain(&mut runner, &mut io),
+        "SUB → top of stack = 4 - 3 = 1; CF set"
+    );
+    assert_eq!(
+        explain(&mut runner, &mut io),
+        "CMPE 1 → top of stack = (1 == 1) = 0xffff; flags unchanged"
+    );
+
+    // mov R01, 5; mov [R00+2], R01 on CISC, which has no executables
+    let mut runner = Runner::new(ProcessorType::Cisc);
+    for (address, cell) in [0x80, 1, 0, 5, 0xa1, 0, 0, 2, 1, 0].iter().enumerate() {
+        assert!(runner.set_memory(MemoryType::Text, address, *cell));
+    }
+    runner.step_with(&mut io);
+    assert_eq!(
+        explain(&mut runner, &mut io),
+        "MOV [R00+0x0002], R01 → [R00+0x0002] = 5, where [R00+0x0002] is [0x0002]"
+    );
+}
+
+#[test]
+fn decodes_cisc_register_copies_the_way_the_emulator_runs_them() {
+    // mov R01, 5; then 0x62, which the emulator runs as mov R00, R01
+    let mut runner = Runner::new(ProcessorType::Cisc);
+    for (address, cell) in [0x80, 1, 0, 5, 0x62, 0x01, 0].iter().enumerate() {
+        assert!(runner.set_memory(MemoryType::Text, address, *cell));
+    }
+    assert_eq!(runner.disassemble(4).unwrap(), "mov R00, R01");
+    let mut io = BufferedIo::new();
+    runner.step_with(&mut io);
+    let copy = runner.step_with(&mut io);
+    assert_eq!(copy.status(), WasmProcessorContinue::Continue);
+    assert!(copy.memory_reads().is_empty());
+    assert!(copy.memory_writes().is_empty());
+    let registers: Vec<(String, u16)> = copy
+        .registers()
+        .iter()
+        .filter(|change| change.name() != "PC")
+        .map(|change| (change.name(), change.new_value()))
+        .collect();
+    assert_eq!(registers, [("R00".to_string(), 5)]);
+    // R00 held 0, so a store through it would have overwritten the first mov.
+    assert_eq!(runner.read_u16(MemoryType::Text, 0).unwrap(), 0x8001);
+}
+
+#[test]
+fn inspects_executables_without_loading_them() {
+    let info = inspect_executable(&executable(RISC, 8, RISC_CALL)).unwrap();
+    assert!(matches!(info.processor_type(), ProcessorType::Risc));
+    assert_eq!(info.entry_point(), 0);
+    let segments = info.segments();
+    assert_eq!(segments.len(), 1);
+    assert_eq!((segments[0].address(), segments[0].size()), (0, 13));
+    assert_eq!(segments[0].memory_type(), MemoryType::Text);
+    assert!(segments[0].is_executable() && !segments[0].is_writable());
+    let symbols = info.symbols();
+    assert_eq!(symbols.len(), 1);
+    assert_eq!(
+        (symbols[0].name().as_str(), symbols[0].address()),
+        ("_start", 0)
+    );
+    assert!(info.fits());
+
+    let mut program = executable(RISC, 8, &[0; 16]);
+    program[9..17].copy_from_slice(&0x1_0000u64.to_le_bytes());
+    program[17..25].copy_from_slice(&0xfff8u64.to_le_bytes());
+    let info = inspect_executable(&program).unwrap();
+    assert!(!info.fits());
+    let problems = info.problems();
+    assert_eq!(problems.len(), 2);
+    assert!(problems[0].contains("doesn't fit"));
+    assert!(problems[1].contains("entry point"));
+
+    assert!(inspect_executable(&[1, 2, 3]).is_err());
+}
+
+#[test]
+fn loads_flat_images_into_either_memory() {
+    // mov 7; out 1; halt, one 6-bit cell per byte
+    let mut runner = Runner::new(ProcessorType::Stack);
+    let mut options = ImageOptions::new();
+    options.set_base(0x10);
+    options.set_entry_point(0x10);
+    runner
+        .load_image(
+            ImageFormat::Flat,
+            &[0x22, 0, 0, 7, 0x2b, 0, 0, 1, 0],
+            &options,
+        )
+        .unwrap();
+    let mut data = ImageOptions::new();
+    data.set_memory_type(MemoryType::Data);
+    data.set_base(0x20);
+    data.set_keep_memory(true);
+    runner
+        .load_image(ImageFormat::Flat, &[0xbe, 0xef], &data)
+        .unwrap();
+    assert_eq!(runner.pc(), 0x10);
+    assert_eq!(runner.read_u16(MemoryType::Data, 0x20).unwrap(), 0xbeef);
+
+    let mut io = BufferedIo::new();
+    assert_eq!(
+        run_to_end(&mut runner, &mut io),
+        WasmProcessorContinue::Halt
+    );
+    assert_eq!(io.outputs(), &[(1, 7)]);
+    // Resetting goes back to both images.
+    runner.write_u16(MemoryType::Data, 0x20, 0).unwrap();
+    runner.reset().unwrap();
+    assert_eq!(runner.pc(), 0x10);
+    assert_eq!(runner.read_u16(MemoryType::Data, 0x20).unwrap(), 0xbeef);
+
+    assert!(runner
+        .load_image(ImageFormat::Flat, &[64], &ImageOptions::new())
+        .unwrap_err()
+        .contains("6-bit cell"));
+    let mut risc = Runner::new(ProcessorType::Risc);
+    assert_eq!(
+        risc.load_image(ImageFormat::Flat, &[1], &data).unwrap_err(),
+        "This processor has no data memory"
+    );
+}
+
+#[test]
+fn loads_intel_hex_and_s_records() {
+    // mov R00, 'H'; out 0, R00; halt at 0x10, starting there
+    let images: [(ImageFormat, &str); 2] = [
+        (
+            ImageFormat::IntelHex,
+            ":09001000180000488800000000FF\n:0400000500000010E7\n:00000001FF\n",
+        ),
+        (
+            ImageFormat::SRecord,
+            "S0050000686929\nS10C0010180000488800000000FB\nS9030010EC\n",
+        ),
+    ];
+    for (format, image) in images.iter() {
+        let mut runner = Runner::new(ProcessorType::Risc);
+        runner
+            .load_image(*format, image.as_bytes(), &ImageOptions::new())
+            .unwrap();
+        assert_eq!(runner.pc(), 0x10);
+        let mut io = BufferedIo::new();
+        assert_eq!(
+            run_to_end(&mut runner, &mut io),
+            WasmProcessorContinue::Halt
+        );
+        assert_eq!(io.outputs_on(0), vec![0x48]);
+    }
+
+    let mut runner = Runner::new(ProcessorType::Risc);
+    let options = ImageOptions::new();
+    assert_eq!(
+        runner
+            .load_image(
+                ImageFormat::IntelHex,
+                b":09001000180000488800000000FE\n",
+                &options
+            )
+            .unwrap_err(),
+        "Line 1: the checksum is wrong"
+    );
+    assert!(runner
+        .load_image(ImageFormat::IntelHex, b":00000001FF", &options)
+        .is_ok());
+    assert!(runner
+        .load_image(ImageFormat::IntelHex, b":0400000500000010E7", &options)
+        .unwrap_err()
+        .contains("end-of-file"));
+    assert!(runner
+        .load_image(
+            ImageFormat::SRecord,
+            b"S10C0010180000488800000000FB\nS5",
+            &options
+        )
+        .unwrap_err()
+        .starts_with("Line 2"));
+}
+
+#[test]
+fn exports_patched_memory_as_images() {
+    let mut runner = load(ProcessorType::Risc, &executable(RISC, 8, RISC_CALL));
+    // Patch `mov R00, 5` into `mov R00, 6`.
+    assert!(runner.set_memory(MemoryType::Text, 11, 6));
+    runner.step_with(&mut BufferedIo::new());
+
+    let flat = runner
+        .export_image(ImageFormat::Flat, MemoryType::Text, 0, 13)
+        .unwrap();
+    assert_eq!(flat[..4], [0x48, 0, 0x20, 0x88]);
+    assert_eq!(flat[11], 6);
+
+    for format in [
+        ImageFormat::Executable,
+        ImageFormat::IntelHex,
+        ImageFormat::SRecord,
+    ]
+    .iter()
+    {
+        let image = runner
+            .export_image(*format, MemoryType::Text, 0, 13)
+            .unwrap();
+        let mut copy = Runner::new(ProcessorType::Risc);
+        copy.load_image(*format, &image, &ImageOptions::new())
+            .unwrap();
+        assert_eq!(copy.pc(), 0);
+        let mut io = BufferedIo::new();
+        assert_eq!(run_to_end(&mut copy, &mut io), WasmProcessorContinue::Halt);
+        assert_eq!(io.outputs_on(0), vec![6]);
+    }
+    let program = runner
+        .export_image(ImageFormat::Executable, MemoryType::Text, 0, 13)
+        .unwrap();
+    let mut copy = Runner::new(ProcessorType::Risc);
+    copy.load_program(&program).unwrap();
+    assert_eq!(copy.describe_address(8).as_deref(), Some("_start+0x8"));
+
+    // The entry point survives the round trip.
+    let mut options = ImageOptions::new();
+    options.set_entry_point(8);
+    let mut flat_program = Runner::new(ProcessorType::Risc);
+    flat_program
+        .load_image(ImageFormat::Flat, &flat, &options)
+        .unwrap();
+    let program = flat_program
+        .export_image(ImageFormat::Executable, MemoryType::Text, 0, 13)
+        .unwrap();
+    assert_eq!(inspect_executable(&program).unwrap().entry_point(), 8);
+    let mut copy = Runner::new(ProcessorType::Risc);
+    copy.load_program(&program).unwrap();
+    assert_eq!(copy.pc(), 8);
+
+    // A stack processor executable keeps data memory along with the text.
+    let text = [0x22, 0, 0, 0x20, 0x01, 0x09, 0x0c, 0];
+    let mut stack = load(ProcessorType::Stack, &executable(STACK, 6, &text));
+    stack.write_u16(MemoryType::Data, 0x40, 0xbeef).unwrap();
+    let program = stack
+        .export_image(ImageFormat::Executable, MemoryType::Text, 0, 8)
+        .unwrap();
+    let mut copy = Runner::new(ProcessorType::Stack);
+    copy.load_program(&program).unwrap();
+    assert_eq!(copy.read_u16(MemoryType::Data, 0x40).unwrap(), 0xbeef);
+    let mut io = BufferedIo::new();
+    assert_eq!(run_to_end(&mut copy, &mut io), WasmProcessorContinue::Halt);
+    assert_eq!(copy.read_u16(MemoryType::Data, 0x40).unwrap(), 0xbeef);
+
+    // Stack data memory goes through Intel HEX and back.
+    let mut stack = Runner::new(ProcessorType::Stack);
+    stack.write_u16(MemoryType::Data, 0x40, 0xbeef).unwrap();
+    let hex = stack
+        .export_image(ImageFormat::IntelHex, MemoryType::Data, 0x40, 0x42)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(hex.clone()).unwrap(),
+        ":02004000BEEF11\n:00000001FF\n"
+    );
+    let mut data = ImageOptions::new();
+    data.set_memory_type(MemoryType::Data);
+    let mut copy = Runner::new(ProcessorType::Stack);
+    copy.load_image(ImageFormat::IntelHex, &hex, &data).unwrap();
+    assert_eq!(copy.read_u16(MemoryType::Data, 0x40).unwrap(), 0xbeef);
+
+    assert!(runner
+        .export_image(ImageFormat::Flat, MemoryType::Text, 4, 4)
+        .is_err());
+    assert!(Runner::new(ProcessorType::Cisc)
+        .export_image(ImageFormat::Executable, MemoryType::Text, 0, 4)
+        .unwrap_err()
+        .contains("no executable format"));
+}
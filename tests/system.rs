@@ -0,0 +1,117 @@
+//! Runs several cores together in a `System`.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use common::{executable, RISC};
+use monistode_emulator_bindings::{
+    BufferedIo, MemoryType, PortIo, ProcessorType, SchedulePolicy, System, WasmProcessorContinue,
+};
+
+fn risc_system(programs: &[&[u16]]) -> System {
+    let mut system = System::new();
+    for program in programs {
+        let core = system.add_core(ProcessorType::Risc).unwrap();
+        system
+            .load_program(core, &executable(RISC, 8, program))
+            .unwrap();
+    }
+    system
+}
+
+/// mov R00, 'A'; out 1, R00; halt
+const SENDER: &[u16] = &[0x18, 0, 0, 0x41, 0x88, 0, 0x04, 0, 0];
+/// in R00, port 2; out 0, R00; halt
+const RECEIVER: &[u16] = &[0x80, 0, 0, 0x02, 0x88, 0, 0, 0, 0];
+
+#[test]
+fn passes_values_through_channels() {
+    let mut system = risc_system(&[RECEIVER, SENDER]);
+    system.connect(1, 1, 0, 2).unwrap();
+    assert!(system.connect(1, 1, 0, 3).is_err());
+    assert!(system.is_waiting(0).unwrap());
+
+    let (mut receiver, mut sender) = (BufferedIo::new(), BufferedIo::new());
+    let status = system.run_n_with(&mut [&mut receiver as &mut dyn PortIo, &mut sender], 100);
+    assert_eq!(status, WasmProcessorContinue::Halt);
+    assert_eq!(receiver.outputs(), [(0, 0x41)]);
+    assert!(sender.outputs().is_empty());
+}
+
+#[test]
+fn reports_deadlocks() {
+    let mut system = risc_system(&[RECEIVER, RECEIVER]);
+    system.connect(0, 1, 1, 2).unwrap();
+    system.connect(1, 1, 0, 2).unwrap();
+    let (mut first, mut second) = (BufferedIo::new(), BufferedIo::new());
+    let status = system.run_n_with(&mut [&mut first as &mut dyn PortIo, &mut second], 100);
+    assert_eq!(status, WasmProcessorContinue::Loop);
+}
+
+#[test]
+fn needs_a_core_and_a_port_io_per_core() {
+    let mut system = System::new();
+    assert!(system.share_memory(MemoryType::Text, 0, 2).is_err());
+    system.add_core(ProcessorType::Risc).unwrap();
+    system.share_memory(MemoryType::Text, 0, 2).unwrap();
+    system.add_core(ProcessorType::Acc).unwrap();
+    assert!(system.add_core(ProcessorType::Stack).is_err());
+
+    let mut io = BufferedIo::new();
+    let status = system.run_n_with(&mut [&mut io as &mut dyn PortIo], 1);
+    assert_eq!(status, WasmProcessorContinue::Error);
+    assert_eq!(system.pc(0).unwrap(), 0);
+}
+
+#[test]
+fn shares_memory_between_cores() {
+    // mov R01, 0x0200; mov R00, 7; store [R01], R00; halt
+    let writer: &[u16] = &[0x18, 0x80, 0x02, 0, 0x18, 0, 0, 7, 0x08, 0x80, 0];
+    // nop; nop; nop; nop; halt
+    let idler: &[u16] = &[0x8c, 0x8c, 0x8c, 0x8c, 0];
+    let mut system = risc_system(&[writer, idler]);
+    system.share_memory(MemoryType::Text, 0x200, 16).unwrap();
+    assert!(system.share_memory(MemoryType::Text, 0x20f, 2).is_err());
+    assert!(system.share_memory(MemoryType::Data, 0, 2).is_err());
+    // Stack text cells are six bits wide.
+    assert!(system.add_core(ProcessorType::Stack).is_err());
+    system.write_u16(1, MemoryType::Text, 0x202, 42).unwrap();
+
+    let (mut first, mut second) = (BufferedIo::new(), BufferedIo::new());
+    let status = system.run_n_with(&mut [&mut first as &mut dyn PortIo, &mut second], 100);
+    assert_eq!(status, WasmProcessorContinue::Halt);
+    assert_eq!(system.read_u16(1, MemoryType::Text, 0x200).unwrap(), 7);
+    assert_eq!(system.read_u16(0, MemoryType::Text, 0x202).unwrap(), 42);
+    // Unshared memory stays apart.
+    assert_eq!(system.read_u16(1, MemoryType::Text, 0).unwrap(), 0x8c8c);
+}
+
+/// The cores that run the first `steps` instructions of two idle loops.
+fn schedule(system: &mut System, steps: usize) -> Vec<usize> {
+    let (mut first, mut second) = (BufferedIo::new(), BufferedIo::new());
+    (0..steps)
+        .map(|_| {
+            system.run_n_with(&mut [&mut first as &mut dyn PortIo, &mut second], 1);
+            system.last_core().unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn interleaves_deterministically() {
+    // jmp 0x0000
+    let idle: &[u16] = &[0x64, 0, 0];
+    let mut system = risc_system(&[idle, idle]);
+    assert_eq!(schedule(&mut system, 4), [0, 1, 0, 1]);
+    // Core 1 gets to finish its now longer turn first.
+    system.set_quantum(2).unwrap();
+    assert_eq!(schedule(&mut system, 5), [1, 0, 0, 1, 1]);
+
+    system.set_policy(SchedulePolicy::Random);
+    system.set_seed(7);
+    let first = schedule(&mut system, 32);
+    system.set_seed(7);
+    assert_eq!(schedule(&mut system, 32), first);
+    assert!(first.contains(&0) && first.contains(&1));
+}